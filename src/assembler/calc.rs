@@ -0,0 +1,103 @@
+use super::Token;
+
+/// Evaluates the tokens of a `:calc` expression
+///
+/// Like in Octo there is no operator precedence, expressions are evaluated from right to left
+/// so `2 * 3 + 1` is `8`, parentheses can be used to group terms. `lookup` resolves the names
+/// of constants and labels.
+pub(super) fn evaluate(
+    tokens: &[Token],
+    lookup: &dyn Fn(&str) -> Option<f64>,
+) -> Result<f64, String> {
+    let mut position = 0;
+    let value = expression(tokens, &mut position, lookup)?;
+    match tokens.get(position) {
+        None => Ok(value),
+        Some(token) => Err(format!("Unexpected '{}' in expression", token.text)),
+    }
+}
+
+fn expression(
+    tokens: &[Token],
+    position: &mut usize,
+    lookup: &dyn Fn(&str) -> Option<f64>,
+) -> Result<f64, String> {
+    let left = term(tokens, position, lookup)?;
+    let operator = match tokens.get(*position) {
+        Some(token) if token.text != ")" => token.text.as_str(),
+        _ => return Ok(left),
+    };
+    *position += 1;
+    let right = expression(tokens, position, lookup)?;
+    let (a, b) = (left as i64, right as i64);
+    Ok(match operator {
+        "+" => left + right,
+        "-" => left - right,
+        "*" => left * right,
+        "/" => left / right,
+        "%" => left % right,
+        "pow" => left.powf(right),
+        "min" => left.min(right),
+        "max" => left.max(right),
+        "&" => (a & b) as f64,
+        "|" => (a | b) as f64,
+        "^" => (a ^ b) as f64,
+        "<<" => (a << b) as f64,
+        ">>" => (a >> b) as f64,
+        "<" => (left < right) as u8 as f64,
+        ">" => (left > right) as u8 as f64,
+        "<=" => (left <= right) as u8 as f64,
+        ">=" => (left >= right) as u8 as f64,
+        "==" => (left == right) as u8 as f64,
+        "!=" => (left != right) as u8 as f64,
+        _ => return Err(format!("Unknown operator '{}'", operator)),
+    })
+}
+
+fn term(
+    tokens: &[Token],
+    position: &mut usize,
+    lookup: &dyn Fn(&str) -> Option<f64>,
+) -> Result<f64, String> {
+    let token = tokens
+        .get(*position)
+        .ok_or_else(|| "Incomplete expression".to_owned())?;
+    *position += 1;
+    let unary: Option<fn(f64) -> f64> = match token.text.as_str() {
+        "(" => {
+            let value = expression(tokens, position, lookup)?;
+            return match tokens.get(*position) {
+                Some(token) if token.text == ")" => {
+                    *position += 1;
+                    Ok(value)
+                }
+                _ => Err("Missing ')' in expression".to_owned()),
+            };
+        }
+        "-" => Some(|x| -x),
+        "~" => Some(|x| !(x as i64) as f64),
+        "!" => Some(|x| (x == 0.0) as u8 as f64),
+        "sin" => Some(f64::sin),
+        "cos" => Some(f64::cos),
+        "tan" => Some(f64::tan),
+        "exp" => Some(f64::exp),
+        "log" => Some(f64::ln),
+        "abs" => Some(f64::abs),
+        "sqrt" => Some(f64::sqrt),
+        "sign" => Some(f64::signum),
+        "ceil" => Some(f64::ceil),
+        "floor" => Some(f64::floor),
+        _ => None,
+    };
+    match unary {
+        Some(function) => Ok(function(term(tokens, position, lookup)?)),
+        None => match token.text.as_str() {
+            "PI" => Ok(std::f64::consts::PI),
+            "E" => Ok(std::f64::consts::E),
+            text => super::number(text)
+                .map(|n| n as f64)
+                .or_else(|| lookup(text))
+                .ok_or_else(|| format!("Unknown name '{}' in expression", text)),
+        },
+    }
+}
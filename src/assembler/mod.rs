@@ -0,0 +1,762 @@
+use super::chip_controller::{Instruction, SymbolMap};
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::convert::TryFrom;
+use std::fmt::{Display, Formatter, Result as FmtResult};
+
+mod calc;
+
+/// Where programs are loaded, the first byte of the assembled ROM belongs to this address
+const START: u16 = 0x200;
+
+/// A whitespace separated word of the source and the line it was found in
+#[derive(Debug, Clone)]
+struct Token {
+    text: String,
+    line: usize,
+}
+
+#[derive(Debug)]
+pub(crate) struct AssemblyError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl Display for AssemblyError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+type Result<T> = std::result::Result<T, AssemblyError>;
+
+/// The assembled ROM image and the labels and source lines of its addresses
+pub(crate) struct Program {
+    pub rom: Vec<u8>,
+    pub symbols: SymbolMap,
+}
+
+/// How an address which is only known later is written into the ROM
+enum Fixup {
+    /// The lower 12 bits of the instruction
+    Nnn,
+    /// Both bytes after an `i := long`
+    Long,
+    /// The byte of `v0 := nn` from `:unpack`, the nibble is put in front of the address or
+    /// the whole high byte is used with `:unpack long`
+    High(Option<u8>),
+    /// The byte of `v1 := nn` from `:unpack`
+    Low,
+}
+
+/// A label which was used before it was defined
+struct Reference {
+    address: u16,
+    name: String,
+    fixup: Fixup,
+    line: usize,
+}
+
+/// The blocks which are still open, the addresses are jumps that are patched when they end
+enum Block {
+    If(u16),
+    Else(u16),
+    Loop { start: u16, breaks: Vec<u16> },
+}
+
+/// `vx == 5`, `vx < vy` or `vx key` in an `if` or `while`
+struct Condition {
+    x: u8,
+    operator: String,
+    operand: Operand,
+}
+
+enum Operand {
+    Register(u8),
+    Byte(u8),
+    None,
+}
+
+/// Assembles Octo source into a ROM, `file` is the name used in the symbol map
+pub(crate) fn assemble(source: &str, file: &str) -> Result<Program> {
+    Assembler::new(source, file).run()
+}
+
+struct Assembler<'a> {
+    file: &'a str,
+    tokens: VecDeque<Token>,
+    rom: Vec<u8>,
+    here: u16,
+    line: usize,
+    labels: BTreeMap<String, u16>,
+    constants: HashMap<String, f64>,
+    aliases: HashMap<String, u8>,
+    macros: HashMap<String, (Vec<String>, Vec<Token>)>,
+    calls: usize,
+    next: Option<String>,
+    references: Vec<Reference>,
+    blocks: Vec<(Block, usize)>,
+    symbols: SymbolMap,
+    /// Whether it is decided yet if the program starts with `main` or with a jump to it
+    entry: bool,
+}
+
+impl<'a> Assembler<'a> {
+    fn new(source: &str, file: &'a str) -> Self {
+        let tokens = source
+            .lines()
+            .enumerate()
+            .flat_map(|(number, line)| {
+                let code = line.split('#').next().unwrap_or_default();
+                code.split_whitespace().map(move |text| Token {
+                    text: text.to_owned(),
+                    line: number + 1,
+                })
+            })
+            .collect();
+        Assembler {
+            file,
+            tokens,
+            rom: Vec::new(),
+            here: START,
+            line: 0,
+            labels: BTreeMap::new(),
+            constants: HashMap::new(),
+            aliases: HashMap::new(),
+            macros: HashMap::new(),
+            calls: 0,
+            next: None,
+            references: Vec::new(),
+            blocks: Vec::new(),
+            symbols: SymbolMap::new(),
+            entry: false,
+        }
+    }
+
+    fn run(mut self) -> Result<Program> {
+        while let Some(token) = self.tokens.pop_front() {
+            self.line = token.line;
+            self.statement(&token.text)?;
+        }
+        if let Some((_, line)) = self.blocks.last() {
+            self.line = *line;
+            return Err(self.error("This block is never closed"));
+        }
+        if let Some(name) = self.next.take() {
+            return Err(self.error(&format!("No instruction follows ':next {}'", name)));
+        }
+        for reference in std::mem::take(&mut self.references) {
+            self.line = reference.line;
+            let target = match self.labels.get(&reference.name) {
+                Some(target) => *target,
+                None => return Err(self.error(&format!("Undefined name '{}'", reference.name))),
+            };
+            self.patch(reference.address, target, reference.fixup)?;
+        }
+        Ok(Program {
+            rom: self.rom,
+            symbols: self.symbols,
+        })
+    }
+
+    fn error(&self, message: &str) -> AssemblyError {
+        AssemblyError {
+            line: self.line,
+            message: message.to_owned(),
+        }
+    }
+
+    fn token(&mut self) -> Result<String> {
+        match self.tokens.pop_front() {
+            Some(token) => Ok(token.text),
+            None => Err(self.error("Unexpected end of file")),
+        }
+    }
+
+    fn expect(&mut self, expected: &str) -> Result<()> {
+        match self.token()? {
+            token if token == expected => Ok(()),
+            token => Err(self.error(&format!("Expected '{}' but found '{}'", expected, token))),
+        }
+    }
+
+    fn peek(&self) -> Option<&str> {
+        self.tokens.front().map(|token| token.text.as_str())
+    }
+
+    fn statement(&mut self, token: &str) -> Result<()> {
+        // Execution starts at the first byte, so like Octo a program that doesn't start with
+        // main jumps there first
+        if !self.entry && ![":const", ":alias", ":macro", ":calc"].contains(&token) {
+            self.entry = true;
+            if !(token == ":" && self.peek() == Some("main")) {
+                let address = self.address("main", self.here, Fixup::Nnn)?;
+                self.emit(Instruction::JP(address))?;
+            }
+        }
+        match token {
+            ":" => {
+                let name = self.name()?;
+                self.define(&name, self.here)?;
+            }
+            ":const" => {
+                let name = self.name()?;
+                let value = self.value()?;
+                self.constants.insert(name, value);
+            }
+            ":alias" => {
+                let name = self.token()?;
+                let register = self.register()?;
+                self.aliases.insert(name, register);
+            }
+            ":macro" => self.define_macro()?,
+            ":calc" => {
+                let name = self.name()?;
+                self.expect("{")?;
+                let value = self.calc()?;
+                self.constants.insert(name, value);
+            }
+            ":org" => {
+                let address = self.value()? as i64;
+                if !(START as i64..=0xffff).contains(&address) {
+                    return Err(self.error(&format!("Can't place code at {:#X}", address)));
+                }
+                self.here = address as u16;
+            }
+            ":byte" => {
+                let value = self.value()? as i64;
+                self.write(value as u8)?;
+            }
+            ":next" => self.next = Some(self.name()?),
+            ":unpack" => {
+                let nibble = match self.token()?.as_str() {
+                    "long" => None,
+                    nibble => Some(self.number(nibble)? as u8 & 0xf),
+                };
+                let label = self.token()?;
+                let address = self.address(&label, self.here, Fixup::High(nibble))?;
+                let high = match nibble {
+                    Some(nibble) => nibble << 4 | (address >> 8) as u8,
+                    None => (address >> 8) as u8,
+                };
+                self.emit(Instruction::LDBR(0, high))?;
+                let address = self.address(&label, self.here, Fixup::Low)?;
+                self.emit(Instruction::LDBR(1, address as u8))?;
+            }
+            ":call" => {
+                let label = self.token()?;
+                let address = self.address(&label, self.here, Fixup::Nnn)?;
+                self.emit(Instruction::CALL(address))?;
+            }
+            "return" | ";" => self.emit(Instruction::RET)?,
+            "clear" => self.emit(Instruction::CLS)?,
+            "exit" => self.emit(Instruction::EXIT)?,
+            "lores" => self.emit(Instruction::LOW)?,
+            "hires" => self.emit(Instruction::HIGH)?,
+            "scroll-left" => self.emit(Instruction::SCL)?,
+            "scroll-right" => self.emit(Instruction::SCR)?,
+            "audio" => self.emit(Instruction::AUDIO)?,
+            "scroll-down" => {
+                let n = self.nibble()?;
+                self.emit(Instruction::SCD(n))?;
+            }
+            "scroll-up" => {
+                let n = self.nibble()?;
+                self.emit(Instruction::SCU(n))?;
+            }
+            "plane" => {
+                let n = self.nibble()?;
+                self.emit(Instruction::PLN(n))?;
+            }
+            "bcd" => {
+                let x = self.register()?;
+                self.emit(Instruction::LDRBCDL(x))?;
+            }
+            "saveflags" => {
+                let x = self.register()?;
+                self.emit(Instruction::LDRF(x))?;
+            }
+            "loadflags" => {
+                let x = self.register()?;
+                self.emit(Instruction::LDFR(x))?;
+            }
+            "save" | "load" => {
+                let x = self.register()?;
+                let instruction = match (self.peek() == Some("-"), token) {
+                    (true, "save") => {
+                        self.token()?;
+                        Instruction::SAVE(x, self.register()?)
+                    }
+                    (true, _) => {
+                        self.token()?;
+                        Instruction::LOAD(x, self.register()?)
+                    }
+                    (false, "save") => Instruction::LDRRL(x),
+                    (false, _) => Instruction::LDLRR(x),
+                };
+                self.emit(instruction)?;
+            }
+            "sprite" => {
+                let x = self.register()?;
+                let y = self.register()?;
+                let n = self.nibble()?;
+                self.emit(Instruction::DRW(x, y, n))?;
+            }
+            "jump" | "jump0" | "native" => {
+                let label = self.token()?;
+                let address = self.address(&label, self.here, Fixup::Nnn)?;
+                self.emit(match token {
+                    "jump" => Instruction::JP(address),
+                    "jump0" => Instruction::JP3N(address),
+                    _ => Instruction::SYS(address),
+                })?;
+            }
+            "delay" | "buzzer" | "pitch" => {
+                self.expect(":=")?;
+                let x = self.register()?;
+                self.emit(match token {
+                    "delay" => Instruction::LDRDT(x),
+                    "buzzer" => Instruction::LDRST(x),
+                    _ => Instruction::PITCH(x),
+                })?;
+            }
+            "i" => self.index()?,
+            "loop" => self.blocks.push((
+                Block::Loop {
+                    start: self.here,
+                    breaks: Vec::new(),
+                },
+                self.line,
+            )),
+            "while" => {
+                let condition = self.condition()?;
+                self.skip_if(&condition, false)?;
+                let jump = self.here;
+                self.emit(Instruction::JP(0))?;
+                match self
+                    .blocks
+                    .iter_mut()
+                    .rev()
+                    .find_map(|(block, _)| match block {
+                        Block::Loop { breaks, .. } => Some(breaks),
+                        _ => None,
+                    }) {
+                    Some(breaks) => breaks.push(jump),
+                    None => return Err(self.error("'while' is only allowed in a loop")),
+                }
+            }
+            "again" => match self.blocks.pop() {
+                Some((Block::Loop { start, breaks }, _)) => {
+                    self.emit(Instruction::JP(start))?;
+                    for jump in breaks {
+                        self.patch(jump, self.here, Fixup::Nnn)?;
+                    }
+                }
+                _ => return Err(self.error("'again' without 'loop'")),
+            },
+            "if" => {
+                let condition = self.condition()?;
+                match self.token()?.as_str() {
+                    "then" => self.skip_if(&condition, true)?,
+                    "begin" => {
+                        self.skip_if(&condition, false)?;
+                        self.blocks.push((Block::If(self.here), self.line));
+                        self.emit(Instruction::JP(0))?;
+                    }
+                    token => {
+                        return Err(self
+                            .error(&format!("Expected 'then' or 'begin' but found '{}'", token)))
+                    }
+                }
+            }
+            "else" => match self.blocks.pop() {
+                Some((Block::If(jump), line)) => {
+                    let end = self.here;
+                    self.emit(Instruction::JP(0))?;
+                    self.patch(jump, self.here, Fixup::Nnn)?;
+                    self.blocks.push((Block::Else(end), line));
+                }
+                _ => return Err(self.error("'else' without 'begin'")),
+            },
+            "end" => match self.blocks.pop() {
+                Some((Block::If(jump), _)) | Some((Block::Else(jump), _)) => {
+                    self.patch(jump, self.here, Fixup::Nnn)?
+                }
+                _ => return Err(self.error("'end' without 'begin'")),
+            },
+            _ if self.parse_register(token).is_some() => self.assignment(token)?,
+            _ if self.macros.contains_key(token) => self.expand(token)?,
+            _ => match number(token).or_else(|| self.constants.get(token).map(|c| *c as i64)) {
+                Some(byte) => self.write(byte as u8)?,
+                None => {
+                    let address = self.address(token, self.here, Fixup::Nnn)?;
+                    self.emit(Instruction::CALL(address))?;
+                }
+            },
+        }
+        Ok(())
+    }
+
+    /// `i := label`, `i := hex vx`, `i := bighex vx`, `i := long label` and `i += vx`
+    fn index(&mut self) -> Result<()> {
+        match self.token()?.as_str() {
+            ":=" => {}
+            "+=" => {
+                let x = self.register()?;
+                return self.emit(Instruction::ADDRI(x));
+            }
+            token => return Err(self.error(&format!("Unknown operator '{}' for i", token))),
+        }
+        let instruction = match self.token()?.as_str() {
+            "hex" => Instruction::LDSI(self.register()?),
+            "bighex" => Instruction::LDBSI(self.register()?),
+            "long" => {
+                let label = self.token()?;
+                let address = self.address(&label, self.here + 2, Fixup::Long)?;
+                self.emit(Instruction::LD4NI)?;
+                self.write((address >> 8) as u8)?;
+                return self.write(address as u8);
+            }
+            label => Instruction::LD3NI(self.address(label, self.here, Fixup::Nnn)?),
+        };
+        self.emit(instruction)
+    }
+
+    /// The operations on a register like `vx := 5`, `vx += vy` or `vx := random 0xff`
+    fn assignment(&mut self, register: &str) -> Result<()> {
+        let x = self.parse_register(register).unwrap_or_default();
+        let operator = self.token()?;
+        let operand = self.token()?;
+        let y = self.parse_register(&operand);
+        let instruction = match (operator.as_str(), operand.as_str(), y) {
+            (":=", "random", _) => Instruction::RND(x, self.byte()?),
+            (":=", "key", _) => Instruction::LDKR(x),
+            (":=", "delay", _) => Instruction::LDDTR(x),
+            (":=", _, Some(y)) => Instruction::LDRR(x, y),
+            (":=", _, None) => Instruction::LDBR(x, self.parse_byte(&operand)?),
+            ("+=", _, Some(y)) => Instruction::ADDRR(x, y),
+            ("+=", _, None) => Instruction::ADDBR(x, self.parse_byte(&operand)?),
+            ("-=", _, Some(y)) => Instruction::SUB(x, y),
+            ("-=", _, None) => Instruction::ADDBR(x, self.parse_byte(&operand)?.wrapping_neg()),
+            ("=-", _, Some(y)) => Instruction::SUBN(x, y),
+            ("|=", _, Some(y)) => Instruction::OR(x, y),
+            ("&=", _, Some(y)) => Instruction::AND(x, y),
+            ("^=", _, Some(y)) => Instruction::XOR(x, y),
+            (">>=", _, Some(y)) => Instruction::SHR(x, y),
+            ("<<=", _, Some(y)) => Instruction::SHL(x, y),
+            _ => return Err(self.error(&format!("Can't use '{}' with '{}'", operator, operand))),
+        };
+        self.emit(instruction)
+    }
+
+    fn condition(&mut self) -> Result<Condition> {
+        let x = self.register()?;
+        let operator = self.token()?;
+        let operand = match operator.as_str() {
+            "key" | "-key" => Operand::None,
+            "==" | "!=" | "<" | ">" | "<=" | ">=" => {
+                let operand = self.token()?;
+                match self.parse_register(&operand) {
+                    Some(y) => Operand::Register(y),
+                    None => Operand::Byte(self.parse_byte(&operand)?),
+                }
+            }
+            _ => return Err(self.error(&format!("Unknown comparison '{}'", operator))),
+        };
+        Ok(Condition {
+            x,
+            operator,
+            operand,
+        })
+    }
+
+    /// Emits the instructions which skip the following one if the condition is true, or if
+    /// it is false when `negate` is set
+    ///
+    /// `<`, `>`, `<=` and `>=` subtract in vF and check the borrow, so they overwrite vF.
+    fn skip_if(&mut self, condition: &Condition, negate: bool) -> Result<()> {
+        let operator = match (negate, condition.operator.as_str()) {
+            (false, operator) => operator,
+            (true, "==") => "!=",
+            (true, "!=") => "==",
+            (true, "key") => "-key",
+            (true, "-key") => "key",
+            (true, "<") => ">=",
+            (true, ">=") => "<",
+            (true, ">") => "<=",
+            (true, _) => ">",
+        };
+        let x = condition.x;
+        match (operator, &condition.operand) {
+            ("==", Operand::Register(y)) => self.emit(Instruction::SIRER(x, *y)),
+            ("==", Operand::Byte(byte)) => self.emit(Instruction::SIREB(x, *byte)),
+            ("!=", Operand::Register(y)) => self.emit(Instruction::SIRNER(x, *y)),
+            ("!=", Operand::Byte(byte)) => self.emit(Instruction::SIRNEB(x, *byte)),
+            ("key", _) => self.emit(Instruction::SKP(x)),
+            ("-key", _) => self.emit(Instruction::SKNP(x)),
+            (operator, operand) => {
+                let x = Operand::Register(x);
+                // vF is 1 when the first operand is greater or equal to the second one
+                match operator {
+                    "<" | ">=" => self.compare(&x, operand)?,
+                    _ => self.compare(operand, &x)?,
+                }
+                match operator {
+                    "<" | ">" => self.emit(Instruction::SIREB(0xf, 0)),
+                    _ => self.emit(Instruction::SIRNEB(0xf, 0)),
+                }
+            }
+        }
+    }
+
+    /// Sets vF to 1 if `a` is greater or equal to `b`, at least one of them is a register
+    fn compare(&mut self, a: &Operand, b: &Operand) -> Result<()> {
+        match (a, b) {
+            (Operand::Register(a), Operand::Register(b)) => {
+                self.emit(Instruction::LDRR(0xf, *a))?;
+                self.emit(Instruction::SUB(0xf, *b))
+            }
+            (Operand::Byte(a), Operand::Register(b)) => {
+                self.emit(Instruction::LDBR(0xf, *a))?;
+                self.emit(Instruction::SUB(0xf, *b))
+            }
+            (Operand::Register(a), Operand::Byte(b)) => {
+                self.emit(Instruction::LDBR(0xf, *b))?;
+                self.emit(Instruction::SUBN(0xf, *a))
+            }
+            _ => Err(self.error("Comparisons need a register")),
+        }
+    }
+
+    /// `:macro name arguments { body }`
+    fn define_macro(&mut self) -> Result<()> {
+        let name = self.name()?;
+        let mut arguments = Vec::new();
+        loop {
+            match self.token()? {
+                brace if brace == "{" => break,
+                argument => arguments.push(argument),
+            }
+        }
+        let body = self.block()?;
+        self.macros.insert(name, (arguments, body));
+        Ok(())
+    }
+
+    /// Replaces the macro's arguments in its body and puts it in front of the remaining source,
+    /// `CALLS` is replaced with the amount of times the macro was used before
+    ///
+    /// The expanded code belongs to the line of the invocation so debuggers step over it.
+    fn expand(&mut self, name: &str) -> Result<()> {
+        let (arguments, body) = self.macros[name].clone();
+        let mut values = HashMap::new();
+        for argument in arguments {
+            values.insert(argument, self.token()?);
+        }
+        values.insert("CALLS".to_owned(), self.calls.to_string());
+        self.calls += 1;
+        for token in body.into_iter().rev() {
+            let text = values.get(&token.text).cloned().unwrap_or(token.text);
+            self.tokens.push_front(Token {
+                text,
+                line: self.line,
+            });
+        }
+        Ok(())
+    }
+
+    /// All tokens up to the `}` which closes an already read `{`
+    fn block(&mut self) -> Result<Vec<Token>> {
+        let mut tokens = Vec::new();
+        let mut depth = 0;
+        loop {
+            let token = match self.tokens.pop_front() {
+                Some(token) => token,
+                None => return Err(self.error("Missing '}'")),
+            };
+            match token.text.as_str() {
+                "{" => depth += 1,
+                "}" if depth == 0 => return Ok(tokens),
+                "}" => depth -= 1,
+                _ => {}
+            }
+            tokens.push(token);
+        }
+    }
+
+    /// Evaluates the expression up to the closing `}`, labels have to be defined already
+    fn calc(&mut self) -> Result<f64> {
+        let tokens = self.block()?;
+        let lookup = |name: &str| match name {
+            "HERE" => Some(self.here as f64),
+            _ => self
+                .constants
+                .get(name)
+                .copied()
+                .or_else(|| self.labels.get(name).map(|address| *address as f64)),
+        };
+        calc::evaluate(&tokens, &lookup).map_err(|message| self.error(&message))
+    }
+
+    /// A number, constant, already defined label or `{ expression }`
+    fn value(&mut self) -> Result<f64> {
+        let token = self.token()?;
+        if token == "{" {
+            return self.calc();
+        }
+        match self.labels.get(&token) {
+            Some(address) => Ok(*address as f64),
+            None => self.number(&token).map(|n| n as f64),
+        }
+    }
+
+    fn number(&self, token: &str) -> Result<i64> {
+        number(token)
+            .or_else(|| self.constants.get(token).map(|c| *c as i64))
+            .ok_or_else(|| self.error(&format!("Expected a number but found '{}'", token)))
+    }
+
+    fn byte(&mut self) -> Result<u8> {
+        let token = self.token()?;
+        self.parse_byte(&token)
+    }
+
+    fn parse_byte(&self, token: &str) -> Result<u8> {
+        match self.number(token)? {
+            n if (-128..=255).contains(&n) => Ok(n as u8),
+            n => Err(self.error(&format!("{} doesn't fit in a byte", n))),
+        }
+    }
+
+    fn nibble(&mut self) -> Result<u8> {
+        let token = self.token()?;
+        match self.number(&token)? {
+            n if (0..=15).contains(&n) => Ok(n as u8),
+            n => Err(self.error(&format!("{} doesn't fit in a nibble", n))),
+        }
+    }
+
+    fn register(&mut self) -> Result<u8> {
+        let token = self.token()?;
+        self.parse_register(&token)
+            .ok_or_else(|| self.error(&format!("Expected a register but found '{}'", token)))
+    }
+
+    fn parse_register(&self, token: &str) -> Option<u8> {
+        if let Some(register) = self.aliases.get(token) {
+            return Some(*register);
+        }
+        match token.strip_prefix('v').or_else(|| token.strip_prefix('V')) {
+            Some(n) if n.len() == 1 => u8::from_str_radix(n, 16).ok(),
+            _ => None,
+        }
+    }
+
+    /// The name of a new label, constant, alias or macro
+    fn name(&mut self) -> Result<String> {
+        let name = self.token()?;
+        let reserved = name.starts_with(':')
+            || number(&name).is_some()
+            || self.parse_register(&name).is_some()
+            || ["{", "}", "i", ";", ":="].contains(&name.as_str());
+        match reserved {
+            true => Err(self.error(&format!("'{}' can't be used as a name", name))),
+            false => Ok(name),
+        }
+    }
+
+    fn define(&mut self, name: &str, address: u16) -> Result<()> {
+        if self.labels.contains_key(name) || self.constants.contains_key(name) {
+            return Err(self.error(&format!("'{}' is already defined", name)));
+        }
+        self.labels.insert(name.to_owned(), address);
+        self.symbols.insert_label(name, address);
+        Ok(())
+    }
+
+    /// Resolves a number, constant or label, labels which aren't defined yet are patched in
+    /// at `at` once the whole source is read
+    fn address(&mut self, token: &str, at: u16, fixup: Fixup) -> Result<u16> {
+        if let Some(n) = number(token).or_else(|| self.constants.get(token).map(|c| *c as i64)) {
+            return u16::try_from(n).map_err(|_| self.error(&format!("Invalid address {}", n)));
+        }
+        if let Some(address) = self.labels.get(token) {
+            return Ok(*address);
+        }
+        self.references.push(Reference {
+            address: at,
+            name: token.to_owned(),
+            fixup,
+            line: self.line,
+        });
+        Ok(0)
+    }
+
+    fn patch(&mut self, at: u16, target: u16, fixup: Fixup) -> Result<()> {
+        let index = (at - START) as usize;
+        match fixup {
+            Fixup::Nnn if target > 0xfff => {
+                return Err(self.error(&format!(
+                    "{:#X} is out of reach, use 'i := long' instead",
+                    target
+                )))
+            }
+            Fixup::Nnn => {
+                self.rom[index] = self.rom[index] & 0xf0 | (target >> 8) as u8;
+                self.rom[index + 1] = target as u8;
+            }
+            Fixup::Long => self.rom[index..index + 2].copy_from_slice(&target.to_be_bytes()),
+            Fixup::High(Some(nibble)) => self.rom[index + 1] = nibble << 4 | (target >> 8) as u8,
+            Fixup::High(None) => self.rom[index + 1] = (target >> 8) as u8,
+            Fixup::Low => self.rom[index + 1] = target as u8,
+        }
+        Ok(())
+    }
+
+    fn emit(&mut self, instruction: Instruction) -> Result<()> {
+        if let Some(name) = self.next.take() {
+            self.define(&name, self.here + 1)?;
+        }
+        if let Instruction::JP(address) | Instruction::CALL(address) | Instruction::LD3NI(address) =
+            instruction
+        {
+            if address > 0xfff {
+                return Err(self.error(&format!("{:#X} is out of reach", address)));
+            }
+        }
+        self.symbols.insert_line(self.here, self.file, self.line);
+        let [high, low] = <[u8; 2]>::from(&instruction);
+        self.write(high)?;
+        self.write(low)
+    }
+
+    fn write(&mut self, byte: u8) -> Result<()> {
+        let index = (self.here - START) as usize;
+        if index >= self.rom.len() {
+            self.rom.resize(index + 1, 0);
+        }
+        self.rom[index] = byte;
+        self.here = self
+            .here
+            .checked_add(1)
+            .ok_or_else(|| self.error("The program doesn't fit in memory"))?;
+        Ok(())
+    }
+}
+
+/// Parses decimal, `0x` hexadecimal and `0b` binary numbers, which may be negative
+fn number(token: &str) -> Option<i64> {
+    let (negative, digits) = match token.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, token),
+    };
+    let n = if let Some(hex) = digits.strip_prefix("0x") {
+        i64::from_str_radix(hex, 16).ok()?
+    } else if let Some(binary) = digits.strip_prefix("0b") {
+        i64::from_str_radix(binary, 2).ok()?
+    } else if digits.starts_with(|c: char| c.is_ascii_digit()) {
+        digits.parse().ok()?
+    } else {
+        return None;
+    };
+    Some(if negative { -n } else { n })
+}
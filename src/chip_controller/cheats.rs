@@ -0,0 +1,237 @@
+use std::fmt::{Display, Formatter, Result as FmtResult};
+use std::fs;
+use std::io::{Error, ErrorKind};
+use std::path::Path;
+
+/// What a cheat does to the RAM
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum Effect {
+    /// The byte at the address is set to the value after every instruction
+    Freeze(u16, u8),
+    /// The bytes are written once, the ones they replaced are restored when it is disabled
+    Patch(u16, Vec<u8>),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Cheat {
+    pub name: String,
+    pub effect: Effect,
+    pub enabled: bool,
+    /// The bytes a patch replaced while it is applied
+    original: Option<Vec<u8>>,
+}
+
+impl Cheat {
+    pub fn new(name: &str, effect: Effect) -> Cheat {
+        Cheat {
+            name: name.to_owned(),
+            effect,
+            enabled: true,
+            original: None,
+        }
+    }
+
+    fn apply(&mut self, ram: &mut [u8]) {
+        match &self.effect {
+            Effect::Freeze(address, value) if self.enabled => ram[*address as usize] = *value,
+            Effect::Patch(address, bytes) if self.enabled && self.original.is_none() => {
+                let range = *address as usize..*address as usize + bytes.len();
+                self.original = Some(ram[range.clone()].to_vec());
+                ram[range].copy_from_slice(bytes);
+            }
+            Effect::Patch(address, _) if !self.enabled => {
+                if let Some(original) = self.original.take() {
+                    let start = *address as usize;
+                    ram[start..start + original.len()].copy_from_slice(&original);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// The cheats of a ROM, usually kept in a file next to it with a `.cht` extension
+///
+/// Each line is a cheat which is enabled with `[x]` and disabled with `[ ]`, the name in front
+/// of the colon is optional and addresses and bytes are hexadecimal:
+/// ```text
+/// [x] Infinite lives: freeze 2F0 03
+/// [ ] Skip the intro: patch 24A 12 5C
+/// ```
+/// Empty lines and lines starting with `#` are ignored.
+#[derive(Debug, Default)]
+pub(crate) struct Cheats {
+    cheats: Vec<Cheat>,
+}
+
+impl Cheats {
+    pub fn new() -> Cheats {
+        Cheats::default()
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Cheats, Error> {
+        Cheats::parse(&fs::read_to_string(path)?)
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), Error> {
+        fs::write(path, self.to_string())
+    }
+
+    pub fn parse(text: &str) -> Result<Cheats, Error> {
+        let mut cheats = Cheats::new();
+        for (number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let invalid = || {
+                Error::new(
+                    ErrorKind::InvalidData,
+                    format!("Invalid cheat in line {}: {}", number + 1, line),
+                )
+            };
+            let (enabled, rest) = match line.get(..3) {
+                Some("[x]") | Some("[X]") => (true, &line[3..]),
+                Some("[ ]") => (false, &line[3..]),
+                _ => (true, line),
+            };
+            let (name, code) = match rest.rsplit_once(':') {
+                Some((name, code)) => (name.trim(), code),
+                None => ("", rest),
+            };
+            let mut parts = code.split_whitespace();
+            let kind = parts.next();
+            let address = parts
+                .next()
+                .and_then(|a| u16::from_str_radix(a, 16).ok())
+                .ok_or_else(invalid)?;
+            let bytes = parts
+                .map(|byte| u8::from_str_radix(byte, 16))
+                .collect::<Result<Vec<u8>, _>>()
+                .map_err(|_| invalid())?;
+            let effect = match (kind, bytes.as_slice()) {
+                (Some("freeze"), [value]) => Effect::Freeze(address, *value),
+                (Some("patch"), [_, ..]) if address as usize + bytes.len() <= 0x10000 => {
+                    Effect::Patch(address, bytes)
+                }
+                _ => return Err(invalid()),
+            };
+            let mut cheat = Cheat::new(name, effect);
+            cheat.enabled = enabled;
+            cheats.cheats.push(cheat);
+        }
+        Ok(cheats)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Cheat> {
+        self.cheats.iter()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cheats.is_empty()
+    }
+
+    pub fn push(&mut self, cheat: Cheat) {
+        self.cheats.push(cheat);
+    }
+
+    /// Enables or disables the cheat at `index`, returns whether it exists
+    pub fn toggle(&mut self, index: usize) -> bool {
+        match self.cheats.get_mut(index) {
+            Some(cheat) => {
+                cheat.enabled = !cheat.enabled;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// The index of the freeze cheat for an address
+    pub fn frozen(&self, address: u16) -> Option<usize> {
+        self.cheats
+            .iter()
+            .position(|cheat| matches!(cheat.effect, Effect::Freeze(a, _) if a == address))
+    }
+
+    /// Writes the frozen values and applies or reverts the patches that were toggled
+    pub fn apply(&mut self, ram: &mut [u8]) {
+        for cheat in &mut self.cheats {
+            cheat.apply(ram);
+        }
+    }
+
+    /// Forgets which patches were applied, because the ROM was loaded again
+    pub fn reload(&mut self) {
+        for cheat in &mut self.cheats {
+            cheat.original = None;
+        }
+    }
+}
+
+impl Display for Cheats {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        for cheat in &self.cheats {
+            write!(f, "[{}] ", if cheat.enabled { 'x' } else { ' ' })?;
+            if !cheat.name.is_empty() {
+                write!(f, "{}: ", cheat.name)?;
+            }
+            match &cheat.effect {
+                Effect::Freeze(address, value) => {
+                    writeln!(f, "freeze {:03X} {:02X}", address, value)?
+                }
+                Effect::Patch(address, bytes) => {
+                    let bytes: Vec<String> = bytes.iter().map(|b| format!("{:02X}", b)).collect();
+                    writeln!(f, "patch {:03X} {}", address, bytes.join(" "))?
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// How a byte has to compare to its value in the last snapshot to stay a candidate
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub(crate) enum Condition {
+    Equals(u8),
+    Decreased,
+    Increased,
+    Changed,
+    Unchanged,
+}
+
+/// Finds the address of a value like the lives by narrowing the candidates over several
+/// snapshots of the RAM, like "decreased since the last snapshot" after losing a life
+pub(crate) struct RamSearch {
+    candidates: Vec<u16>,
+    snapshot: Vec<u8>,
+}
+
+impl RamSearch {
+    /// Starts with every address as a candidate
+    pub fn new(ram: &[u8]) -> RamSearch {
+        RamSearch {
+            candidates: (0..ram.len()).map(|a| a as u16).collect(),
+            snapshot: ram.to_vec(),
+        }
+    }
+
+    /// Keeps the candidates that fulfill the condition and takes a new snapshot
+    pub fn filter(&mut self, ram: &[u8], condition: Condition) {
+        let snapshot = &self.snapshot;
+        self.candidates.retain(|address| {
+            let (old, new) = (snapshot[*address as usize], ram[*address as usize]);
+            match condition {
+                Condition::Equals(value) => new == value,
+                Condition::Decreased => new < old,
+                Condition::Increased => new > old,
+                Condition::Changed => new != old,
+                Condition::Unchanged => new == old,
+            }
+        });
+        self.snapshot = ram.to_vec();
+    }
+
+    pub fn candidates(&self) -> &[u16] {
+        &self.candidates
+    }
+}
@@ -0,0 +1,486 @@
+use super::super::{Instruction, Quirks};
+use super::rom::{Disassembly, Rom};
+use std::collections::BTreeMap;
+use std::fmt::{Display, Formatter, Result as FmtResult};
+
+/// How many instructions are followed when looking for the value of a register or I
+const SCAN_DEPTH: usize = 16;
+
+/// The interpreter an instruction was introduced with
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Copy, Clone)]
+pub(crate) enum Platform {
+    Chip8,
+    SChip,
+    XoChip,
+}
+
+impl Display for Platform {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        let name = match self {
+            Platform::Chip8 => "CHIP-8",
+            Platform::SChip => "SCHIP",
+            Platform::XoChip => "XO-CHIP",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// The opcode pattern of an instruction and the platform that introduced it
+fn opcode(instruction: &Instruction) -> (Platform, &'static str) {
+    use Platform::*;
+    match instruction {
+        Instruction::SYS(_) => (Chip8, "0NNN"),
+        Instruction::CLS => (Chip8, "00E0"),
+        Instruction::RET => (Chip8, "00EE"),
+        Instruction::ERR(_) => (Chip8, "????"),
+        Instruction::JP(_) => (Chip8, "1NNN"),
+        Instruction::CALL(_) => (Chip8, "2NNN"),
+        Instruction::SIREB(_, _) => (Chip8, "3XNN"),
+        Instruction::SIRNEB(_, _) => (Chip8, "4XNN"),
+        Instruction::SIRER(_, _) => (Chip8, "5XY0"),
+        Instruction::LDBR(_, _) => (Chip8, "6XNN"),
+        Instruction::ADDBR(_, _) => (Chip8, "7XNN"),
+        Instruction::LDRR(_, _) => (Chip8, "8XY0"),
+        Instruction::OR(_, _) => (Chip8, "8XY1"),
+        Instruction::AND(_, _) => (Chip8, "8XY2"),
+        Instruction::XOR(_, _) => (Chip8, "8XY3"),
+        Instruction::ADDRR(_, _) => (Chip8, "8XY4"),
+        Instruction::SUB(_, _) => (Chip8, "8XY5"),
+        Instruction::SHR(_, _) => (Chip8, "8XY6"),
+        Instruction::SUBN(_, _) => (Chip8, "8XY7"),
+        Instruction::SHL(_, _) => (Chip8, "8XYE"),
+        Instruction::SIRNER(_, _) => (Chip8, "9XY0"),
+        Instruction::LD3NI(_) => (Chip8, "ANNN"),
+        Instruction::JP3N(_) => (Chip8, "BNNN"),
+        Instruction::RND(_, _) => (Chip8, "CXNN"),
+        Instruction::DRW(_, _, 0) => (SChip, "DXY0"),
+        Instruction::DRW(_, _, _) => (Chip8, "DXYN"),
+        Instruction::SKP(_) => (Chip8, "EX9E"),
+        Instruction::SKNP(_) => (Chip8, "EXA1"),
+        Instruction::LDDTR(_) => (Chip8, "FX07"),
+        Instruction::LDKR(_) => (Chip8, "FX0A"),
+        Instruction::LDRDT(_) => (Chip8, "FX15"),
+        Instruction::LDRST(_) => (Chip8, "FX18"),
+        Instruction::ADDRI(_) => (Chip8, "FX1E"),
+        Instruction::LDSI(_) => (Chip8, "FX29"),
+        Instruction::LDRBCDL(_) => (Chip8, "FX33"),
+        Instruction::LDRRL(_) => (Chip8, "FX55"),
+        Instruction::LDLRR(_) => (Chip8, "FX65"),
+        Instruction::SCD(_) => (SChip, "00CN"),
+        Instruction::SCR => (SChip, "00FB"),
+        Instruction::SCL => (SChip, "00FC"),
+        Instruction::EXIT => (SChip, "00FD"),
+        Instruction::LOW => (SChip, "00FE"),
+        Instruction::HIGH => (SChip, "00FF"),
+        Instruction::LDBSI(_) => (SChip, "FX30"),
+        Instruction::LDRF(_) => (SChip, "FX75"),
+        Instruction::LDFR(_) => (SChip, "FX85"),
+        Instruction::SCU(_) => (XoChip, "00DN"),
+        Instruction::SAVE(_, _) => (XoChip, "5XY2"),
+        Instruction::LOAD(_, _) => (XoChip, "5XY3"),
+        Instruction::LD4NI => (XoChip, "F000"),
+        Instruction::PLN(_) => (XoChip, "FN01"),
+        Instruction::AUDIO => (XoChip, "F002"),
+        Instruction::PITCH(_) => (XoChip, "FX3A"),
+    }
+}
+
+/// Whether the instruction changes register `r`
+fn writes(instruction: &Instruction, r: u8) -> bool {
+    match instruction {
+        Instruction::LDBR(x, _)
+        | Instruction::LDRR(x, _)
+        | Instruction::ADDBR(x, _)
+        | Instruction::RND(x, _)
+        | Instruction::LDDTR(x)
+        | Instruction::LDKR(x) => *x == r,
+        Instruction::OR(x, _)
+        | Instruction::AND(x, _)
+        | Instruction::XOR(x, _)
+        | Instruction::ADDRR(x, _)
+        | Instruction::SUB(x, _)
+        | Instruction::SUBN(x, _)
+        | Instruction::SHR(x, _)
+        | Instruction::SHL(x, _) => *x == r || r == 0xf,
+        Instruction::DRW(_, _, _) => r == 0xf,
+        Instruction::LDLRR(x) | Instruction::LDFR(x) => r <= *x,
+        Instruction::LOAD(x, y) => (*x.min(y)..=*x.max(y)).contains(&r),
+        _ => false,
+    }
+}
+
+/// Whether the instruction changes I, `LDRRL` and `LDLRR` only do with the memory quirk
+fn writes_i(instruction: &Instruction) -> bool {
+    matches!(
+        instruction,
+        Instruction::LD3NI(_)
+            | Instruction::LD4NI
+            | Instruction::ADDRI(_)
+            | Instruction::LDSI(_)
+            | Instruction::LDBSI(_)
+    )
+}
+
+/// Whether the instruction uses the value of I
+fn reads_i(instruction: &Instruction) -> bool {
+    matches!(
+        instruction,
+        Instruction::DRW(_, _, _)
+            | Instruction::LDRBCDL(_)
+            | Instruction::LDRRL(_)
+            | Instruction::LDLRR(_)
+            | Instruction::ADDRI(_)
+            | Instruction::SAVE(_, _)
+            | Instruction::LOAD(_, _)
+            | Instruction::AUDIO
+    )
+}
+
+/// What a static scan of a ROM found out about it
+pub(crate) struct Analysis {
+    platform: Platform,
+    opcodes: BTreeMap<(Platform, &'static str), usize>,
+    /// Instructions that behave differently depending on the quirks, why and how the quirk
+    /// most likely has to be set for them, like `-clipping`
+    quirk_sensitive: Vec<(u16, String, Option<&'static str>)>,
+    /// Instructions that write to code and the address they write to
+    self_modifying: Vec<(u16, u16)>,
+    sys: Vec<(u16, u16)>,
+    /// Regions which are neither reached nor loaded into I but decode as instructions
+    unreachable: Vec<(u16, u16)>,
+    invalid: Vec<u16>,
+}
+
+impl Analysis {
+    pub fn new(rom: &Rom) -> Analysis {
+        let disassembly = rom.disassemble();
+        let code: Vec<(u16, Instruction)> = disassembly
+            .code()
+            .map(|address| (address, rom.instruction(address)))
+            .collect();
+        let mut opcodes = BTreeMap::new();
+        for (_, instruction) in &code {
+            *opcodes.entry(opcode(instruction)).or_insert(0) += 1;
+        }
+        let platform = opcodes
+            .keys()
+            .map(|(platform, _)| *platform)
+            .max()
+            .unwrap_or(Platform::Chip8);
+        let hires = code
+            .iter()
+            .any(|(_, instruction)| matches!(instruction, Instruction::HIGH));
+
+        let mut analysis = Analysis {
+            platform,
+            opcodes,
+            quirk_sensitive: Vec::new(),
+            self_modifying: Vec::new(),
+            sys: Vec::new(),
+            unreachable: Vec::new(),
+            invalid: disassembly.invalid().collect(),
+        };
+        for (address, instruction) in &code {
+            let address = *address;
+            match instruction {
+                Instruction::SYS(target) => analysis.sys.push((address, *target)),
+                // Naming a second register only makes sense if it is shifted into the first
+                Instruction::SHR(x, y) | Instruction::SHL(x, y) if x != y => {
+                    analysis.quirk_sensitive.push((
+                        address,
+                        format!(
+                            "{} with v{:X} != v{:X} depends on the shifting quirk",
+                            opcode(instruction).1,
+                            x,
+                            y
+                        ),
+                        Some("-shifting"),
+                    ))
+                }
+                // The register that was set right before is most likely the one jumped by
+                Instruction::JP3N(target) if target >> 8 != 0 => {
+                    let x = (target >> 8) as u8;
+                    let set = |r| Self::preceding(&disassembly, address).any(|i| writes(&i, r));
+                    let expected = match (set(0), set(x)) {
+                        (true, false) => Some("-jumping"),
+                        (false, true) => Some("jumping"),
+                        _ => None,
+                    };
+                    analysis.quirk_sensitive.push((
+                        address,
+                        format!(
+                            "BNNN jumps relative to v0 or v{:X} depending on the jumping quirk",
+                            x
+                        ),
+                        expected,
+                    ))
+                }
+                Instruction::LDRRL(_) | Instruction::LDLRR(_) => {
+                    if let Some(user) = Self::following(&disassembly, address)
+                        .take_while(|(_, next)| !writes_i(next))
+                        .find(|(_, next)| reads_i(next))
+                    {
+                        // Using I again without loading it expects it to be where it was
+                        analysis.quirk_sensitive.push((
+                            address,
+                            format!(
+                                "{} is followed by a use of I at {:03X}, which depends on the memory quirk",
+                                opcode(instruction).1,
+                                user.0
+                            ),
+                            Some("-memory"),
+                        ));
+                    }
+                }
+                Instruction::DRW(x, y, n) => {
+                    let (width, height) = match n {
+                        0 => (16, 16),
+                        _ => (8, *n as u16),
+                    };
+                    let (screen_width, screen_height) = if hires { (128, 64) } else { (64, 32) };
+                    let vx = Self::register(&disassembly, address, *x);
+                    let vy = Self::register(&disassembly, address, *y);
+                    let crosses_x =
+                        vx.is_some_and(|vx| vx as u16 % screen_width + width > screen_width);
+                    let crosses_y =
+                        vy.is_some_and(|vy| vy as u16 % screen_height + height > screen_height);
+                    // A sprite is only drawn there on purpose if it wraps around
+                    if crosses_x || crosses_y {
+                        analysis.quirk_sensitive.push((
+                            address,
+                            format!(
+                                "{} draws across the edge of the display, which depends on the clipping quirk",
+                                opcode(instruction).1
+                            ),
+                            Some("-clipping"),
+                        ));
+                    }
+                }
+                _ => {}
+            }
+            // Writing into code only works if I is known
+            let length = match instruction {
+                Instruction::LDRRL(x) => *x as u16 + 1,
+                Instruction::LDRBCDL(_) => 3,
+                Instruction::SAVE(x, y) => x.abs_diff(*y) as u16 + 1,
+                _ => continue,
+            };
+            if let Some(target) = Self::index(&disassembly, address) {
+                let start = target.saturating_sub(3);
+                if (start..target.saturating_add(length)).any(|a| {
+                    disassembly.is_code(a)
+                        && a as u32 + rom.instruction_length(a) as u32 > target as u32
+                }) {
+                    analysis.self_modifying.push((address, target));
+                }
+            }
+        }
+
+        // Bytes that are neither code nor loaded into I, padding with zeros is ignored
+        // Counted in u32, so a ROM that reaches the end of the RAM doesn't overflow it
+        let mut address: u32 = 0x200;
+        let code = |address: u32| disassembly.is_code(address as u16);
+        while address < rom.end() {
+            if code(address) {
+                address += rom.instruction_length(address as u16) as u32;
+                continue;
+            }
+            if disassembly.is_data(address as u16) || disassembly.label(address as u16).is_some() {
+                // Data continues until the next code
+                while address < rom.end() && !code(address) {
+                    address += 1;
+                }
+                continue;
+            }
+            let start = address;
+            while address < rom.end() && !code(address) && !disassembly.is_data(address as u16) {
+                address += 1;
+            }
+            let decodes = (start..address.saturating_sub(1)).step_by(2).any(|a| {
+                !matches!(
+                    rom.instruction(a as u16),
+                    Instruction::ERR(_) | Instruction::SYS(0)
+                )
+            });
+            if decodes {
+                analysis
+                    .unreachable
+                    .push((start as u16, (address - 1) as u16));
+            }
+        }
+        analysis
+    }
+
+    /// The instructions executed after the one at `address` as long as there is no jump
+    fn following<'a>(
+        disassembly: &'a Disassembly<'_>,
+        address: u16,
+    ) -> impl Iterator<Item = (u16, Instruction)> + 'a {
+        let rom = disassembly.rom();
+        let mut address = address;
+        std::iter::from_fn(move || {
+            address = address.wrapping_add(rom.instruction_length(address));
+            match disassembly.is_code(address) {
+                true => Some((address, rom.instruction(address))),
+                false => None,
+            }
+        })
+        .take_while(|(_, instruction)| {
+            !matches!(
+                instruction,
+                Instruction::JP(_) | Instruction::JP3N(_) | Instruction::RET | Instruction::CALL(_)
+            )
+        })
+        .take(SCAN_DEPTH)
+    }
+
+    /// The instructions executed before the one at `address`, nearest first, until a label is
+    /// reached because the code there can also be reached from somewhere else
+    fn preceding<'a>(
+        disassembly: &'a Disassembly<'_>,
+        address: u16,
+    ) -> impl Iterator<Item = Instruction> + 'a {
+        let rom = disassembly.rom();
+        let mut address = address;
+        std::iter::from_fn(move || {
+            if disassembly.label(address).is_some() {
+                return None;
+            }
+            address = address.checked_sub(2)?;
+            match disassembly.is_code(address) {
+                true => Some(rom.instruction(address)),
+                false => None,
+            }
+        })
+        .take(SCAN_DEPTH)
+    }
+
+    /// The constant loaded into a register before the instruction at `address`, if any
+    fn register(disassembly: &Disassembly<'_>, address: u16, r: u8) -> Option<u8> {
+        match Self::preceding(disassembly, address).find(|i| writes(i, r))? {
+            Instruction::LDBR(_, byte) => Some(byte),
+            _ => None,
+        }
+    }
+
+    /// The address loaded into I before the instruction at `address`, if any
+    pub(super) fn index(disassembly: &Disassembly<'_>, address: u16) -> Option<u16> {
+        match Self::preceding(disassembly, address).find(writes_i)? {
+            Instruction::LD3NI(target) => Some(target),
+            _ => None,
+        }
+    }
+
+    pub fn platform(&self) -> Platform {
+        self.platform
+    }
+
+    /// The quirks of the platform the ROM was most likely written for, changed to what the
+    /// quirk-sensitive instructions most likely expect
+    pub fn quirks(&self) -> Quirks {
+        let mut quirks = match self.platform {
+            Platform::Chip8 => Quirks::CHIP8,
+            Platform::SChip => Quirks::SCHIP,
+            Platform::XoChip => Quirks::XOCHIP,
+        };
+        for expected in self.expected() {
+            quirks.set(expected).unwrap();
+        }
+        quirks
+    }
+
+    /// How the quirk-sensitive instructions most likely expect the quirks to be set
+    fn expected(&self) -> impl Iterator<Item = &'static str> + '_ {
+        self.quirk_sensitive
+            .iter()
+            .filter_map(|(_, _, expected)| *expected)
+    }
+}
+
+impl Display for Analysis {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        writeln!(f, "Platform: {}", self.platform)?;
+        writeln!(f, "\nOpcodes:")?;
+        for platform in [Platform::Chip8, Platform::SChip, Platform::XoChip] {
+            let used: Vec<String> = self
+                .opcodes
+                .iter()
+                .filter(|((p, _), _)| *p == platform)
+                .map(|((_, opcode), count)| format!("{} x{}", opcode, count))
+                .collect();
+            if !used.is_empty() {
+                writeln!(f, "  {:<8} {}", platform.to_string(), used.join(", "))?;
+            }
+        }
+        let sections: [(&str, Vec<String>); 5] = [
+            (
+                "Quirk-sensitive instructions",
+                self.quirk_sensitive
+                    .iter()
+                    .map(|(address, reason, _)| format!("{:03X}: {}", address, reason))
+                    .collect(),
+            ),
+            (
+                "Self-modifying code",
+                self.self_modifying
+                    .iter()
+                    .map(|(address, target)| {
+                        format!("{:03X}: writes to code at {:03X}", address, target)
+                    })
+                    .collect(),
+            ),
+            (
+                "SYS calls",
+                self.sys
+                    .iter()
+                    .map(|(address, target)| format!("{:03X}: SYS {:03X}", address, target))
+                    .collect(),
+            ),
+            (
+                "Unreachable code",
+                self.unreachable
+                    .iter()
+                    .map(|(start, end)| format!("{:03X}-{:03X}", start, end))
+                    .collect(),
+            ),
+            (
+                "Invalid opcodes in reachable code",
+                self.invalid
+                    .iter()
+                    .map(|address| format!("{:03X}", address))
+                    .collect(),
+            ),
+        ];
+        for (title, findings) in &sections {
+            writeln!(f, "\n{}:", title)?;
+            if findings.is_empty() {
+                writeln!(f, "  none")?;
+            }
+            for finding in findings {
+                writeln!(f, "  {}", finding)?;
+            }
+        }
+        let quirks = self.quirks();
+        let mut reason = match self.platform {
+            Platform::Chip8 => "only CHIP-8 instructions are used".to_owned(),
+            Platform::SChip => "SCHIP instructions are used".to_owned(),
+            Platform::XoChip => "XO-CHIP instructions are used".to_owned(),
+        };
+        let mut expected: Vec<&str> = self.expected().collect();
+        expected.sort_unstable();
+        expected.dedup();
+        if !expected.is_empty() {
+            reason += &format!(
+                " and the quirk-sensitive instructions expect {}",
+                expected.join(",")
+            );
+        }
+        writeln!(
+            f,
+            "\nSuggested quirks: {} ({}), because {}",
+            quirks.name().unwrap_or("custom"),
+            quirks,
+            reason
+        )
+    }
+}
@@ -0,0 +1,141 @@
+use super::super::RAM_SIZE;
+use super::rom::{DataStyle, Rom, Syntax};
+use super::symbols::SymbolMap;
+
+/// How a byte of RAM was used
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub(crate) enum Access {
+    /// Part of an executed instruction
+    Executed = 1,
+    /// Read by `DRW`, `LDLRR`, `LOAD` or `AUDIO`
+    Read = 2,
+    /// Written by `LDRRL`, `LDRBCDL` or `SAVE`
+    Written = 4,
+}
+
+/// Records for each byte of RAM whether it was executed, read or written
+pub(crate) struct Coverage {
+    access: Vec<u8>,
+    /// How often an instruction starting at an address was executed
+    executions: Vec<u32>,
+}
+
+impl Coverage {
+    pub fn new() -> Coverage {
+        Coverage {
+            access: vec![0; RAM_SIZE],
+            executions: vec![0; RAM_SIZE],
+        }
+    }
+
+    /// Records the instruction at `address` which takes `length` bytes
+    pub fn execute(&mut self, address: u16, length: usize) {
+        self.executions[address as usize] += 1;
+        self.cover(address as usize, length, Access::Executed);
+    }
+
+    pub fn cover(&mut self, address: usize, length: usize, access: Access) {
+        for byte in address..address + length {
+            self.access[byte % RAM_SIZE] |= access as u8;
+        }
+    }
+
+    pub fn is(&self, address: u16, access: Access) -> bool {
+        self.access[address as usize] & access as u8 != 0
+    }
+
+    pub fn executions(&self, address: u16) -> u32 {
+        self.executions[address as usize]
+    }
+
+    /// Addresses where executed instructions start, the disassembler uses them as entry points
+    /// to find code it can't reach statically
+    pub fn entries(&self) -> Vec<u16> {
+        (0..RAM_SIZE)
+            .filter(|a| self.executions[*a] > 0)
+            .map(|a| a as u16)
+            .collect()
+    }
+
+    /// A listing of the ROM where each line starts with how often it was executed, code that
+    /// never ran is marked with `#####` like gcov does and data with the ways it was accessed
+    ///
+    /// ```text
+    /// Code: 13 of 15 instructions executed (86.7%)
+    /// Data: 4 of 6 bytes read, 0 bytes written
+    ///
+    ///          main:
+    ///      1   200-201:    HIGH
+    ///  #####   21C-21D:    LDBR 3  1
+    ///     R-   23A:        DB 60  ;  ██
+    /// ```
+    pub fn report(&self, rom: &Rom, symbols: &SymbolMap) -> String {
+        let disassembly = rom.disassemble_from(&self.entries_in(rom));
+        let disassembly = disassembly.with_symbols(symbols);
+        let lines = disassembly.lines(Syntax::Mnemonic, DataStyle::Sprites);
+        let code: Vec<u16> = (0x200..rom.end())
+            .map(|a| a as u16)
+            .filter(|a| disassembly.is_code(*a))
+            .collect();
+        let executed = code.iter().filter(|a| self.executions(**a) > 0).count();
+        let data: Vec<u16> = lines
+            .iter()
+            .filter(|line| line.length > 0 && !disassembly.is_code(line.address))
+            .flat_map(|line| (0..line.length).map(move |i| line.address.wrapping_add(i)))
+            .collect();
+        let read = data.iter().filter(|a| self.is(**a, Access::Read)).count();
+        let written = (0x200..rom.end())
+            .filter(|a| self.is(*a as u16, Access::Written))
+            .count();
+
+        let mut report = format!(
+            "Code: {} of {} instructions executed ({:.1}%)\n",
+            executed,
+            code.len(),
+            percent(executed, code.len())
+        );
+        report += &format!(
+            "Data: {} of {} bytes read, {} bytes written\n\n",
+            read,
+            data.len(),
+            written
+        );
+        for line in lines {
+            let marker = match (line.length, disassembly.is_code(line.address)) {
+                (0, _) => String::new(),
+                (_, true) => match self.executions(line.address) {
+                    0 => "#####".to_owned(),
+                    n => n.to_string(),
+                },
+                (_, false) => {
+                    let range = (0..line.length).map(|i| line.address.wrapping_add(i));
+                    let any = |access| range.clone().any(|a| self.is(a, access));
+                    format!(
+                        "{}{}",
+                        if any(Access::Read) { 'R' } else { '-' },
+                        if any(Access::Written) { 'W' } else { '-' },
+                    )
+                }
+            };
+            report += &format!("{:>6}   {}\n", marker, line.text);
+        }
+        report
+    }
+
+    fn entries_in(&self, rom: &Rom) -> Vec<u16> {
+        let mut entries = vec![0x200];
+        entries.extend(
+            self.entries()
+                .into_iter()
+                .filter(|a| (0x200..rom.end()).contains(&(*a as u32))),
+        );
+        entries
+    }
+}
+
+fn percent(part: usize, total: usize) -> f64 {
+    match total {
+        0 => 100.0,
+        _ => part as f64 * 100.0 / total as f64,
+    }
+}
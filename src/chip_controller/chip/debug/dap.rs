@@ -0,0 +1,534 @@
+use super::super::{Chip, Instruction, RAM_SIZE};
+use super::symbols::SymbolMap;
+use super::{fault, Fault};
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+use std::fs;
+use std::io::{stdin, stdout, BufRead, BufReader, Error, ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{channel, Receiver, TryRecvError};
+use std::thread;
+
+const REGISTERS: i64 = 1;
+const TIMERS: i64 = 2;
+const STACK: i64 = 3;
+
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+enum State {
+    /// Waiting for the launch and configuration requests
+    Idle,
+    Paused,
+    Running,
+    StepIn,
+    /// Runs until the stack pointer is back at the given depth
+    StepOver(u8),
+    /// Runs until the stack pointer is below the given depth
+    StepOut(u8),
+}
+
+/// A Debug Adapter Protocol server which talks to the editor, usually over stdin and stdout
+///
+/// The chip is driven by the usual emulator loop, which calls `tick` instead of executing the
+/// next instruction directly, so the game keeps being displayed while it is debugged.
+pub(crate) struct DapServer {
+    requests: Receiver<Value>,
+    output: Box<dyn Write>,
+    seq: i64,
+    state: State,
+    stop_on_entry: bool,
+    /// Skips the breakpoint at the current pc after resuming
+    resumed: bool,
+    terminated: bool,
+    symbols: SymbolMap,
+    /// Source files in the symbol map are relative to this directory
+    source_dir: PathBuf,
+    /// Breakpoints set through source lines, grouped by their source file
+    line_breakpoints: BTreeMap<String, Vec<u16>>,
+    instruction_breakpoints: Vec<u16>,
+    function_breakpoints: Vec<u16>,
+}
+
+impl DapServer {
+    /// Starts reading requests from stdin, stdout must not be used for anything else afterwards
+    pub fn new() -> DapServer {
+        DapServer::connect(BufReader::new(stdin()), stdout())
+    }
+
+    /// Starts reading requests from `input`, the responses and events are written to `output`
+    pub fn connect<R, W>(mut input: R, output: W) -> DapServer
+    where
+        R: BufRead + Send + 'static,
+        W: Write + 'static,
+    {
+        let (sender, requests) = channel();
+        thread::spawn(move || loop {
+            match read_message(&mut input) {
+                Ok(Some(message)) => {
+                    if sender.send(message).is_err() {
+                        break;
+                    }
+                }
+                // A broken message is skipped, the editor can still send the next ones
+                Ok(None) => {}
+                Err(_) => break,
+            }
+        });
+        DapServer {
+            requests,
+            output: Box::new(output),
+            seq: 1,
+            state: State::Idle,
+            stop_on_entry: false,
+            resumed: false,
+            terminated: false,
+            symbols: SymbolMap::new(),
+            source_dir: PathBuf::new(),
+            line_breakpoints: BTreeMap::new(),
+            instruction_breakpoints: Vec::new(),
+            function_breakpoints: Vec::new(),
+        }
+    }
+
+    /// Whether the editor ended the session
+    pub fn terminated(&self) -> bool {
+        self.terminated
+    }
+
+    /// Whether the chip is not running right now, so its timers should not be decremented
+    pub fn paused(&self) -> bool {
+        matches!(self.state, State::Idle | State::Paused)
+    }
+
+    /// Handles all pending requests and executes the next instruction if the chip is running
+    pub fn tick(&mut self, chip: &mut Chip) {
+        loop {
+            match self.requests.try_recv() {
+                Ok(request) => self.handle(&request, chip),
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    self.terminated = true;
+                    return;
+                }
+            }
+        }
+        if self.paused() {
+            return;
+        }
+        if !std::mem::take(&mut self.resumed) && self.is_breakpoint(chip.pc) {
+            self.stop("breakpoint", None);
+            return;
+        }
+        let instruction = chip.fetch();
+        if let Some(fault) = fault(chip, &instruction) {
+            let text = match fault {
+                Fault::IllegalInstruction => format!("Illegal instruction {}", instruction),
+                Fault::StackUnderflow => "RET with an empty stack".to_owned(),
+                Fault::StackOverflow => "CALL with a full stack".to_owned(),
+            };
+            self.stop("exception", Some(&text));
+            return;
+        }
+        instruction.execute(chip);
+        let finished = match self.state {
+            State::StepIn => true,
+            State::StepOver(depth) => chip.sp <= depth,
+            State::StepOut(depth) => chip.sp < depth,
+            _ => false,
+        };
+        if finished {
+            self.stop("step", None);
+        }
+    }
+
+    fn is_breakpoint(&self, address: u16) -> bool {
+        self.instruction_breakpoints.contains(&address)
+            || self.function_breakpoints.contains(&address)
+            || self
+                .line_breakpoints
+                .values()
+                .any(|addresses| addresses.contains(&address))
+    }
+
+    fn resume(&mut self, state: State) {
+        self.state = state;
+        self.resumed = true;
+    }
+
+    fn stop(&mut self, reason: &str, text: Option<&str>) {
+        self.state = State::Paused;
+        let mut body = json!({ "reason": reason, "threadId": 1, "allThreadsStopped": true });
+        if let Some(text) = text {
+            body["text"] = json!(text);
+        }
+        self.event("stopped", body);
+    }
+
+    fn send(&mut self, mut message: Value) {
+        message["seq"] = json!(self.seq);
+        self.seq += 1;
+        let content = message.to_string();
+        // The editor is gone if this fails, which is noticed by the reading thread
+        let _ = write!(
+            self.output,
+            "Content-Length: {}\r\n\r\n{}",
+            content.len(),
+            content
+        );
+        let _ = self.output.flush();
+    }
+
+    fn event(&mut self, event: &str, body: Value) {
+        self.send(json!({ "type": "event", "event": event, "body": body }));
+    }
+
+    fn respond(&mut self, request: &Value, result: Result<Value, String>) {
+        let mut response = json!({
+            "type": "response",
+            "request_seq": request["seq"],
+            "command": request["command"],
+            "success": result.is_ok(),
+        });
+        match result {
+            Ok(body) => response["body"] = body,
+            Err(message) => response["message"] = json!(message),
+        }
+        self.send(response);
+    }
+
+    fn handle(&mut self, request: &Value, chip: &mut Chip) {
+        let arguments = &request["arguments"];
+        let result = match request["command"].as_str().unwrap_or_default() {
+            "initialize" => Ok(json!({
+                "supportsConfigurationDoneRequest": true,
+                "supportsFunctionBreakpoints": true,
+                "supportsInstructionBreakpoints": true,
+                "supportsReadMemoryRequest": true,
+                "supportsDisassembleRequest": true,
+                "supportsTerminateRequest": true,
+            })),
+            "launch" => self.launch(arguments, chip),
+            "configurationDone" => {
+                if self.stop_on_entry {
+                    self.stop("entry", None);
+                } else {
+                    self.state = State::Running;
+                }
+                Ok(Value::Null)
+            }
+            "setBreakpoints" => Ok(self.set_line_breakpoints(arguments)),
+            "setInstructionBreakpoints" => Ok(self.set_instruction_breakpoints(arguments)),
+            "setFunctionBreakpoints" => Ok(self.set_function_breakpoints(arguments)),
+            "threads" => Ok(json!({ "threads": [{ "id": 1, "name": "Chip 8" }] })),
+            "stackTrace" => Ok(self.stack_trace(chip)),
+            "scopes" => Ok(json!({ "scopes": [
+                { "name": "Registers", "variablesReference": REGISTERS, "expensive": false },
+                { "name": "Timers", "variablesReference": TIMERS, "expensive": false },
+                { "name": "Stack", "variablesReference": STACK, "expensive": false },
+            ]})),
+            "variables" => Ok(json!({
+                "variables": Self::variables(chip, arguments["variablesReference"].as_i64())
+            })),
+            "readMemory" => Self::read_memory(arguments, chip),
+            "disassemble" => self.disassemble(arguments, chip),
+            "continue" => {
+                self.resume(State::Running);
+                Ok(json!({ "allThreadsContinued": true }))
+            }
+            "next" => {
+                self.resume(State::StepOver(chip.sp));
+                Ok(Value::Null)
+            }
+            "stepIn" => {
+                self.resume(State::StepIn);
+                Ok(Value::Null)
+            }
+            "stepOut" => {
+                self.resume(State::StepOut(chip.sp));
+                Ok(Value::Null)
+            }
+            "pause" => {
+                self.stop("pause", None);
+                Ok(Value::Null)
+            }
+            "disconnect" | "terminate" => {
+                self.terminated = true;
+                Ok(Value::Null)
+            }
+            command => Err(format!("Unsupported request {}", command)),
+        };
+        // `initialized` must not arrive before the response to `initialize`
+        let initialize = request["command"] == "initialize";
+        self.respond(request, result);
+        if initialize {
+            self.event("initialized", json!({}));
+        }
+    }
+
+    /// Loads the ROM from `program` and the symbols from `symbols`, which defaults to the ROM
+    /// path with the extension `sym`
+    fn launch(&mut self, arguments: &Value, chip: &mut Chip) -> Result<Value, String> {
+        let program = arguments["program"]
+            .as_str()
+            .ok_or("The launch configuration needs a program")?;
+        let rom = fs::read(program)
+            .and_then(|rom| Chip::check_rom(&rom).map(|_| rom))
+            .map_err(|e| format!("Can't read {}: {}", program, e))?;
+        let symbols = match arguments["symbols"].as_str() {
+            Some(path) => PathBuf::from(path),
+            None => Path::new(program).with_extension("sym"),
+        };
+        self.symbols = match SymbolMap::load(&symbols) {
+            Ok(symbols) => symbols,
+            Err(_) if arguments["symbols"].is_null() => SymbolMap::new(),
+            Err(e) => return Err(format!("{}: {}", symbols.display(), e)),
+        };
+        self.source_dir = symbols.parent().map(Path::to_path_buf).unwrap_or_default();
+        self.stop_on_entry = arguments["stopOnEntry"].as_bool().unwrap_or(false);
+        chip.reset();
+        chip.read_rom_bytes(rom);
+        Ok(Value::Null)
+    }
+
+    fn set_line_breakpoints(&mut self, arguments: &Value) -> Value {
+        let path = arguments["source"]["path"]
+            .as_str()
+            .unwrap_or_default()
+            .to_owned();
+        let mut addresses = Vec::new();
+        let mut breakpoints = Vec::new();
+        for breakpoint in arguments["breakpoints"].as_array().into_iter().flatten() {
+            let line = breakpoint["line"].as_u64().unwrap_or_default() as usize;
+            match self.symbols.line_address(&path, line) {
+                Some((address, line)) => {
+                    addresses.push(address);
+                    breakpoints.push(json!({
+                        "verified": true,
+                        "line": line,
+                        "instructionReference": format!("0x{:03X}", address),
+                    }));
+                }
+                None => breakpoints.push(json!({
+                    "verified": false,
+                    "line": line,
+                    "message": "No code at this line or no symbols loaded",
+                })),
+            }
+        }
+        self.line_breakpoints.insert(path, addresses);
+        json!({ "breakpoints": breakpoints })
+    }
+
+    fn set_instruction_breakpoints(&mut self, arguments: &Value) -> Value {
+        self.instruction_breakpoints.clear();
+        let mut breakpoints = Vec::new();
+        for breakpoint in arguments["breakpoints"].as_array().into_iter().flatten() {
+            let address = breakpoint["instructionReference"]
+                .as_str()
+                .and_then(parse_address)
+                .map(|a| a as i64 + breakpoint["offset"].as_i64().unwrap_or_default())
+                .filter(|a| (0..RAM_SIZE as i64).contains(a));
+            match address {
+                Some(address) => {
+                    self.instruction_breakpoints.push(address as u16);
+                    breakpoints.push(json!({ "verified": true }));
+                }
+                None => breakpoints.push(json!({ "verified": false })),
+            }
+        }
+        json!({ "breakpoints": breakpoints })
+    }
+
+    /// Function breakpoints are either labels from the symbol map or plain addresses
+    fn set_function_breakpoints(&mut self, arguments: &Value) -> Value {
+        self.function_breakpoints.clear();
+        let mut breakpoints = Vec::new();
+        for breakpoint in arguments["breakpoints"].as_array().into_iter().flatten() {
+            let name = breakpoint["name"].as_str().unwrap_or_default();
+            match self.symbols.address(name).or_else(|| parse_address(name)) {
+                Some(address) => {
+                    self.function_breakpoints.push(address);
+                    breakpoints.push(json!({
+                        "verified": true,
+                        "instructionReference": format!("0x{:03X}", address),
+                    }));
+                }
+                None => breakpoints.push(json!({
+                    "verified": false,
+                    "message": format!("Unknown label {}", name),
+                })),
+            }
+        }
+        json!({ "breakpoints": breakpoints })
+    }
+
+    /// The current instruction and the `CALL`s on the stack, the innermost first
+    fn stack_trace(&self, chip: &Chip) -> Value {
+        let addresses =
+            std::iter::once(chip.pc).chain((1..=chip.sp as usize).rev().map(|i| chip.stack[i]));
+        let frames: Vec<Value> = addresses
+            .enumerate()
+            .map(|(id, address)| {
+                let mut frame = json!({
+                    "id": id,
+                    "name": self.symbols.describe(address),
+                    "line": 0,
+                    "column": 0,
+                    "instructionPointerReference": format!("0x{:03X}", address),
+                });
+                if let Some(source) = self.symbols.source(address) {
+                    let path = self.source_dir.join(&source.file);
+                    frame["source"] = json!({ "path": path.to_string_lossy() });
+                    frame["line"] = json!(source.line);
+                }
+                frame
+            })
+            .collect();
+        json!({ "stackFrames": frames, "totalFrames": frames.len() })
+    }
+
+    fn variables(chip: &Chip, reference: Option<i64>) -> Vec<Value> {
+        let variable = |name: String, value: String| json!({ "name": name, "value": value, "variablesReference": 0 });
+        match reference {
+            Some(REGISTERS) => {
+                let mut variables: Vec<Value> = (0..16)
+                    .map(|x| variable(format!("V{:X}", x), format!("0x{:02X}", chip.v[x])))
+                    .collect();
+                let mut i = variable("I".to_owned(), format!("0x{:03X}", chip.i));
+                i["memoryReference"] = json!(format!("0x{:03X}", chip.i));
+                variables.push(i);
+                let mut pc = variable("PC".to_owned(), format!("0x{:03X}", chip.pc));
+                pc["memoryReference"] = json!(format!("0x{:03X}", chip.pc));
+                variables.push(pc);
+                variables
+            }
+            Some(TIMERS) => vec![
+                variable("DT".to_owned(), chip.dt.to_string()),
+                variable("ST".to_owned(), chip.st.to_string()),
+            ],
+            Some(STACK) => std::iter::once(variable("SP".to_owned(), chip.sp.to_string()))
+                .chain(
+                    (1..=chip.sp as usize)
+                        .rev()
+                        .map(|i| variable(format!("[{}]", i), format!("0x{:03X}", chip.stack[i]))),
+                )
+                .collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    fn read_memory(arguments: &Value, chip: &Chip) -> Result<Value, String> {
+        let start = arguments["memoryReference"]
+            .as_str()
+            .and_then(parse_address)
+            .ok_or("Invalid memory reference")? as i64
+            + arguments["offset"].as_i64().unwrap_or_default();
+        let count = arguments["count"].as_i64().unwrap_or_default().max(0);
+        let readable = start.max(0)..(start + count).clamp(0, chip.ram.len() as i64);
+        let bytes = chip.ram.get(readable.start as usize..readable.end as usize);
+        let bytes = bytes.unwrap_or_default();
+        Ok(json!({
+            "address": format!("0x{:03X}", start.max(0)),
+            "data": base64(bytes),
+            "unreadableBytes": count - bytes.len() as i64,
+        }))
+    }
+
+    /// Decodes the instructions around a memory reference, their addresses and operands are
+    /// named after the symbols
+    fn disassemble(&self, arguments: &Value, chip: &Chip) -> Result<Value, String> {
+        let start = arguments["memoryReference"]
+            .as_str()
+            .and_then(parse_address)
+            .ok_or("Invalid memory reference")? as i64
+            + arguments["offset"].as_i64().unwrap_or_default()
+            + arguments["instructionOffset"].as_i64().unwrap_or_default() * 2;
+        let count = arguments["instructionCount"].as_i64().unwrap_or_default();
+        let instructions: Vec<Value> = (0..count)
+            .map(|n| start + n * 2)
+            .map(|address| {
+                let text = match address {
+                    0..=0xfffe => {
+                        let bytes = [chip.ram[address as usize], chip.ram[address as usize + 1]];
+                        let instruction = Instruction::from(bytes);
+                        json!(self.symbols.instruction(&instruction).trim())
+                    }
+                    _ => json!("??"),
+                };
+                let mut instruction = json!({
+                    "address": format!("0x{:03X}", address),
+                    "instruction": text,
+                });
+                if let Some(label) = u16::try_from(address)
+                    .ok()
+                    .and_then(|a| self.symbols.label(a))
+                {
+                    instruction["symbol"] = json!(label);
+                }
+                instruction
+            })
+            .collect();
+        Ok(json!({ "instructions": instructions }))
+    }
+}
+
+impl Drop for DapServer {
+    /// Lets the editor know that the game was closed
+    fn drop(&mut self) {
+        self.event("terminated", json!({}));
+    }
+}
+
+/// Reads a single `Content-Length` framed message, returns `None` if it has no length or isn't
+/// JSON and an error once the input is closed
+fn read_message<R: BufRead>(input: &mut R) -> Result<Option<Value>, Error> {
+    let mut length = None;
+    loop {
+        let mut header = Vec::new();
+        if input.read_until(b'\n', &mut header)? == 0 {
+            return Err(Error::from(ErrorKind::UnexpectedEof));
+        }
+        let header = String::from_utf8_lossy(&header);
+        let header = header.trim();
+        if header.is_empty() {
+            break;
+        }
+        if let Some(value) = header.strip_prefix("Content-Length:") {
+            length = value.trim().parse().ok();
+        }
+    }
+    let length = match length {
+        Some(length) => length,
+        None => return Ok(None),
+    };
+    let mut content = vec![0; length];
+    input.read_exact(&mut content)?;
+    Ok(serde_json::from_slice(&content).ok())
+}
+
+/// Accepts hexadecimal addresses with `0x` prefix or plain decimal ones
+fn parse_address(string: &str) -> Option<u16> {
+    match string.strip_prefix("0x") {
+        Some(hex) => u16::from_str_radix(hex, 16).ok(),
+        None => string.parse().ok(),
+    }
+}
+
+fn base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut string = String::new();
+    for chunk in bytes.chunks(3) {
+        let n = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |n, (i, b)| n | (*b as u32) << (16 - i * 8));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                string.push(ALPHABET[(n >> (18 - i * 6)) as usize & 0x3f] as char);
+            } else {
+                string.push('=');
+            }
+        }
+    }
+    string
+}
@@ -0,0 +1,494 @@
+use super::super::{Chip, RAM_SIZE};
+use super::symbols::SymbolMap;
+use super::{fault, Fault};
+use std::collections::BTreeSet;
+use std::io::{BufRead, BufReader, Error, ErrorKind, Read, Result as IoResult, Write};
+use std::net::{Ipv4Addr, TcpListener, TcpStream};
+use std::thread;
+use std::time::{Duration, Instant};
+
+const SIGINT: u8 = 2;
+const SIGILL: u8 = 4;
+const SIGTRAP: u8 = 5;
+const SIGSEGV: u8 = 11;
+
+/// Number of registers in the order in which they are sent by the `g` packet
+const REGISTER_COUNT: usize = 21;
+
+/// Describes the register layout, the order has to match `GdbServer::register`
+const TARGET_XML: &str = r#"<?xml version="1.0"?>
+<!DOCTYPE target SYSTEM "gdb-target.dtd">
+<target version="1.0">
+  <feature name="org.chip8.core">
+    <reg name="v0" bitsize="8" type="uint8" regnum="0"/>
+    <reg name="v1" bitsize="8" type="uint8"/>
+    <reg name="v2" bitsize="8" type="uint8"/>
+    <reg name="v3" bitsize="8" type="uint8"/>
+    <reg name="v4" bitsize="8" type="uint8"/>
+    <reg name="v5" bitsize="8" type="uint8"/>
+    <reg name="v6" bitsize="8" type="uint8"/>
+    <reg name="v7" bitsize="8" type="uint8"/>
+    <reg name="v8" bitsize="8" type="uint8"/>
+    <reg name="v9" bitsize="8" type="uint8"/>
+    <reg name="va" bitsize="8" type="uint8"/>
+    <reg name="vb" bitsize="8" type="uint8"/>
+    <reg name="vc" bitsize="8" type="uint8"/>
+    <reg name="vd" bitsize="8" type="uint8"/>
+    <reg name="ve" bitsize="8" type="uint8"/>
+    <reg name="vf" bitsize="8" type="uint8"/>
+    <reg name="i" bitsize="16" type="data_ptr"/>
+    <reg name="pc" bitsize="16" type="code_ptr"/>
+    <reg name="sp" bitsize="8" type="uint8"/>
+    <reg name="dt" bitsize="8" type="uint8"/>
+    <reg name="st" bitsize="8" type="uint8"/>
+  </feature>
+</target>
+"#;
+
+/// What the client sent, either a whole packet or the interrupt byte (Ctrl-C)
+enum Incoming {
+    Packet(Vec<u8>),
+    Interrupt,
+}
+
+/// A single client connected to the stub
+struct Connection {
+    reader: BufReader<TcpStream>,
+    writer: TcpStream,
+    no_ack: bool,
+}
+
+impl Connection {
+    fn new(stream: TcpStream) -> IoResult<Connection> {
+        Ok(Connection {
+            reader: BufReader::new(stream.try_clone()?),
+            writer: stream,
+            no_ack: false,
+        })
+    }
+
+    /// Blocks until a packet or an interrupt arrives, returns `None` if the client hung up
+    fn receive(&mut self) -> IoResult<Option<Incoming>> {
+        loop {
+            let mut byte = [0];
+            if self.reader.read(&mut byte)? == 0 {
+                return Ok(None);
+            }
+            match byte[0] {
+                0x03 => return Ok(Some(Incoming::Interrupt)),
+                b'$' => break,
+                // Acks of our own packets and line noise
+                _ => (),
+            }
+        }
+        let mut data = Vec::new();
+        self.reader.read_until(b'#', &mut data)?;
+        if data.pop() != Some(b'#') {
+            return Ok(None);
+        }
+        let mut checksum = [0; 2];
+        self.reader.read_exact(&mut checksum)?;
+        let expected = std::str::from_utf8(&checksum)
+            .ok()
+            .and_then(|c| u8::from_str_radix(c, 16).ok());
+        let actual = data.iter().fold(0u8, |sum, b| sum.wrapping_add(*b));
+        if !self.no_ack {
+            if expected == Some(actual) {
+                self.writer.write_all(b"+")?;
+            } else {
+                self.writer.write_all(b"-")?;
+                return self.receive();
+            }
+        }
+        Ok(Some(Incoming::Packet(unescape(&data))))
+    }
+
+    fn send(&mut self, data: &str) -> IoResult<()> {
+        let checksum = data.bytes().fold(0u8, |sum, b| sum.wrapping_add(b));
+        write!(self.writer, "${}#{:02x}", data, checksum)?;
+        self.writer.flush()
+    }
+
+    /// Checks without blocking whether the client wants to interrupt the running chip
+    fn interrupted(&mut self) -> IoResult<bool> {
+        self.reader.get_ref().set_nonblocking(true)?;
+        let mut byte = [0];
+        let result = match self.reader.read(&mut byte) {
+            Ok(0) => Err(Error::new(ErrorKind::ConnectionAborted, "GDB hung up")),
+            Ok(_) => Ok(byte[0] == 0x03),
+            Err(e) if e.kind() == ErrorKind::WouldBlock => Ok(false),
+            Err(e) => Err(e),
+        };
+        self.reader.get_ref().set_nonblocking(false)?;
+        result
+    }
+}
+
+/// Resolves the `}` escapes of the binary packets
+fn unescape(data: &[u8]) -> Vec<u8> {
+    let mut result = Vec::with_capacity(data.len());
+    let mut bytes = data.iter();
+    while let Some(byte) = bytes.next() {
+        match byte {
+            b'}' => {
+                if let Some(escaped) = bytes.next() {
+                    result.push(escaped ^ 0x20);
+                }
+            }
+            _ => result.push(*byte),
+        }
+    }
+    result
+}
+
+fn parse_hex(string: &str) -> Option<usize> {
+    usize::from_str_radix(string, 16).ok()
+}
+
+fn hex_bytes(string: &str) -> Option<Vec<u8>> {
+    if !string.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..string.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(string.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+/// Whether the actions of a `vCont` packet step or continue the chip, the first action that
+/// applies to our only thread is used and the signals of `C` and `S` are ignored
+fn resume_action(packet: &str) -> Option<bool> {
+    packet
+        .strip_prefix("vCont;")?
+        .split(';')
+        .find_map(|action| {
+            let (action, thread) = match action.split_once(':') {
+                Some((action, thread)) => (action, Some(thread)),
+                None => (action, None),
+            };
+            let ours = thread.is_none_or(|thread| ["1", "-1", "p1.1", "p1.-1"].contains(&thread));
+            match action.chars().next()? {
+                's' | 'S' if ours => Some(true),
+                'c' | 'C' if ours => Some(false),
+                _ => None,
+            }
+        })
+}
+
+/// Parses the `addr,length` part of the memory packets
+fn address_range(string: &str) -> Option<(usize, usize)> {
+    let mut parts = string.splitn(2, ',');
+    Some((parse_hex(parts.next()?)?, parse_hex(parts.next()?)?))
+}
+
+/// A GDB remote serial protocol stub, which lets a GDB client inspect and drive the chip
+///
+/// Registers are transferred in big endian just like the chip stores its instructions, so the
+/// client should use `set endian big`.
+///
+/// GDB can't read the labels of a ROM, so they are used through `monitor` commands instead:
+/// `monitor break draw_player`, `monitor delete draw_player` and `monitor backtrace`.
+pub(crate) struct GdbServer {
+    listener: TcpListener,
+    breakpoints: BTreeSet<u16>,
+    symbols: SymbolMap,
+    /// Instructions per second while the chip is continued
+    freq: usize,
+    last_stop: String,
+}
+
+impl GdbServer {
+    /// Listens on the given port of localhost
+    pub fn bind(port: u16, freq: usize, symbols: SymbolMap) -> IoResult<GdbServer> {
+        Ok(GdbServer {
+            listener: TcpListener::bind((Ipv4Addr::LOCALHOST, port))?,
+            breakpoints: BTreeSet::new(),
+            symbols,
+            freq: freq.max(60),
+            last_stop: format!("S{:02x}", SIGTRAP),
+        })
+    }
+
+    /// Waits for a client and serves it until it detaches, kills the session or hangs up
+    pub fn serve(&mut self, chip: &mut Chip) -> IoResult<()> {
+        let (stream, _) = self.listener.accept()?;
+        stream.set_nodelay(true)?;
+        let mut connection = Connection::new(stream)?;
+        while let Some(incoming) = connection.receive()? {
+            let data = match incoming {
+                Incoming::Packet(data) => data,
+                Incoming::Interrupt => continue,
+            };
+            let packet = String::from_utf8_lossy(&data);
+            let single = match packet.as_ref() {
+                "s" => Some(true),
+                "c" => Some(false),
+                _ => resume_action(&packet),
+            };
+            if let Some(single) = single {
+                self.last_stop = self.resume(&mut connection, chip, single)?;
+                connection.send(&self.last_stop.clone())?;
+                continue;
+            }
+            match packet.as_ref() {
+                "k" => return Ok(()),
+                "D" => {
+                    connection.send("OK")?;
+                    return Ok(());
+                }
+                "QStartNoAckMode" => {
+                    connection.send("OK")?;
+                    connection.no_ack = true;
+                }
+                _ => {
+                    let reply = self.handle(&data, chip);
+                    connection.send(&reply)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Answers every packet that does not need to execute any instructions, the packet stays
+    /// in bytes for the binary data of `X`
+    fn handle(&mut self, data: &[u8], chip: &mut Chip) -> String {
+        let packet = &String::from_utf8_lossy(data);
+        let reply = match packet.chars().next() {
+            Some('?') => Some(self.last_stop.clone()),
+            Some('g') => Some(
+                (0..REGISTER_COUNT)
+                    .map(|n| Self::register(chip, n).unwrap_or_default())
+                    .collect(),
+            ),
+            Some('G') => self.write_registers(chip, &packet[1..]),
+            Some('p') => parse_hex(&packet[1..]).and_then(|n| Self::register(chip, n)),
+            Some('P') => {
+                let mut parts = packet[1..].splitn(2, '=');
+                match (parts.next().and_then(parse_hex), parts.next()) {
+                    (Some(n), Some(value)) => Self::set_register(chip, n, value),
+                    _ => None,
+                }
+            }
+            Some('m') => address_range(&packet[1..]).and_then(|(address, length)| {
+                chip.ram
+                    .get(address..address.checked_add(length)?)
+                    .map(|bytes| bytes.iter().map(|b| format!("{:02x}", b)).collect())
+            }),
+            Some('M') => {
+                let mut parts = packet[1..].splitn(2, ':');
+                match (parts.next().and_then(address_range), parts.next()) {
+                    (Some((address, length)), Some(data)) => {
+                        Self::write_memory(chip, address, length, hex_bytes(data))
+                    }
+                    _ => None,
+                }
+            }
+            Some('X') => {
+                let colon = data.iter().position(|b| *b == b':');
+                let range = colon.and_then(|colon| address_range(&packet[1..colon]));
+                match (range, colon) {
+                    (Some((address, length)), Some(colon)) => {
+                        Self::write_memory(chip, address, length, Some(data[colon + 1..].to_vec()))
+                    }
+                    _ => None,
+                }
+            }
+            Some('Z') | Some('z') => return self.breakpoint(packet),
+            Some('H') | Some('T') => Some("OK".to_owned()),
+            _ => match packet.strip_prefix("qRcmd,").and_then(hex_bytes) {
+                Some(command) => Some(self.monitor(&String::from_utf8_lossy(&command), chip)),
+                None => return Self::query(packet),
+            },
+        };
+        reply.unwrap_or_else(|| "E01".to_owned())
+    }
+
+    /// General queries, an empty answer tells GDB that the packet is not supported
+    fn query(packet: &str) -> String {
+        if packet.starts_with("qSupported") {
+            "PacketSize=1000;qXfer:features:read+;swbreak+;QStartNoAckMode+".to_owned()
+        } else if let Some(range) = packet.strip_prefix("qXfer:features:read:target.xml:") {
+            match address_range(range) {
+                Some((offset, length)) if offset < TARGET_XML.len() => {
+                    let end = (offset + length).min(TARGET_XML.len());
+                    let marker = if end == TARGET_XML.len() { 'l' } else { 'm' };
+                    format!("{}{}", marker, &TARGET_XML[offset..end])
+                }
+                Some(_) => "l".to_owned(),
+                None => "E01".to_owned(),
+            }
+        } else {
+            match packet {
+                "qAttached" => "1".to_owned(),
+                "qC" => "QC1".to_owned(),
+                "qfThreadInfo" => "m1".to_owned(),
+                "qsThreadInfo" => "l".to_owned(),
+                "vCont?" => "vCont;c;C;s;S".to_owned(),
+                _ => String::new(),
+            }
+        }
+    }
+
+    /// Handles `Z0,addr,kind` and `z0,addr,kind`, only software breakpoints are supported
+    fn breakpoint(&mut self, packet: &str) -> String {
+        let mut parts = packet[1..].split(',');
+        if parts.next() != Some("0") {
+            return String::new();
+        }
+        match parts.next().and_then(parse_hex) {
+            Some(address) if address < RAM_SIZE => {
+                if packet.starts_with('Z') {
+                    self.breakpoints.insert(address as u16);
+                } else {
+                    self.breakpoints.remove(&(address as u16));
+                }
+                "OK".to_owned()
+            }
+            _ => "E01".to_owned(),
+        }
+    }
+
+    /// Runs a `monitor` command, the answer is hex encoded text for the GDB console
+    fn monitor(&mut self, command: &str, chip: &Chip) -> String {
+        let mut words = command.split_whitespace();
+        let text = match (words.next(), words.next()) {
+            (Some("break"), Some(location)) | (Some("delete"), Some(location)) => {
+                let address = self
+                    .symbols
+                    .address(location)
+                    .or_else(|| parse_hex(location.trim_start_matches("0x")).map(|a| a as u16));
+                match (address, command.starts_with("break")) {
+                    (Some(address), true) => {
+                        self.breakpoints.insert(address);
+                        format!("Breakpoint at 0x{:03X}\n", address)
+                    }
+                    (Some(address), false) => {
+                        self.breakpoints.remove(&address);
+                        format!("Deleted breakpoint at 0x{:03X}\n", address)
+                    }
+                    (None, _) => format!("Unknown label {}\n", location),
+                }
+            }
+            (Some("backtrace"), None) | (Some("bt"), None) => std::iter::once(chip.pc)
+                .chain((1..=chip.sp as usize).rev().map(|i| chip.stack[i]))
+                .enumerate()
+                .map(|(n, address)| {
+                    format!(
+                        "#{} 0x{:03X} {}\n",
+                        n,
+                        address,
+                        self.symbols.describe(address)
+                    )
+                })
+                .collect(),
+            _ => "Commands: break <label>, delete <label>, backtrace\n".to_owned(),
+        };
+        text.bytes().map(|b| format!("{:02x}", b)).collect()
+    }
+
+    /// Encodes register number `n` as it is sent to GDB
+    fn register(chip: &Chip, n: usize) -> Option<String> {
+        match n {
+            0x0..=0xf => Some(format!("{:02x}", chip.v[n])),
+            16 => Some(format!("{:04x}", chip.i)),
+            17 => Some(format!("{:04x}", chip.pc)),
+            18 => Some(format!("{:02x}", chip.sp)),
+            19 => Some(format!("{:02x}", chip.dt)),
+            20 => Some(format!("{:02x}", chip.st)),
+            _ => None,
+        }
+    }
+
+    fn set_register(chip: &mut Chip, n: usize, value: &str) -> Option<String> {
+        let value = parse_hex(value)?;
+        match n {
+            0x0..=0xf => chip.v[n] = value as u8,
+            16 => chip.i = value as u16,
+            17 => chip.pc = value as u16,
+            18 => chip.sp = (value as u8).min(15),
+            19 => chip.dt = value as u8,
+            20 => chip.st = value as u8,
+            _ => return None,
+        }
+        Some("OK".to_owned())
+    }
+
+    fn write_registers(&self, chip: &mut Chip, data: &str) -> Option<String> {
+        let mut offset = 0;
+        for n in 0..REGISTER_COUNT {
+            let width = Self::register(chip, n)?.len();
+            Self::set_register(chip, n, data.get(offset..offset + width)?)?;
+            offset += width;
+        }
+        Some("OK".to_owned())
+    }
+
+    fn write_memory(
+        chip: &mut Chip,
+        address: usize,
+        length: usize,
+        data: Option<Vec<u8>>,
+    ) -> Option<String> {
+        let data = data?;
+        if data.len() != length {
+            return None;
+        }
+        chip.ram
+            .get_mut(address..address.checked_add(length)?)?
+            .copy_from_slice(&data);
+        Some("OK".to_owned())
+    }
+
+    /// Executes a single instruction, instructions which would crash the emulator are not
+    /// executed and reported as signal instead
+    fn step(chip: &mut Chip) -> Option<u8> {
+        let instruction = chip.fetch();
+        match fault(chip, &instruction) {
+            Some(Fault::IllegalInstruction) => Some(SIGILL),
+            Some(Fault::StackUnderflow) | Some(Fault::StackOverflow) => Some(SIGSEGV),
+            None => {
+                instruction.execute(chip);
+                None
+            }
+        }
+    }
+
+    /// Steps once or keeps running in 60Hz frames until a breakpoint is hit, an instruction
+    /// traps or the client interrupts, returns the stop reply
+    fn resume(
+        &mut self,
+        connection: &mut Connection,
+        chip: &mut Chip,
+        single: bool,
+    ) -> IoResult<String> {
+        if single {
+            return Ok(match Self::step(chip) {
+                Some(signal) => format!("S{:02x}", signal),
+                None => format!("S{:02x}", SIGTRAP),
+            });
+        }
+        const FRAME: Duration = Duration::from_micros(16_667);
+        let per_frame = self.freq / 60;
+        let mut first = true;
+        loop {
+            let start = Instant::now();
+            for _ in 0..per_frame {
+                // Don't stop at the breakpoint we are continuing from
+                if !first && self.breakpoints.contains(&chip.pc) {
+                    return Ok(format!("T{:02x}swbreak:;", SIGTRAP));
+                }
+                first = false;
+                if let Some(signal) = Self::step(chip) {
+                    return Ok(format!("S{:02x}", signal));
+                }
+            }
+            chip.dec_timers();
+            if connection.interrupted()? {
+                return Ok(format!("S{:02x}", SIGINT));
+            }
+            if let Some(rest) = FRAME.checked_sub(start.elapsed()) {
+                thread::sleep(rest);
+            }
+        }
+    }
+}
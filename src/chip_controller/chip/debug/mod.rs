@@ -1,2 +1,56 @@
-mod rom;
-mod sprite;
\ No newline at end of file
+use super::instruction::Instruction;
+use super::Chip;
+use std::fmt::{Display, Formatter, Result as FmtResult};
+
+pub(crate) mod analyze;
+pub(crate) mod coverage;
+pub(crate) mod dap;
+pub(crate) mod gdb;
+pub(crate) mod profile;
+pub(crate) mod rom;
+pub(crate) mod sprite;
+pub(crate) mod symbols;
+pub(crate) mod trace;
+
+/// Reasons why an instruction can not be executed without crashing the emulator
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub(crate) enum Fault {
+    /// `ERR` or `SYS`, which would panic
+    IllegalInstruction,
+    /// `RET` without a matching `CALL`
+    StackUnderflow,
+    /// `CALL` with a full stack
+    StackOverflow,
+}
+
+/// A fault the chip stopped at instead of executing the instruction
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub(crate) struct Crash {
+    pub fault: Fault,
+    /// Where the instruction is
+    pub pc: u16,
+}
+
+impl Display for Crash {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        let fault = match self.fault {
+            Fault::IllegalInstruction => "Illegal instruction",
+            Fault::StackUnderflow => "Stack underflow",
+            Fault::StackOverflow => "Stack overflow",
+        };
+        write!(f, "{} at {:X}", fault, self.pc)
+    }
+}
+
+/// Checks whether executing the instruction would crash the emulator, so debuggers can stop
+/// before that happens
+pub(crate) fn fault(chip: &Chip, instruction: &Instruction) -> Option<Fault> {
+    match instruction {
+        Instruction::ERR(_) | Instruction::SYS(_) => Some(Fault::IllegalInstruction),
+        Instruction::RET if chip.sp == 0 => Some(Fault::StackUnderflow),
+        Instruction::CALL(_) if chip.sp as usize >= chip.stack.len() - 1 => {
+            Some(Fault::StackOverflow)
+        }
+        _ => None,
+    }
+}
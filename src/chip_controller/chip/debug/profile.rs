@@ -0,0 +1,219 @@
+use super::super::Instruction;
+use super::symbols::SymbolMap;
+use std::collections::BTreeMap;
+
+/// Machine cycles of the COSMAC VIP in one frame, it runs at 1.76MHz with 8 clocks per cycle
+const VIP_FRAME_CYCLES: u64 = 3668;
+
+/// The approximate machine cycles the original interpreter of the COSMAC VIP needs for an
+/// instruction, instructions of SCHIP and XO-CHIP didn't exist there
+///
+/// `DRW` waits for the next interrupt on the VIP, so it is counted as a whole frame.
+fn vip_cycles(instruction: &Instruction) -> Option<u64> {
+    Some(match instruction {
+        Instruction::CLS => 24,
+        Instruction::RET | Instruction::JP(_) | Instruction::CALL(_) | Instruction::JP3N(_) => 23,
+        Instruction::SIREB(_, _) | Instruction::SIRNEB(_, _) | Instruction::LD3NI(_) => 12,
+        Instruction::SIRER(_, _) | Instruction::SIRNER(_, _) => 16,
+        Instruction::SKP(_) | Instruction::SKNP(_) => 16,
+        Instruction::LDBR(_, _) => 6,
+        Instruction::ADDBR(_, _) => 10,
+        Instruction::LDRR(_, _)
+        | Instruction::OR(_, _)
+        | Instruction::AND(_, _)
+        | Instruction::XOR(_, _)
+        | Instruction::ADDRR(_, _)
+        | Instruction::SUB(_, _)
+        | Instruction::SUBN(_, _)
+        | Instruction::SHR(_, _)
+        | Instruction::SHL(_, _) => 44,
+        Instruction::RND(_, _) => 36,
+        Instruction::DRW(_, _, _) => VIP_FRAME_CYCLES,
+        Instruction::LDDTR(_) | Instruction::LDRDT(_) | Instruction::LDRST(_) => 10,
+        Instruction::LDKR(_) => 0,
+        Instruction::ADDRI(_) => 19,
+        Instruction::LDSI(_) => 20,
+        Instruction::LDRBCDL(_) => 204,
+        Instruction::LDRRL(x) | Instruction::LDLRR(x) => 4 + 14 * (*x as u64 + 1),
+        _ => return None,
+    })
+}
+
+#[derive(Debug, Default, Copy, Clone)]
+struct Cost {
+    instructions: u64,
+    cycles: u64,
+}
+
+impl Cost {
+    fn add(&mut self, other: Cost) {
+        self.instructions += other.instructions;
+        self.cycles += other.cycles;
+    }
+}
+
+/// What the folded stacks are weighted by
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub(crate) enum Weight {
+    Instructions,
+    Cycles,
+}
+
+/// Attributes executed instructions to the routine they belong to by following `CALL` and
+/// `RET`, the chip starts in a root routine at `0x200`
+pub(crate) struct Profiler {
+    symbols: SymbolMap,
+    /// Entry addresses of the routines on the call stack
+    stack: Vec<u16>,
+    /// The cost of each call path, only counting instructions of its innermost routine
+    paths: BTreeMap<Vec<u16>, Cost>,
+    calls: BTreeMap<u16, u64>,
+    frames: Vec<Cost>,
+    frame: Cost,
+}
+
+impl Profiler {
+    pub fn new(symbols: SymbolMap) -> Profiler {
+        Profiler {
+            symbols,
+            stack: vec![0x200],
+            paths: BTreeMap::new(),
+            calls: BTreeMap::new(),
+            frames: Vec::new(),
+            frame: Cost::default(),
+        }
+    }
+
+    /// Records an instruction before it is executed
+    pub fn record(&mut self, instruction: &Instruction) {
+        let cost = Cost {
+            instructions: 1,
+            cycles: vip_cycles(instruction).unwrap_or_default(),
+        };
+        self.paths.entry(self.stack.clone()).or_default().add(cost);
+        self.frame.add(cost);
+        match instruction {
+            Instruction::CALL(address) => {
+                *self.calls.entry(*address).or_default() += 1;
+                self.stack.push(*address);
+            }
+            Instruction::RET if self.stack.len() > 1 => {
+                self.stack.pop();
+            }
+            _ => {}
+        }
+    }
+
+    /// Closes the current frame, should be called at 60Hz
+    pub fn frame(&mut self) {
+        self.frames.push(std::mem::take(&mut self.frame));
+    }
+
+    fn name(&self, routine: u16) -> String {
+        match (self.symbols.label(routine), routine) {
+            (Some(label), _) => label.to_owned(),
+            (None, 0x200) => "main".to_owned(),
+            (None, _) => format!("sub_{:03X}", routine),
+        }
+    }
+
+    /// The flat profile, the call tree and the instructions per frame as text
+    pub fn report(&self) -> String {
+        let mut total = Cost::default();
+        let mut flat: BTreeMap<u16, (Cost, Cost)> = BTreeMap::new();
+        let mut tree: BTreeMap<&[u16], (Cost, Cost)> = BTreeMap::new();
+        for (path, cost) in &self.paths {
+            total.add(*cost);
+            flat.entry(*path.last().unwrap()).or_default().0.add(*cost);
+            // Recursive routines are only counted once per path
+            let mut seen = Vec::new();
+            for (depth, routine) in path.iter().enumerate() {
+                if !seen.contains(routine) {
+                    flat.entry(*routine).or_default().1.add(*cost);
+                    seen.push(*routine);
+                }
+                tree.entry(&path[..=depth]).or_default().1.add(*cost);
+            }
+            tree.entry(path).or_default().0.add(*cost);
+        }
+        let percent = |cost: u64| cost as f64 * 100.0 / total.instructions.max(1) as f64;
+
+        let mut report = String::from("Flat profile\n");
+        report += &format!(
+            "{:>8} {:>6} {:>8} {:>6} {:>10} {:>7}  routine\n",
+            "self", "%", "total", "%", "cycles", "calls"
+        );
+        let mut routines: Vec<_> = flat.iter().collect();
+        routines.sort_by_key(|(_, (own, _))| std::cmp::Reverse(own.instructions));
+        for (routine, (own, inclusive)) in routines {
+            report += &format!(
+                "{:>8} {:>5.1}% {:>8} {:>5.1}% {:>10} {:>7}  {}\n",
+                own.instructions,
+                percent(own.instructions),
+                inclusive.instructions,
+                percent(inclusive.instructions),
+                own.cycles,
+                self.calls.get(routine).copied().unwrap_or_default(),
+                self.name(*routine)
+            );
+        }
+
+        // The paths are sorted, so every routine follows the one that called it
+        report += "\nCall tree\n";
+        report += &format!("{:>8} {:>6} {:>8}  routine\n", "total", "%", "self");
+        for (path, (own, inclusive)) in &tree {
+            report += &format!(
+                "{:>8} {:>5.1}% {:>8}  {:indent$}{}\n",
+                inclusive.instructions,
+                percent(inclusive.instructions),
+                own.instructions,
+                "",
+                self.name(*path.last().unwrap()),
+                indent = (path.len() - 1) * 2
+            );
+        }
+
+        report += "\nFrames\n";
+        match self.frames.len() {
+            0 => report += "No frame was completed\n",
+            frames => {
+                let instructions = self.frames.iter().map(|f| f.instructions);
+                let cycles = self.frames.iter().map(|f| f.cycles);
+                let slow = self
+                    .frames
+                    .iter()
+                    .filter(|f| f.cycles > VIP_FRAME_CYCLES)
+                    .count();
+                report += &format!(
+                    "{} frames, {:.1} instructions per frame (min {}, max {})\n",
+                    frames,
+                    instructions.clone().sum::<u64>() as f64 / frames as f64,
+                    instructions.clone().min().unwrap_or_default(),
+                    instructions.max().unwrap_or_default(),
+                );
+                report += &format!(
+                    "{:.0} VIP cycles per frame (max {}), {} frames would be too slow on a VIP\n",
+                    cycles.clone().sum::<u64>() as f64 / frames as f64,
+                    cycles.max().unwrap_or_default(),
+                    slow
+                );
+            }
+        }
+        report
+    }
+
+    /// One line per call path like `main;update;draw_player 1234`, which flamegraph tools read
+    pub fn folded(&self, weight: Weight) -> String {
+        self.paths
+            .iter()
+            .map(|(path, cost)| {
+                let names: Vec<String> = path.iter().map(|routine| self.name(*routine)).collect();
+                let value = match weight {
+                    Weight::Instructions => cost.instructions,
+                    Weight::Cycles => cost.cycles,
+                };
+                format!("{} {}\n", names.join(";"), value)
+            })
+            .collect()
+    }
+}
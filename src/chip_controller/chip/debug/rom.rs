@@ -1,10 +1,48 @@
 #![allow(dead_code)]
 use super::super::instruction::Instruction;
+use super::symbols::SymbolMap;
+use std::collections::{BTreeMap, BTreeSet};
 use std::fmt::{Display, Formatter, Result as FmtResult};
 use std::fs;
 use std::io::Error;
+
+/// The syntax in which the code of a ROM is written
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub(crate) enum Syntax {
+    /// The mnemonics of `Instruction`
+    Mnemonic,
+    /// Octo assembly, which can be assembled again
+    Octo,
+}
+
+/// How regions that are not code are written
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub(crate) enum DataStyle {
+    Bytes,
+    /// Data which is drawn by `DRW` is written as bitmap, everything else as bytes
+    Sprites,
+}
+
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Copy, Clone)]
+enum LabelKind {
+    /// Target of a `CALL`
+    Routine,
+    /// Target of a `JP` or a skip
+    Jump,
+    /// Loaded into I
+    Data,
+}
+
+/// A line of a listing
+pub(crate) struct Line {
+    pub address: u16,
+    /// The amount of bytes written in this line
+    pub length: u16,
+    pub text: String,
+}
+
 /// A struct which is only used to display chip8 code in a readable manner
-pub(super) struct Rom {
+pub(crate) struct Rom {
     instructions: Vec<u8>,
     offset: usize,
 }
@@ -12,34 +50,425 @@ pub(super) struct Rom {
 impl Rom {
     pub fn new(path: String) -> Result<Rom, Error> {
         match fs::read(path) {
-            Ok(instructions) => Ok(Rom {
-                instructions: instructions,
-                offset: 0x200,
-            }),
+            Ok(instructions) => Ok(Rom::from_bytes(instructions)),
             Err(e) => Err(e),
         }
     }
 
-    pub fn instruction(&self, address: u8) -> Instruction {
+    pub fn from_bytes(instructions: Vec<u8>) -> Rom {
+        Rom {
+            instructions,
+            offset: 0x200,
+        }
+    }
+
+    /// Decodes the instruction at the given RAM address, bytes behind the ROM are read as `0`
+    pub fn instruction(&self, address: u16) -> Instruction {
         Instruction::from([
-            self.instructions[address as usize],
-            self.instructions[(address + 1) as usize],
+            self.byte(address).unwrap_or(0),
+            self.byte(address.wrapping_add(1)).unwrap_or(0),
+        ])
+    }
+
+    /// The amount of bytes the instruction at the given address takes
+    pub fn instruction_length(&self, address: u16) -> u16 {
+        self.instruction(address).length() as u16
+    }
+
+    /// The address which follows the `LD4NI` at the given address
+    fn long_address(&self, address: u16) -> u16 {
+        u16::from_be_bytes([
+            self.byte(address.wrapping_add(2)).unwrap_or(0),
+            self.byte(address.wrapping_add(3)).unwrap_or(0),
         ])
     }
+
+    /// The byte at the given RAM address
+    pub fn byte(&self, address: u16) -> Option<u8> {
+        (address as usize)
+            .checked_sub(self.offset)
+            .and_then(|i| self.instructions.get(i))
+            .copied()
+    }
+
+    /// The first address after the ROM, which is 0x10000 for a ROM that fills the RAM
+    pub fn end(&self) -> u32 {
+        (self.offset + self.instructions.len()) as u32
+    }
+
+    fn contains(&self, address: u16) -> bool {
+        (self.offset as u32..self.end()).contains(&(address as u32))
+    }
+
+    /// Separates code from data by following every path through the code, starting at `0x200`
+    pub fn disassemble(&self) -> Disassembly<'_> {
+        self.disassemble_from(&[self.offset as u16])
+    }
+
+    /// Like `disassemble` but with additional entry points, for example addresses that are
+    /// known to be executed but can't be found statically because of `JP3N`
+    pub fn disassemble_from(&self, entries: &[u16]) -> Disassembly<'_> {
+        let mut code = BTreeSet::new();
+        let mut labels = BTreeMap::new();
+        let mut sprites = BTreeMap::new();
+        let mut invalid = BTreeSet::new();
+        let mut pending: Vec<(u16, Option<u16>)> = entries.iter().map(|e| (*e, None)).collect();
+        while let Some((mut address, mut i)) = pending.pop() {
+            // Follows a single path until it ends or reaches known code
+            while self.contains(address) && !code.contains(&address) {
+                let instruction = self.instruction(address);
+                if let Instruction::ERR(_) = instruction {
+                    invalid.insert(address);
+                    break;
+                }
+                code.insert(address);
+                let next = address.wrapping_add(self.instruction_length(address));
+                match instruction {
+                    Instruction::RET | Instruction::SYS(_) | Instruction::EXIT => break,
+                    Instruction::JP(target) => {
+                        Self::label(&mut labels, target, LabelKind::Jump);
+                        address = target;
+                        continue;
+                    }
+                    // Only the target for V0 = 0 can be found, it is usually a jump table
+                    Instruction::JP3N(target) => {
+                        Self::label(&mut labels, target, LabelKind::Jump);
+                        address = target;
+                        i = None;
+                        continue;
+                    }
+                    Instruction::CALL(target) => {
+                        Self::label(&mut labels, target, LabelKind::Routine);
+                        pending.push((target, i));
+                        i = None;
+                    }
+                    Instruction::SIREB(_, _)
+                    | Instruction::SIRNEB(_, _)
+                    | Instruction::SIRER(_, _)
+                    | Instruction::SIRNER(_, _)
+                    | Instruction::SKP(_)
+                    | Instruction::SKNP(_) => {
+                        pending.push((next.wrapping_add(self.instruction_length(next)), i));
+                    }
+                    Instruction::LD3NI(target) => {
+                        Self::label(&mut labels, target, LabelKind::Data);
+                        i = Some(target);
+                    }
+                    Instruction::LD4NI => {
+                        let target = self.long_address(address);
+                        Self::label(&mut labels, target, LabelKind::Data);
+                        i = Some(target);
+                    }
+                    Instruction::LDSI(_) | Instruction::LDBSI(_) | Instruction::ADDRI(_) => {
+                        i = None
+                    }
+                    Instruction::DRW(_, _, n) => {
+                        // 16x16 sprites take two bytes per row
+                        let length = match n {
+                            0 => 32,
+                            _ => n,
+                        };
+                        if let Some(sprite) = i {
+                            let height = sprites.entry(sprite).or_insert(length);
+                            *height = (*height).max(length);
+                        }
+                    }
+                    _ => (),
+                }
+                address = next;
+            }
+        }
+        // Labels in the middle of an instruction can't be written
+        let data: BTreeSet<u16> = (self.offset as u32..self.end())
+            .map(|a| a as u16)
+            .filter(|a| {
+                (a.saturating_sub(3)..=*a)
+                    .filter(|c| code.contains(c))
+                    .all(|c| c as u32 + self.instruction_length(c) as u32 <= *a as u32)
+            })
+            .collect();
+        labels.retain(|a, _| code.contains(a) || data.contains(a));
+        sprites.retain(|a, _| data.contains(a));
+        Disassembly {
+            rom: self,
+            code,
+            labels,
+            sprites,
+            invalid,
+            symbols: None,
+        }
+    }
+
+    /// Keeps the most meaningful kind if an address is reached in different ways
+    fn label(labels: &mut BTreeMap<u16, LabelKind>, address: u16, kind: LabelKind) {
+        let entry = labels.entry(address).or_insert(kind);
+        *entry = (*entry).min(kind);
+    }
 }
 
 impl Display for Rom {
     fn fmt(&self, f: &mut Formatter) -> FmtResult {
-        let mut string = String::new();
-        for i in 0..self.instructions.len() / 2 {
-            string += format!(
-                "{:X}-{:X}: {}\n",
-                self.offset + i * 2,
-                self.offset + i * 2 + 1,
-                self.instruction((self.offset + i * 2) as u8)
-            )
-            .as_str();
-        }
-        write!(f, "{}", string)
+        write!(
+            f,
+            "{}",
+            self.disassemble()
+                .listing(Syntax::Mnemonic, DataStyle::Sprites)
+        )
+    }
+}
+
+/// The result of `Rom::disassemble`, which knows which addresses are code and which are data
+pub(crate) struct Disassembly<'a> {
+    rom: &'a Rom,
+    /// Addresses where an instruction starts
+    code: BTreeSet<u16>,
+    labels: BTreeMap<u16, LabelKind>,
+    /// Sprites that are drawn and their height
+    sprites: BTreeMap<u16, u8>,
+    /// Addresses which are reached but don't hold a valid instruction
+    invalid: BTreeSet<u16>,
+    /// Names from the assembler which are used instead of the generated labels
+    symbols: Option<&'a SymbolMap>,
+}
+
+impl<'a> Disassembly<'a> {
+    pub fn is_code(&self, address: u16) -> bool {
+        self.code.contains(&address)
+    }
+
+    /// Addresses where an instruction starts
+    pub fn code(&self) -> impl Iterator<Item = u16> + '_ {
+        self.code.iter().copied()
+    }
+
+    /// Addresses which are reached by the code but can't be decoded
+    pub fn invalid(&self) -> impl Iterator<Item = u16> + '_ {
+        self.invalid.iter().copied()
+    }
+
+    /// Whether the address is the start of data that is loaded into I
+    pub fn is_data(&self, address: u16) -> bool {
+        self.labels.get(&address) == Some(&LabelKind::Data)
     }
+
+    pub fn rom(&self) -> &Rom {
+        self.rom
+    }
+
+    /// Uses the labels of a symbol map instead of generated ones where there are any
+    pub fn with_symbols(mut self, symbols: &'a SymbolMap) -> Self {
+        self.symbols = Some(symbols);
+        self
+    }
+
+    /// The label of an address, either from the symbol map or generated
+    pub fn label(&self, address: u16) -> Option<String> {
+        if let Some(label) = self.symbols.and_then(|symbols| symbols.label(address)) {
+            return Some(label.to_owned());
+        }
+        self.labels.get(&address).map(|kind| {
+            let prefix = match kind {
+                LabelKind::Routine => "sub",
+                LabelKind::Jump => "label",
+                LabelKind::Data if self.sprites.contains_key(&address) => "sprite",
+                LabelKind::Data => "data",
+            };
+            format!("{}_{:03X}", prefix, address)
+        })
+    }
+
+    fn target(&self, address: u16, syntax: Syntax) -> String {
+        match (self.label(address), syntax) {
+            (Some(label), _) => label,
+            (None, Syntax::Mnemonic) => format!("{:X}", address),
+            (None, Syntax::Octo) => format!("0x{:03X}", address),
+        }
+    }
+
+    /// Writes the whole ROM, code as instructions and everything else as data
+    pub fn listing(&self, syntax: Syntax, style: DataStyle) -> String {
+        self.lines(syntax, style)
+            .iter()
+            .map(|line| format!("{}\n", line.text))
+            .collect()
+    }
+
+    /// The lines of the listing and the bytes each of them covers, labels cover no bytes
+    pub fn lines(&self, syntax: Syntax, style: DataStyle) -> Vec<Line> {
+        let mut lines = Vec::new();
+        // Counted in u32, so a ROM that reaches the end of the RAM doesn't overflow it
+        let mut next = self.rom.offset as u32;
+        let mut push = |address: u16, length: u16, text: String| {
+            lines.push(Line {
+                address,
+                length,
+                text,
+            })
+        };
+        // Octo starts executing at the label main
+        if syntax == Syntax::Octo && self.label(next as u16).as_deref() != Some("main") {
+            push(next as u16, 0, ": main".to_owned());
+        }
+        while next < self.rom.end() {
+            let address = next as u16;
+            if let Some(label) = self.label(address) {
+                push(
+                    address,
+                    0,
+                    match syntax {
+                        Syntax::Mnemonic => format!("{}:", label),
+                        Syntax::Octo => format!(": {}", label),
+                    },
+                );
+            }
+            if self.is_code(address) {
+                let instruction = self.rom.instruction(address);
+                let length = self.rom.instruction_length(address);
+                let text = match syntax {
+                    Syntax::Mnemonic => format!(
+                        "{:X}-{:X}: {}",
+                        address,
+                        address.wrapping_add(length - 1),
+                        self.mnemonic(address, &instruction)
+                    ),
+                    Syntax::Octo => format!("\t{}", self.octo(address, &instruction)),
+                };
+                push(address, length, text);
+                next += length as u32;
+                continue;
+            }
+            let length = match self.sprites.get(&address) {
+                Some(height) if style == DataStyle::Sprites => (next..next + *height as u32)
+                    .take_while(|a| *a < self.rom.end() && !self.is_code(*a as u16))
+                    .count() as u16,
+                _ => 0,
+            };
+            if length > 0 {
+                // A sprite is written as one row per line
+                for row in (next..next + length as u32).map(|a| a as u16) {
+                    let byte = self.rom.byte(row).unwrap_or(0);
+                    let text = match syntax {
+                        Syntax::Mnemonic => {
+                            format!("{:X}: {:>9} {:02X}\t; {}", row, "DB", byte, bitmap(byte))
+                        }
+                        Syntax::Octo => format!("\t0b{:08b}", byte),
+                    };
+                    push(row, 1, text);
+                }
+                next += length as u32;
+            } else {
+                // Plain data is grouped into lines of up to 8 bytes, ending at the next label
+                let mut end = next + 1;
+                while end < self.rom.end()
+                    && end - next < 8
+                    && !self.is_code(end as u16)
+                    && self.label(end as u16).is_none()
+                {
+                    end += 1;
+                }
+                let bytes: Vec<String> = (next..end)
+                    .map(|a| a as u16)
+                    .map(|a| match syntax {
+                        Syntax::Mnemonic => format!("{:02X}", self.rom.byte(a).unwrap_or(0)),
+                        Syntax::Octo => format!("0x{:02X}", self.rom.byte(a).unwrap_or(0)),
+                    })
+                    .collect();
+                let text = match syntax {
+                    Syntax::Mnemonic => format!("{:X}: {:>9} {}", address, "DB", bytes.join(" ")),
+                    Syntax::Octo => format!("\t{}", bytes.join(" ")),
+                };
+                push(address, (end - next) as u16, text);
+                next = end;
+            }
+        }
+        lines
+    }
+
+    /// Like the `Display` of `Instruction`, but with labels instead of addresses
+    fn mnemonic(&self, address: u16, instruction: &Instruction) -> String {
+        let target = |name: &str, address: &u16| {
+            format!("{:>7} {}", name, self.target(*address, Syntax::Mnemonic))
+        };
+        match instruction {
+            Instruction::JP(address) => target("JP", address),
+            Instruction::JP3N(address) => target("JP3N", address),
+            Instruction::CALL(address) => target("CALL", address),
+            Instruction::LD3NI(address) => target("LD3NI", address),
+            Instruction::LD4NI => target("LD4NI", &self.rom.long_address(address)),
+            _ => instruction.to_string(),
+        }
+    }
+
+    fn octo(&self, address: u16, instruction: &Instruction) -> String {
+        match instruction {
+            Instruction::CLS => "clear".to_owned(),
+            Instruction::RET => "return".to_owned(),
+            Instruction::SYS(address) => format!("0x{:02X} 0x{:02X}", address >> 8, address & 0xff),
+            Instruction::ERR(opcode) => format!("0x{:02X} 0x{:02X}", opcode >> 8, opcode & 0xff),
+            Instruction::JP(address) => format!("jump {}", self.target(*address, Syntax::Octo)),
+            Instruction::JP3N(address) => format!("jump0 {}", self.target(*address, Syntax::Octo)),
+            Instruction::CALL(address) => match self.label(*address) {
+                Some(label) => label,
+                None => format!(":call 0x{:03X}", address),
+            },
+            Instruction::SIREB(x, byte) => format!("if v{:x} != 0x{:02X} then", x, byte),
+            Instruction::SIRNEB(x, byte) => format!("if v{:x} == 0x{:02X} then", x, byte),
+            Instruction::SIRER(x, y) => format!("if v{:x} != v{:x} then", x, y),
+            Instruction::SIRNER(x, y) => format!("if v{:x} == v{:x} then", x, y),
+            Instruction::SKP(x) => format!("if v{:x} -key then", x),
+            Instruction::SKNP(x) => format!("if v{:x} key then", x),
+            Instruction::LDBR(x, byte) => format!("v{:x} := 0x{:02X}", x, byte),
+            Instruction::LDRR(x, y) => format!("v{:x} := v{:x}", x, y),
+            Instruction::LD3NI(address) => format!("i := {}", self.target(*address, Syntax::Octo)),
+            Instruction::LDDTR(x) => format!("v{:x} := delay", x),
+            Instruction::LDRDT(x) => format!("delay := v{:x}", x),
+            Instruction::LDKR(x) => format!("v{:x} := key", x),
+            Instruction::LDRST(x) => format!("buzzer := v{:x}", x),
+            Instruction::LDSI(x) => format!("i := hex v{:x}", x),
+            Instruction::LDRBCDL(x) => format!("bcd v{:x}", x),
+            Instruction::LDRRL(x) => format!("save v{:x}", x),
+            Instruction::LDLRR(x) => format!("load v{:x}", x),
+            Instruction::ADDBR(x, byte) => format!("v{:x} += 0x{:02X}", x, byte),
+            Instruction::ADDRR(x, y) => format!("v{:x} += v{:x}", x, y),
+            Instruction::ADDRI(x) => format!("i += v{:x}", x),
+            Instruction::OR(x, y) => format!("v{:x} |= v{:x}", x, y),
+            Instruction::AND(x, y) => format!("v{:x} &= v{:x}", x, y),
+            Instruction::XOR(x, y) => format!("v{:x} ^= v{:x}", x, y),
+            Instruction::SUB(x, y) => format!("v{:x} -= v{:x}", x, y),
+            Instruction::SUBN(x, y) => format!("v{:x} =- v{:x}", x, y),
+            Instruction::SHR(x, y) => format!("v{:x} >>= v{:x}", x, y),
+            Instruction::SHL(x, y) => format!("v{:x} <<= v{:x}", x, y),
+            Instruction::RND(x, byte) => format!("v{:x} := random 0x{:02X}", x, byte),
+            Instruction::DRW(x, y, n) => format!("sprite v{:x} v{:x} {}", x, y, n),
+            Instruction::SCD(n) => format!("scroll-down {}", n),
+            Instruction::SCU(n) => format!("scroll-up {}", n),
+            Instruction::SCR => "scroll-right".to_owned(),
+            Instruction::SCL => "scroll-left".to_owned(),
+            Instruction::EXIT => "exit".to_owned(),
+            Instruction::LOW => "lores".to_owned(),
+            Instruction::HIGH => "hires".to_owned(),
+            Instruction::LDBSI(x) => format!("i := bighex v{:x}", x),
+            Instruction::LDRF(x) => format!("saveflags v{:x}", x),
+            Instruction::LDFR(x) => format!("loadflags v{:x}", x),
+            Instruction::SAVE(x, y) => format!("save v{:x} - v{:x}", x, y),
+            Instruction::LOAD(x, y) => format!("load v{:x} - v{:x}", x, y),
+            Instruction::LD4NI => format!(
+                "i := long {}",
+                self.target(self.rom.long_address(address), Syntax::Octo)
+            ),
+            Instruction::PLN(n) => format!("plane {}", n),
+            Instruction::AUDIO => "audio".to_owned(),
+            Instruction::PITCH(x) => format!("pitch := v{:x}", x),
+        }
+    }
+}
+
+/// A single sprite row drawn with blocks
+fn bitmap(byte: u8) -> String {
+    (0..8)
+        .map(|bit| match byte << bit & 0x80 {
+            0 => ' ',
+            _ => '█',
+        })
+        .collect()
 }
@@ -1,7 +1,19 @@
 #![allow(dead_code)]
+use super::super::{Instruction, SPRITES};
+use super::analyze::Analysis;
+use super::rom::Rom;
+use std::collections::BTreeMap;
 use std::fmt::{Display, Formatter, Result as FmtResult};
 
-struct Sprite {
+/// How many sprites are put next to each other in the image of a sprite sheet
+const SHEET_COLUMNS: usize = 8;
+/// The size of a cell in the image, it fits a 16x16 sprite below its address
+const CELL_WIDTH: usize = 22;
+const CELL_HEIGHT: usize = 25;
+/// Each pixel of the image is drawn as a square of this size, so the sheet isn't tiny
+const SHEET_SCALE: usize = 4;
+
+pub(crate) struct Sprite {
     pixels: Vec<u8>,
     height: usize,
     width: usize,
@@ -16,6 +28,18 @@ impl Sprite {
         }
     }
 
+    /// The sprite `DRW` draws from `bytes`, rows of 16 pixel wide sprites take two bytes
+    pub fn from_bytes(bytes: &[u8], width: usize) -> Sprite {
+        let mut sprite = Sprite::new(width, bytes.len() * 8 / width);
+        for (i, byte) in bytes.iter().enumerate() {
+            for bit in 0..8 {
+                let (x, y) = ((i * 8 + bit) % width, i * 8 / width);
+                *sprite.pixel_mut(x, y) = (byte >> (7 - bit)) & 1;
+            }
+        }
+        sprite
+    }
+
     pub fn get_height(&self) -> usize {
         self.height
     }
@@ -48,3 +72,134 @@ impl Display for Sprite {
         write!(f, "{}", sprite_string)
     }
 }
+
+/// Collects the sprites a ROM draws, either by looking for the `LD3NI` in front of a `DRW` or
+/// by recording I whenever `DRW` is executed
+#[derive(Default)]
+pub(crate) struct SpriteFinder {
+    /// The width and biggest height drawn from an address
+    sprites: BTreeMap<u16, (usize, usize)>,
+}
+
+impl SpriteFinder {
+    pub fn new() -> SpriteFinder {
+        SpriteFinder::default()
+    }
+
+    /// Finds the sprites whose address is loaded right before they are drawn
+    pub fn scan(rom: &Rom) -> SpriteFinder {
+        let mut finder = SpriteFinder::new();
+        let disassembly = rom.disassemble();
+        for address in disassembly.code() {
+            if let Instruction::DRW(_, _, n) = rom.instruction(address) {
+                if let Some(sprite) = Analysis::index(&disassembly, address) {
+                    finder.record(sprite, n);
+                }
+            }
+        }
+        finder
+    }
+
+    /// Records that `DRW` with the given n drew the sprite at `address`
+    pub fn record(&mut self, address: u16, n: u8) {
+        let (width, height) = match n {
+            0 => (16, 16),
+            _ => (8, n as usize),
+        };
+        let size = self.sprites.entry(address).or_insert((width, height));
+        *size = (size.0.max(width), size.1.max(height));
+    }
+
+    /// The found sprites with the bytes they currently have in `memory`
+    pub fn sheet(&self, memory: impl Fn(u16) -> u8) -> SpriteSheet {
+        SpriteSheet(
+            self.sprites
+                .iter()
+                .map(|(address, (width, height))| {
+                    let bytes: Vec<u8> = (0..(width / 8 * height) as u16)
+                        .map(|i| memory(address.wrapping_add(i)))
+                        .collect();
+                    (*address, Sprite::from_bytes(&bytes, *width))
+                })
+                .collect(),
+        )
+    }
+}
+
+/// Sprites labeled with the address they are stored at
+pub(crate) struct SpriteSheet(Vec<(u16, Sprite)>);
+
+impl SpriteSheet {
+    pub fn sprites(&self) -> &[(u16, Sprite)] {
+        &self.0
+    }
+
+    /// The sheet as a PBM image, each sprite has its address written above it in the font of
+    /// the chip
+    pub fn image(&self) -> Vec<u8> {
+        let rows = self.0.len().div_ceil(SHEET_COLUMNS);
+        let (width, height) = (SHEET_COLUMNS * CELL_WIDTH, rows.max(1) * CELL_HEIGHT);
+        let mut pixels = vec![0; width * height];
+        let mut set = |x: usize, y: usize| pixels[y * width + x] = 1;
+        for (i, (address, sprite)) in self.0.iter().enumerate() {
+            let (left, top) = (
+                (i % SHEET_COLUMNS) * CELL_WIDTH + 1,
+                (i / SHEET_COLUMNS) * CELL_HEIGHT + 1,
+            );
+            for (digit, c) in format!("{:04X}", address).chars().enumerate() {
+                let glyph = &SPRITES[c.to_digit(16).unwrap() as usize * 5..][..5];
+                let glyph = Sprite::from_bytes(glyph, 8);
+                for (x, y) in pixels_of(&glyph) {
+                    set(left + digit * 5 + x, top + y);
+                }
+            }
+            for (x, y) in pixels_of(sprite) {
+                set(left + x, top + 7 + y);
+            }
+        }
+
+        let mut image = format!("P1\n{} {}\n", width * SHEET_SCALE, height * SHEET_SCALE);
+        for row in pixels.chunks(width) {
+            let line: Vec<&str> = row
+                .iter()
+                .flat_map(|pixel| {
+                    std::iter::repeat_n(if *pixel == 1 { "1" } else { "0" }, SHEET_SCALE)
+                })
+                .collect();
+            let line = line.join(" ") + "\n";
+            image += &line.repeat(SHEET_SCALE);
+        }
+        image.into_bytes()
+    }
+}
+
+/// The positions of the set pixels of a sprite
+fn pixels_of(sprite: &Sprite) -> impl Iterator<Item = (usize, usize)> + '_ {
+    (0..sprite.get_height())
+        .flat_map(move |y| (0..sprite.get_width()).map(move |x| (x, y)))
+        .filter(move |(x, y)| sprite.pixel(*x, *y) != 0)
+}
+
+/// Every sprite below a line with its address and size
+///
+/// ```text
+/// 23A: 8x3
+/// ████████
+///  █    █
+/// ████████
+/// ```
+impl Display for SpriteSheet {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        for (address, sprite) in &self.0 {
+            writeln!(
+                f,
+                "{:03X}: {}x{}",
+                address,
+                sprite.get_width(),
+                sprite.get_height()
+            )?;
+            writeln!(f, "{}", sprite)?;
+        }
+        Ok(())
+    }
+}
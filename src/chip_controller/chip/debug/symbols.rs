@@ -0,0 +1,179 @@
+use super::super::Instruction;
+use std::collections::BTreeMap;
+use std::fmt::{Display, Formatter, Result as FmtResult};
+use std::fs;
+use std::io::{Error, ErrorKind};
+use std::path::Path;
+
+/// The line of a source file an address was assembled from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct SourceLine {
+    pub file: String,
+    pub line: usize,
+}
+
+/// Maps labels to addresses and addresses to the source lines they were assembled from
+///
+/// A symbol file is plain text with one entry per line, addresses are hexadecimal:
+/// ```text
+/// label draw_player 2A4
+/// line 2A4 12 game.8o
+/// ```
+/// Empty lines and lines starting with `#` are ignored.
+#[derive(Debug, Default)]
+pub(crate) struct SymbolMap {
+    labels: BTreeMap<String, u16>,
+    addresses: BTreeMap<u16, String>,
+    lines: BTreeMap<u16, SourceLine>,
+}
+
+impl SymbolMap {
+    pub fn new() -> SymbolMap {
+        SymbolMap::default()
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<SymbolMap, Error> {
+        SymbolMap::parse(&fs::read_to_string(path)?)
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), Error> {
+        fs::write(path, self.to_string())
+    }
+
+    pub fn parse(text: &str) -> Result<SymbolMap, Error> {
+        let mut map = SymbolMap::new();
+        for (number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let invalid = || {
+                Error::new(
+                    ErrorKind::InvalidData,
+                    format!("Invalid symbol in line {}: {}", number + 1, line),
+                )
+            };
+            let mut parts = line.splitn(4, char::is_whitespace);
+            match parts.next() {
+                Some("label") => {
+                    let name = parts.next().ok_or_else(invalid)?;
+                    let address = parts
+                        .next()
+                        .and_then(|a| u16::from_str_radix(a, 16).ok())
+                        .ok_or_else(invalid)?;
+                    map.insert_label(name, address);
+                }
+                Some("line") => {
+                    let address = parts
+                        .next()
+                        .and_then(|a| u16::from_str_radix(a, 16).ok())
+                        .ok_or_else(invalid)?;
+                    let source_line = parts
+                        .next()
+                        .and_then(|l| l.parse().ok())
+                        .ok_or_else(invalid)?;
+                    let file = parts.next().ok_or_else(invalid)?;
+                    map.insert_line(address, file, source_line);
+                }
+                _ => return Err(invalid()),
+            }
+        }
+        Ok(map)
+    }
+
+    pub fn insert_label(&mut self, name: &str, address: u16) {
+        self.labels.insert(name.to_owned(), address);
+        self.addresses
+            .entry(address)
+            .or_insert_with(|| name.to_owned());
+    }
+
+    pub fn insert_line(&mut self, address: u16, file: &str, line: usize) {
+        self.lines.insert(
+            address,
+            SourceLine {
+                file: file.to_owned(),
+                line,
+            },
+        );
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.labels.is_empty() && self.lines.is_empty()
+    }
+
+    /// The address of a label
+    pub fn address(&self, label: &str) -> Option<u16> {
+        self.labels.get(label).copied()
+    }
+
+    /// The first label which was defined for exactly this address
+    pub fn label(&self, address: u16) -> Option<&str> {
+        self.addresses.get(&address).map(String::as_str)
+    }
+
+    /// The closest label at or before the address, which is usually the routine it belongs to
+    pub fn routine(&self, address: u16) -> Option<(&str, u16)> {
+        self.addresses
+            .range(..=address)
+            .next_back()
+            .map(|(start, name)| (name.as_str(), *start))
+    }
+
+    /// The address relative to the routine it belongs to, like `draw_player+4`, or just the
+    /// address if there is no label before it
+    pub fn describe(&self, address: u16) -> String {
+        match self.routine(address) {
+            Some((label, start)) if start == address => label.to_owned(),
+            Some((label, start)) => format!("{}+{}", label, address - start),
+            None => format!("0x{:03X}", address),
+        }
+    }
+
+    /// The instruction like it is displayed, but with the label of its address
+    pub fn instruction(&self, instruction: &Instruction) -> String {
+        let text = instruction.to_string();
+        match instruction
+            .address()
+            .and_then(|a| Some((a, self.label(a)?)))
+        {
+            Some((address, label)) => {
+                let operand = format!("{:X}", address);
+                format!("{}{}", text.strip_suffix(&operand).unwrap_or(&text), label)
+            }
+            None => text,
+        }
+    }
+
+    pub fn source(&self, address: u16) -> Option<&SourceLine> {
+        self.lines.get(&address)
+    }
+
+    /// The first address of the given source line, if that line produced no code the next line
+    /// that did is used instead
+    pub fn line_address(&self, file: &str, line: usize) -> Option<(u16, usize)> {
+        self.lines
+            .iter()
+            .filter(|(_, source)| source.line >= line && same_file(file, &source.file))
+            .min_by_key(|(address, source)| (source.line, **address))
+            .map(|(address, source)| (*address, source.line))
+    }
+}
+
+impl Display for SymbolMap {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        for (name, address) in &self.labels {
+            writeln!(f, "label {} {:03X}", name, address)?;
+        }
+        for (address, source) in &self.lines {
+            writeln!(f, "line {:03X} {} {}", address, source.line, source.file)?;
+        }
+        Ok(())
+    }
+}
+
+/// Source files are usually stored relative to the symbol file, so only the trailing path
+/// components have to match
+fn same_file(a: &str, b: &str) -> bool {
+    Path::new(a).ends_with(b) || Path::new(b).ends_with(a)
+}
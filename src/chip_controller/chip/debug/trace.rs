@@ -0,0 +1,36 @@
+use super::super::{Chip, Instruction};
+use super::symbols::SymbolMap;
+use std::io::Write;
+
+/// Writes every executed instruction with the routine it belongs to, calls are indented by
+/// the depth of the stack
+///
+/// ```text
+/// 2A0 main                 CALL draw_player
+/// 2A4   draw_player          LD3NI player_sprite
+/// 2A6   draw_player+2        ADDRI 1
+/// ```
+pub(crate) struct Tracer {
+    output: Box<dyn Write>,
+    symbols: SymbolMap,
+}
+
+impl Tracer {
+    pub fn new(output: Box<dyn Write>, symbols: SymbolMap) -> Tracer {
+        Tracer { output, symbols }
+    }
+
+    /// Logs the instruction before it is executed, errors of the output are ignored so a closed
+    /// pipe doesn't stop the game
+    pub fn trace(&mut self, chip: &Chip, instruction: &Instruction) {
+        let _ = writeln!(
+            self.output,
+            "{:03X} {:indent$}{:<20} {}",
+            chip.pc,
+            "",
+            self.symbols.describe(chip.pc),
+            self.symbols.instruction(instruction).trim(),
+            indent = chip.sp as usize * 2,
+        );
+    }
+}
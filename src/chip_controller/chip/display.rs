@@ -1,6 +1,6 @@
 use std::fmt::{Debug, Display, Formatter, Result as fmtResult};
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct ChipDisplay {
     pixels: Vec<u8>,
     height: u8,
@@ -17,6 +17,15 @@ impl ChipDisplay {
             width: width,
         }
     }
+    /// A display with the given pixels, like the ones of a saved state
+    pub(super) fn with_pixels(width: u8, height: u8, pixels: Vec<u8>) -> ChipDisplay {
+        ChipDisplay {
+            pixels,
+            height,
+            width,
+        }
+    }
+
     pub(crate) fn get_height(&self) -> u8 {
         self.height
     }
@@ -40,6 +49,53 @@ impl ChipDisplay {
     pub fn clear(&mut self) {
         self.pixels = vec![0; self.height as usize * self.width as usize];
     }
+
+    /// Clears only the given planes, each plane is a bit of the pixels
+    pub fn clear_planes(&mut self, planes: u8) {
+        for pixel in self.pixels.iter_mut() {
+            *pixel &= !planes;
+        }
+    }
+
+    /// Changes the resolution, which also clears the display
+    pub fn resize(&mut self, width: u8, height: u8) {
+        self.width = width;
+        self.height = height;
+        self.clear();
+    }
+
+    /// Moves the given planes by dx and dy pixels, pixels that are moved in are empty
+    pub fn scroll(&mut self, dx: i16, dy: i16, planes: u8) {
+        let (width, height) = (self.width as i16, self.height as i16);
+        let previous = self.pixels.clone();
+        for y in 0..height {
+            for x in 0..width {
+                let (from_x, from_y) = (x - dx, y - dy);
+                let moved = if (0..width).contains(&from_x) && (0..height).contains(&from_y) {
+                    previous[(from_y * width + from_x) as usize] & planes
+                } else {
+                    0
+                };
+                let pixel = &mut self.pixels[(y * width + x) as usize];
+                *pixel = (*pixel & !planes) | moved;
+            }
+        }
+    }
+}
+
+impl ChipDisplay {
+    /// The display as a PBM image, pixels that are set in any plane are black
+    pub(crate) fn image(&self) -> String {
+        let mut image = format!("P1\n{} {}\n", self.width, self.height);
+        for row in self.pixels.chunks(self.width as usize) {
+            let line: Vec<&str> = row
+                .iter()
+                .map(|pixel| if *pixel == 0 { "0" } else { "1" })
+                .collect();
+            image += &(line.join(" ") + "\n");
+        }
+        image
+    }
 }
 
 impl Display for ChipDisplay {
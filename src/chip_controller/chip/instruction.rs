@@ -1,4 +1,5 @@
-use super::{input::ChipKey, Chip};
+use super::debug::coverage::Access;
+use super::{input::ChipKey, Chip, BIG_SPRITES_START};
 use rand::Rng;
 use std::fmt::{Debug, Display, Formatter, Result as FmtResult};
 
@@ -67,25 +68,66 @@ pub enum Instruction {
     SUB(u8, u8),
     SUBN(u8, u8),
 
-    SHR(u8),
-    SHL(u8),
+    /// Shift Register x right by one, y is kept so the instruction can be encoded again
+    SHR(u8, u8),
+    /// Shift Register x left by one, y is kept so the instruction can be encoded again
+    SHL(u8, u8),
 
     RND(u8, u8),
     /// Reads n addresses starting from I to I + n-1 and places these sprites at the positions starting from (x, y)
     ///
+    /// If n is 0 a 16x16 sprite is drawn (SCHIP), with several planes selected the sprite data
+    /// for each plane follows the previous one (XO-CHIP)
+    ///
     /// ---
     /// ~~Also wraps around if the sprite overflows from the right or left~~
     ///
     /// All the roms I tested place their sprites correctly and therefore do not need an
     /// **overflow wrap** handling
     DRW(u8, u8, u8),
+
+    /// Scroll the display down by n pixels (SCHIP)
+    SCD(u8),
+    /// Scroll the display up by n pixels (XO-CHIP)
+    SCU(u8),
+    /// Scroll the display right by 4 pixels (SCHIP)
+    SCR,
+    /// Scroll the display left by 4 pixels (SCHIP)
+    SCL,
+    /// Exit the interpreter, the chip just stays at this instruction (SCHIP)
+    EXIT,
+    /// Switch to the 64x32 display (SCHIP)
+    LOW,
+    /// Switch to the 128x64 display (SCHIP)
+    HIGH,
+    /// Load the big 8x10 Sprite which represents the 4 Bit Hex Value into I (SCHIP)
+    LDBSI(u8),
+    /// Load content of Register V0-Vx into the persistent flags (SCHIP)
+    LDRF(u8),
+    /// Load content of the persistent flags into Register V0-Vx (SCHIP)
+    LDFR(u8),
+    /// Save Registers Vx-Vy at I without changing I, also in descending order (XO-CHIP)
+    SAVE(u8, u8),
+    /// Load Registers Vx-Vy from I without changing I, also in descending order (XO-CHIP)
+    LOAD(u8, u8),
+    /// Load the 4 Nibbles that follow this instruction in I, so it takes 4 bytes (XO-CHIP)
+    LD4NI,
+    /// Select the display planes n which are drawn, cleared and scrolled (XO-CHIP)
+    PLN(u8),
+    /// Load the 16 byte audio pattern at I (XO-CHIP)
+    AUDIO,
+    /// Load Register x into the pitch of the audio pattern (XO-CHIP)
+    PITCH(u8),
 }
 
 impl Instruction {
     pub fn execute(&self, chip: &mut Chip) {
+        if let Some(coverage) = &mut chip.coverage {
+            coverage.execute(chip.pc, self.length());
+        }
         match self {
             Instruction::CLS => {
-                chip.display.clear();
+                chip.display.clear_planes(chip.planes);
                 chip.next();
             }
             Instruction::RET => {
@@ -93,20 +135,20 @@ impl Instruction {
                 chip.sp -= 1;
                 chip.next();
             }
+            // `fault` reports these, so the chip stops before them
             Instruction::SYS(address) => {
-                println!(
-                    "This Instruction should not be used, by most of the roms: {:X}",
-                    address
-                );
-                chip.next();
-                panic!();
+                panic!("SYS {:X} can't be executed", address);
             }
 
             Instruction::JP(address) => {
                 chip.pc = *address;
             }
             Instruction::JP3N(nnn) => {
-                chip.pc = *nnn + chip.v[0x0] as u16;
+                let x = match chip.quirks.jumping {
+                    true => (*nnn >> 8) as usize,
+                    false => 0x0,
+                };
+                chip.pc = *nnn + chip.v[x] as u16;
             }
 
             Instruction::CALL(address) => {
@@ -144,31 +186,17 @@ impl Instruction {
                 }
             }
             Instruction::SKP(key) => {
-                let key = ChipKey::from(chip.v[*key as usize]);
-                match chip.pressed_key {
-                    Some(k) => {
-                        let key_pressed = k == key;
-                        if key_pressed {
-                            chip.skip();
-                        } else {
-                            chip.next();
-                        }
-                    }
-                    None => chip.next(),
+                if chip.is_pressed(ChipKey::from(chip.v[*key as usize])) {
+                    chip.skip();
+                } else {
+                    chip.next();
                 }
             }
             Instruction::SKNP(key) => {
-                let key = ChipKey::from(chip.v[*key as usize]);
-                match chip.pressed_key {
-                    Some(k) => {
-                        let key_pressed = k == key;
-                        if !key_pressed {
-                            chip.skip();
-                        } else {
-                            chip.next();
-                        }
-                    }
-                    None => chip.skip(),
+                if !chip.is_pressed(ChipKey::from(chip.v[*key as usize])) {
+                    chip.skip();
+                } else {
+                    chip.next();
                 }
             }
 
@@ -192,7 +220,8 @@ impl Instruction {
                 chip.dt = chip.v[*x as usize];
                 chip.next();
             }
-            Instruction::LDKR(x) => match chip.pressed_key {
+            // Like the COSMAC VIP it waits until a key is let go
+            Instruction::LDKR(x) => match chip.take_released() {
                 Some(k) => {
                     chip.v[*x as usize] = k as u8;
                     chip.next();
@@ -203,28 +232,37 @@ impl Instruction {
                 chip.st = chip.v[*x as usize];
                 chip.next();
             }
-            Instruction::LDSI(n) => {
-                chip.i = (*n * 5) as u16;
+            Instruction::LDSI(x) => {
+                chip.i = (chip.v[*x as usize] & 0xf) as u16 * 5;
                 chip.next();
             }
             Instruction::LDRBCDL(x) => {
                 let e = chip.v[*x as usize] % 10;
                 let z = (chip.v[*x as usize] % 100) / 10;
                 let h = chip.v[*x as usize] / 100;
-                chip.ram[chip.i as usize] = h;
-                chip.ram[chip.i as usize + 1] = z;
-                chip.ram[chip.i as usize + 2] = e;
+                chip.cover(chip.i, 3, Access::Written);
+                for (offset, digit) in [h, z, e].iter().enumerate() {
+                    chip.ram[(chip.i as usize + offset) & 0xffff] = *digit;
+                }
                 chip.next();
             }
             Instruction::LDRRL(x) => {
+                chip.cover(chip.i, *x as usize + 1, Access::Written);
                 for i in 0..=(*x as usize) {
-                    chip.ram[chip.i as usize + i] = chip.v[i];
+                    chip.ram[(chip.i as usize + i) & 0xffff] = chip.v[i];
+                }
+                if chip.quirks.memory {
+                    chip.i = chip.i.wrapping_add(*x as u16 + 1);
                 }
                 chip.next();
             }
             Instruction::LDLRR(x) => {
+                chip.cover(chip.i, *x as usize + 1, Access::Read);
                 for i in 0..=(*x as usize) {
-                    chip.v[i] = chip.ram[chip.i as usize + i] as u8;
+                    chip.v[i] = chip.ram[(chip.i as usize + i) & 0xffff];
+                }
+                if chip.quirks.memory {
+                    chip.i = chip.i.wrapping_add(*x as u16 + 1);
                 }
                 chip.next();
             }
@@ -234,7 +272,9 @@ impl Instruction {
                 chip.next();
             }
             Instruction::ADDRR(x, y) => {
-                chip.v[*x as usize] = chip.v[*x as usize].wrapping_add(chip.v[*y as usize]);
+                let (sum, carry) = chip.v[*x as usize].overflowing_add(chip.v[*y as usize]);
+                chip.v[*x as usize] = sum;
+                chip.v[0xf] = carry as u8;
                 chip.next();
             }
             Instruction::ADDRI(x) => {
@@ -244,95 +284,227 @@ impl Instruction {
 
             Instruction::OR(x, y) => {
                 chip.v[*x as usize] |= chip.v[*y as usize];
+                if chip.quirks.vf_reset {
+                    chip.v[0xf] = 0;
+                }
                 chip.next();
             }
             Instruction::AND(x, y) => {
                 chip.v[*x as usize] &= chip.v[*y as usize];
+                if chip.quirks.vf_reset {
+                    chip.v[0xf] = 0;
+                }
                 chip.next();
             }
             Instruction::XOR(x, y) => {
                 chip.v[*x as usize] ^= chip.v[*y as usize];
+                if chip.quirks.vf_reset {
+                    chip.v[0xf] = 0;
+                }
                 chip.next();
             }
 
+            // The flag is written last, so it wins if x is F
             Instruction::SUB(x, y) => {
-                if chip.v[*x as usize] > chip.v[*y as usize] {
-                    chip.v[0xf] = 1;
-                } else {
-                    chip.v[0xf] = 0;
-                }
-                chip.v[*x as usize] = chip.v[*x as usize].wrapping_sub(chip.v[*y as usize]);
+                let (vx, vy) = (chip.v[*x as usize], chip.v[*y as usize]);
+                chip.v[*x as usize] = vx.wrapping_sub(vy);
+                chip.v[0xf] = (vx >= vy) as u8;
                 chip.next();
             }
             Instruction::SUBN(x, y) => {
-                if chip.v[*x as usize] < chip.v[*y as usize] {
-                    chip.v[0xf] = 1;
-                } else {
-                    chip.v[0xf] = 0;
-                }
-                chip.v[*y as usize] = chip.v[*y as usize].wrapping_sub(chip.v[*x as usize]);
+                let (vx, vy) = (chip.v[*x as usize], chip.v[*y as usize]);
+                chip.v[*x as usize] = vy.wrapping_sub(vx);
+                chip.v[0xf] = (vy >= vx) as u8;
                 chip.next();
             }
 
-            Instruction::SHR(x) => {
-                chip.v[0xf] = match chip.v[*x as usize] << 7 >> 7 {
-                    1 => 1,
-                    _ => 0,
-                };
-                chip.v[*x as usize] = chip.v[*x as usize].wrapping_div(2);
+            Instruction::SHR(x, y) => {
+                let vx = chip.v[Self::shifted(chip, *x, *y)];
+                chip.v[*x as usize] = vx >> 1;
+                chip.v[0xf] = vx & 1;
                 chip.next();
             }
-            Instruction::SHL(x) => {
-                chip.v[0xf] = match chip.v[*x as usize] >> 7 {
-                    1 => 1,
-                    _ => 0,
-                };
-                chip.v[*x as usize] = chip.v[*x as usize].wrapping_mul(2);
+            Instruction::SHL(x, y) => {
+                let vx = chip.v[Self::shifted(chip, *x, *y)];
+                chip.v[*x as usize] = vx << 1;
+                chip.v[0xf] = vx >> 7;
                 chip.next();
             }
 
             Instruction::RND(x, byte) => {
-                let rnd: u8 = rand::thread_rng().gen();
+                let rnd: u8 = chip.rng.gen();
                 chip.v[*x as usize] = byte & rnd;
                 chip.next();
             }
             Instruction::DRW(x, y, n) => {
+                if let Some(sprites) = &mut chip.sprites {
+                    sprites.record(chip.i, *n);
+                }
                 let mut v_f = 0;
-                let mut sprite: Vec<Vec<u8>> = vec![vec![0; *n as usize]; 8];
-                // Puts the sprite into a u8 matrix
-                for row in 0..sprite[0].len() {
-                    for column in 0..sprite.len() {
-                        sprite[column][row] = ((chip.ram[(chip.i as usize + row)] << column as u8)
-                            >> column as u8)
-                            >> 7 - column as u8;
+                let (width, height) = match n {
+                    0 => (16, 16),
+                    _ => (8, *n as usize),
+                };
+                let row_bytes = width / 8;
+                let mut address = chip.i as usize;
+                let (display_width, display_height) = (
+                    chip.display.get_width() as usize,
+                    chip.display.get_height() as usize,
+                );
+                // The position always wraps, only the pixels beyond the edges may be cut off
+                let start_x = chip.v[*x as usize] as usize % display_width;
+                let start_y = chip.v[*y as usize] as usize % display_height;
+                // Every selected plane gets its own sprite data, plane 1 is bit 0 of a pixel
+                for plane in [1, 2] {
+                    if chip.planes & plane == 0 {
+                        continue;
                     }
-                }
-                for sp_x in 0..sprite.len() {
-                    for sp_y in 0..*n as usize {
-                        let dis_x = chip.v[*x as usize] as usize + sp_x;
-                        let dis_y = chip.v[*y as usize] as usize + sp_y;
-                        if dis_x < chip.display.get_width() as usize
-                            && dis_y < chip.display.get_height() as usize
-                        {
-                            let prev_value = chip.display.pixel(dis_x as u8, dis_y as u8);
-                            *chip.display.pixel_mut(dis_x as u8, dis_y as u8) ^=
-                                sprite[sp_x as usize][sp_y as usize];
-                            if chip.display.pixel(dis_x as u8, dis_y as u8) == 0 && prev_value == 1
-                            {
-                                v_f = 1;
+                    for row in 0..height {
+                        for column in 0..width {
+                            let byte = chip.ram[(address + row * row_bytes + column / 8) & 0xffff];
+                            if (byte << (column % 8)) & 0x80 == 0 {
+                                continue;
+                            }
+                            let (mut dis_x, mut dis_y) = (start_x + column, start_y + row);
+                            if !chip.quirks.clipping {
+                                dis_x %= display_width;
+                                dis_y %= display_height;
+                            }
+                            if dis_x < display_width && dis_y < display_height {
+                                let pixel = chip.display.pixel_mut(dis_x as u8, dis_y as u8);
+                                if *pixel & plane != 0 {
+                                    v_f = 1;
+                                }
+                                *pixel ^= plane;
                             }
                         }
                     }
+                    address += height * row_bytes;
                 }
+                chip.cover(chip.i, address - chip.i as usize, Access::Read);
                 chip.v[0xf] = v_f;
                 chip.next();
             }
+
+            Instruction::SCD(n) => {
+                chip.display.scroll(0, *n as i16, chip.planes);
+                chip.next();
+            }
+            Instruction::SCU(n) => {
+                chip.display.scroll(0, -(*n as i16), chip.planes);
+                chip.next();
+            }
+            Instruction::SCR => {
+                chip.display.scroll(4, 0, chip.planes);
+                chip.next();
+            }
+            Instruction::SCL => {
+                chip.display.scroll(-4, 0, chip.planes);
+                chip.next();
+            }
+            Instruction::EXIT => {}
+            Instruction::LOW => {
+                chip.display.resize(64, 32);
+                chip.next();
+            }
+            Instruction::HIGH => {
+                chip.display.resize(128, 64);
+                chip.next();
+            }
+            Instruction::LDBSI(x) => {
+                chip.i = BIG_SPRITES_START + (chip.v[*x as usize] & 0xf) as u16 * 10;
+                chip.next();
+            }
+            Instruction::LDRF(x) => {
+                chip.flags[..=*x as usize].copy_from_slice(&chip.v[..=*x as usize]);
+                chip.next();
+            }
+            Instruction::LDFR(x) => {
+                chip.v[..=*x as usize].copy_from_slice(&chip.flags[..=*x as usize]);
+                chip.next();
+            }
+            Instruction::SAVE(x, y) => {
+                let registers = Self::range(*x, *y);
+                chip.cover(chip.i, x.abs_diff(*y) as usize + 1, Access::Written);
+                for (offset, register) in registers.enumerate() {
+                    chip.ram[(chip.i as usize + offset) & 0xffff] = chip.v[register];
+                }
+                chip.next();
+            }
+            Instruction::LOAD(x, y) => {
+                let registers = Self::range(*x, *y);
+                chip.cover(chip.i, x.abs_diff(*y) as usize + 1, Access::Read);
+                for (offset, register) in registers.enumerate() {
+                    chip.v[register] = chip.ram[(chip.i as usize + offset) & 0xffff];
+                }
+                chip.next();
+            }
+            Instruction::LD4NI => {
+                let address = chip.pc as usize + 2;
+                chip.i = u16::from_be_bytes([
+                    chip.ram[address & 0xffff],
+                    chip.ram[(address + 1) & 0xffff],
+                ]);
+                chip.pc = chip.pc.wrapping_add(4);
+            }
+            Instruction::PLN(n) => {
+                chip.planes = *n;
+                chip.next();
+            }
+            Instruction::AUDIO => {
+                let address = chip.i as usize;
+                chip.cover(chip.i, 16, Access::Read);
+                for (offset, byte) in chip.pattern.iter_mut().enumerate() {
+                    *byte = chip.ram[(address + offset) & 0xffff];
+                }
+                chip.next();
+            }
+            Instruction::PITCH(x) => {
+                chip.pitch = chip.v[*x as usize];
+                chip.next();
+            }
             Instruction::ERR(instruction) => {
-                println!("{:X}", instruction);
-                panic!();
+                panic!("{:04X} is no instruction", instruction);
             }
         };
     }
+
+    /// The register `SHR` and `SHL` read, vx is shifted in place with the shifting quirk
+    fn shifted(chip: &Chip, x: u8, y: u8) -> usize {
+        match chip.quirks.shifting {
+            true => x as usize,
+            false => y as usize,
+        }
+    }
+
+    /// The registers from x to y, which are counted down if y is lower than x
+    fn range(x: u8, y: u8) -> Box<dyn Iterator<Item = usize>> {
+        if x <= y {
+            Box::new(x as usize..=y as usize)
+        } else {
+            Box::new((y as usize..=x as usize).rev())
+        }
+    }
+
+    /// The amount of bytes the instruction takes in RAM
+    pub fn length(&self) -> usize {
+        match self {
+            Instruction::LD4NI => 4,
+            _ => 2,
+        }
+    }
+
+    /// The address an instruction jumps to, calls or loads into I
+    pub fn address(&self) -> Option<u16> {
+        match self {
+            Instruction::SYS(address)
+            | Instruction::JP(address)
+            | Instruction::JP3N(address)
+            | Instruction::CALL(address)
+            | Instruction::LD3NI(address) => Some(*address),
+            _ => None,
+        }
+    }
 }
 
 impl From<[u8; 2]> for Instruction {
@@ -347,8 +519,15 @@ impl From<[u8; 2]> for Instruction {
 
         let byte = nnnn as u8;
         match nnnn {
+            0x00c0..=0x00cf => Instruction::SCD(n),
+            0x00d0..=0x00df => Instruction::SCU(n),
             0x00e0 => Instruction::CLS,
             0x00ee => Instruction::RET,
+            0x00fb => Instruction::SCR,
+            0x00fc => Instruction::SCL,
+            0x00fd => Instruction::EXIT,
+            0x00fe => Instruction::LOW,
+            0x00ff => Instruction::HIGH,
             0x0000..=0x0fff => Instruction::SYS(address),
 
             0x1000..=0x1fff => Instruction::JP(address),
@@ -358,19 +537,27 @@ impl From<[u8; 2]> for Instruction {
 
             0x3000..=0x3fff => Instruction::SIREB(x, byte),
             0x4000..=0x4fff => Instruction::SIRNEB(x, byte),
-            0x5000..=0x5fff => Instruction::SIRER(x, y),
-            0x9000..=0x9fff => Instruction::SIRNER(x, y),
+            0x5000..=0x5fff => match n {
+                0x0 => Instruction::SIRER(x, y),
+                0x2 => Instruction::SAVE(x, y),
+                0x3 => Instruction::LOAD(x, y),
+                _ => Instruction::ERR(nnnn),
+            },
+            0x9000..=0x9fff => match n {
+                0x0 => Instruction::SIRNER(x, y),
+                _ => Instruction::ERR(nnnn),
+            },
             0xe000..=0xefff => match nnnn << 2 * 4 >> 2 * 4 {
                 0x9e => Instruction::SKP(key),
                 0xa1 => Instruction::SKNP(key),
-                _ => {
-                    println!("There is no instruction for {:X}", nnnn);
-                    Instruction::ERR(nnnn)
-                }
+                _ => Instruction::ERR(nnnn),
             },
             0x6000..=0x6fff => Instruction::LDBR(x, byte),
             0xa000..=0xafff => Instruction::LD3NI(nnn),
             0xf000..=0xffff => match nnnn << 2 * 4 >> 2 * 4 {
+                0x00 if x == 0 => Instruction::LD4NI,
+                0x02 if x == 0 => Instruction::AUDIO,
+                0x01 => Instruction::PLN(x),
                 0x07 => Instruction::LDDTR(x),
                 0x15 => Instruction::LDRDT(x),
                 0x0a => Instruction::LDKR(x),
@@ -379,12 +566,13 @@ impl From<[u8; 2]> for Instruction {
                 0x33 => Instruction::LDRBCDL(x),
                 0x55 => Instruction::LDRRL(x),
                 0x65 => Instruction::LDLRR(x),
+                0x30 => Instruction::LDBSI(x),
+                0x3a => Instruction::PITCH(x),
+                0x75 => Instruction::LDRF(x),
+                0x85 => Instruction::LDFR(x),
 
                 0x1e => Instruction::ADDRI(x),
-                _ => {
-                    println!("There is no instruction for {:X}", nnnn);
-                    Instruction::ERR(nnnn)
-                }
+                _ => Instruction::ERR(nnnn),
             },
 
             0x7000..=0x7fff => Instruction::ADDBR(x, byte),
@@ -395,13 +583,10 @@ impl From<[u8; 2]> for Instruction {
                 0x3 => Instruction::XOR(x, y),
                 0x4 => Instruction::ADDRR(x, y),
                 0x5 => Instruction::SUB(x, y),
-                0x6 => Instruction::SHR(x),
+                0x6 => Instruction::SHR(x, y),
                 0x7 => Instruction::SUBN(x, y),
-                0xE => Instruction::SHL(x),
-                _ => {
-                    println!("There is no instruction for {:X}", nnnn);
-                    Instruction::ERR(nnnn)
-                }
+                0xE => Instruction::SHL(x, y),
+                _ => Instruction::ERR(nnnn),
             },
 
             0xc000..=0xcfff => Instruction::RND(x, byte),
@@ -444,11 +629,27 @@ impl Display for Instruction {
             Instruction::XOR(x, y) => format!("    XOR {:X}\t\t{:X}", x, y),
             Instruction::SUB(x, y) => format!("    SUB {:X}\t\t{:X}", x, y),
             Instruction::SUBN(x, y) => format!("   SUBN {:X}\t\t{:X}", x, y),
-            Instruction::SHR(x) => format!("    SHR {:X}", x),
-            Instruction::SHL(x) => format!("    SHL {:X}", x),
+            Instruction::SHR(x, y) => format!("    SHR {:X}\t\t{:X}", x, y),
+            Instruction::SHL(x, y) => format!("    SHL {:X}\t\t{:X}", x, y),
             Instruction::RND(x, byte) => format!("    RND {:X}\t\t{:X}", x, byte),
             Instruction::DRW(x, y, n) => format!("    DRW {}\t\t{}\t{:X}", x, y, n),
             Instruction::ERR(instruction) => format!("    ERR {:X}", instruction),
+            Instruction::SCD(n) => format!("    SCD {:X}", n),
+            Instruction::SCU(n) => format!("    SCU {:X}", n),
+            Instruction::SCR => "    SCR ".to_owned(),
+            Instruction::SCL => "    SCL ".to_owned(),
+            Instruction::EXIT => "   EXIT ".to_owned(),
+            Instruction::LOW => "    LOW ".to_owned(),
+            Instruction::HIGH => "   HIGH ".to_owned(),
+            Instruction::LDBSI(x) => format!("  LDBSI {:X}", x),
+            Instruction::LDRF(x) => format!("   LDRF {:X}", x),
+            Instruction::LDFR(x) => format!("   LDFR {:X}", x),
+            Instruction::SAVE(x, y) => format!("   SAVE {:X}\t\t{:X}", x, y),
+            Instruction::LOAD(x, y) => format!("   LOAD {:X}\t\t{:X}", x, y),
+            Instruction::LD4NI => "  LD4NI ".to_owned(),
+            Instruction::PLN(n) => format!("    PLN {:X}", n),
+            Instruction::AUDIO => "  AUDIO ".to_owned(),
+            Instruction::PITCH(x) => format!("  PITCH {:X}", x),
         };
         write!(f, "{}", string)
     }
@@ -488,12 +689,95 @@ impl Debug for Instruction {
             Instruction::XOR(x, y) => format!("    XOR {:X}\t\t{:X}", x, y),
             Instruction::SUB(x, y) => format!("    SUB {:X}\t\t{:X}", x, y),
             Instruction::SUBN(x, y) => format!("   SUBN {:X}\t\t{:X}", x, y),
-            Instruction::SHR(x) => format!("    SHR {:X}", x),
-            Instruction::SHL(x) => format!("    SHL {:X}", x),
+            Instruction::SHR(x, y) => format!("    SHR {:X}\t\t{:X}", x, y),
+            Instruction::SHL(x, y) => format!("    SHL {:X}\t\t{:X}", x, y),
             Instruction::RND(x, byte) => format!("    RND {:X}\t\t{:X}", x, byte),
             Instruction::DRW(x, y, n) => format!("    DRW {}\t\t{}\t{:X}", x, y, n),
             Instruction::ERR(instruction) => format!("    ERR {:X}", instruction),
+            Instruction::SCD(n) => format!("    SCD {:X}", n),
+            Instruction::SCU(n) => format!("    SCU {:X}", n),
+            Instruction::SCR => "    SCR ".to_owned(),
+            Instruction::SCL => "    SCL ".to_owned(),
+            Instruction::EXIT => "   EXIT ".to_owned(),
+            Instruction::LOW => "    LOW ".to_owned(),
+            Instruction::HIGH => "   HIGH ".to_owned(),
+            Instruction::LDBSI(x) => format!("  LDBSI {:X}", x),
+            Instruction::LDRF(x) => format!("   LDRF {:X}", x),
+            Instruction::LDFR(x) => format!("   LDFR {:X}", x),
+            Instruction::SAVE(x, y) => format!("   SAVE {:X}\t\t{:X}", x, y),
+            Instruction::LOAD(x, y) => format!("   LOAD {:X}\t\t{:X}", x, y),
+            Instruction::LD4NI => "  LD4NI ".to_owned(),
+            Instruction::PLN(n) => format!("    PLN {:X}", n),
+            Instruction::AUDIO => "  AUDIO ".to_owned(),
+            Instruction::PITCH(x) => format!("  PITCH {:X}", x),
         };
         write!(f, "{}", string)
     }
 }
+
+/// Encodes an instruction back into the two bytes it was decoded from, `LD4NI` only encodes
+/// its first two bytes, the address has to follow it
+impl From<&Instruction> for [u8; 2] {
+    fn from(instruction: &Instruction) -> [u8; 2] {
+        let xy = |high: u16, x: &u8, y: &u8, low: u16| {
+            high << 12 | (*x as u16 & 0xf) << 8 | (*y as u16 & 0xf) << 4 | low
+        };
+        let xnn = |high: u16, x: &u8, byte: &u8| high << 12 | (*x as u16 & 0xf) << 8 | *byte as u16;
+        let fx = |x: &u8, low: u16| 0xf000 | (*x as u16 & 0xf) << 8 | low;
+        let nnnn: u16 = match instruction {
+            Instruction::SYS(address) => address & 0xfff,
+            Instruction::CLS => 0x00e0,
+            Instruction::RET => 0x00ee,
+            Instruction::ERR(instruction) => *instruction,
+            Instruction::JP(address) => 0x1000 | address & 0xfff,
+            Instruction::JP3N(address) => 0xb000 | address & 0xfff,
+            Instruction::CALL(address) => 0x2000 | address & 0xfff,
+            Instruction::SIREB(x, byte) => xnn(0x3, x, byte),
+            Instruction::SIRNEB(x, byte) => xnn(0x4, x, byte),
+            Instruction::SIRER(x, y) => xy(0x5, x, y, 0x0),
+            Instruction::SIRNER(x, y) => xy(0x9, x, y, 0x0),
+            Instruction::SKP(x) => xnn(0xe, x, &0x9e),
+            Instruction::SKNP(x) => xnn(0xe, x, &0xa1),
+            Instruction::LDBR(x, byte) => xnn(0x6, x, byte),
+            Instruction::LDRR(x, y) => xy(0x8, x, y, 0x0),
+            Instruction::LD3NI(address) => 0xa000 | address & 0xfff,
+            Instruction::LDDTR(x) => fx(x, 0x07),
+            Instruction::LDRDT(x) => fx(x, 0x15),
+            Instruction::LDKR(x) => fx(x, 0x0a),
+            Instruction::LDRST(x) => fx(x, 0x18),
+            Instruction::LDSI(x) => fx(x, 0x29),
+            Instruction::LDRBCDL(x) => fx(x, 0x33),
+            Instruction::LDRRL(x) => fx(x, 0x55),
+            Instruction::LDLRR(x) => fx(x, 0x65),
+            Instruction::ADDBR(x, byte) => xnn(0x7, x, byte),
+            Instruction::ADDRR(x, y) => xy(0x8, x, y, 0x4),
+            Instruction::ADDRI(x) => fx(x, 0x1e),
+            Instruction::OR(x, y) => xy(0x8, x, y, 0x1),
+            Instruction::AND(x, y) => xy(0x8, x, y, 0x2),
+            Instruction::XOR(x, y) => xy(0x8, x, y, 0x3),
+            Instruction::SUB(x, y) => xy(0x8, x, y, 0x5),
+            Instruction::SUBN(x, y) => xy(0x8, x, y, 0x7),
+            Instruction::SHR(x, y) => xy(0x8, x, y, 0x6),
+            Instruction::SHL(x, y) => xy(0x8, x, y, 0xe),
+            Instruction::RND(x, byte) => xnn(0xc, x, byte),
+            Instruction::DRW(x, y, n) => xy(0xd, x, y, *n as u16 & 0xf),
+            Instruction::SCD(n) => 0x00c0 | *n as u16 & 0xf,
+            Instruction::SCU(n) => 0x00d0 | *n as u16 & 0xf,
+            Instruction::SCR => 0x00fb,
+            Instruction::SCL => 0x00fc,
+            Instruction::EXIT => 0x00fd,
+            Instruction::LOW => 0x00fe,
+            Instruction::HIGH => 0x00ff,
+            Instruction::LDBSI(x) => fx(x, 0x30),
+            Instruction::LDRF(x) => fx(x, 0x75),
+            Instruction::LDFR(x) => fx(x, 0x85),
+            Instruction::SAVE(x, y) => xy(0x5, x, y, 0x2),
+            Instruction::LOAD(x, y) => xy(0x5, x, y, 0x3),
+            Instruction::LD4NI => 0xf000,
+            Instruction::PLN(n) => fx(n, 0x01),
+            Instruction::AUDIO => 0xf002,
+            Instruction::PITCH(x) => fx(x, 0x3a),
+        };
+        nnnn.to_be_bytes()
+    }
+}
@@ -1,15 +1,23 @@
 use super::Byte;
+use debug::coverage::{Access, Coverage};
+use debug::sprite::SpriteFinder;
 use display::ChipDisplay;
 pub use input::ChipKey;
 pub use input::KeyCode;
 pub use instruction::Instruction;
+pub use quirks::Quirks;
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+pub(crate) use state::State;
 use std::fs;
 use std::time::{Duration, Instant};
 
-mod debug;
+pub(crate) mod debug;
 mod display;
 mod input;
 mod instruction;
+mod quirks;
+mod state;
 
 const SPRITES: [u8; 80] = [
     0xF0, 0x90, 0x90, 0x90, 0xF0, 0x20, 0x60, 0x20, 0x20, 0x70, 0xF0, 0x10, 0xF0, 0x80, 0xF0, 0xF0,
@@ -19,8 +27,30 @@ const SPRITES: [u8; 80] = [
     0xF0, 0xE0, 0x90, 0x90, 0x90, 0xE0, 0xF0, 0x80, 0xF0, 0x80, 0xF0, 0xF0, 0x80, 0xF0, 0x80, 0x80,
 ];
 
+/// Where the 8x10 hex sprites of SCHIP are stored, right behind the small ones
+const BIG_SPRITES_START: u16 = 0x50;
+
+const BIG_SPRITES: [u8; 160] = [
+    0xFF, 0xFF, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, 0x18, 0x78, 0x78, 0x18, 0x18, 0x18,
+    0x18, 0x18, 0xFF, 0xFF, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xFF, 0xFF,
+    0x03, 0x03, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, 0xC3, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, 0x03, 0x03,
+    0x03, 0x03, 0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, 0xFF, 0xFF, 0xC0, 0xC0,
+    0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, 0xFF, 0xFF, 0x03, 0x03, 0x06, 0x0C, 0x18, 0x18, 0x18, 0x18,
+    0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, 0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF,
+    0x03, 0x03, 0xFF, 0xFF, 0x7E, 0xFF, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, 0xC3, 0xC3, 0xC3, 0xFC, 0xFC,
+    0xC3, 0xC3, 0xFC, 0xFC, 0xC3, 0xC3, 0xFC, 0xFC, 0x3C, 0xFF, 0xC3, 0xC0, 0xC0, 0xC0, 0xC0, 0xC3,
+    0xFF, 0x3C, 0xFC, 0xFE, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xFE, 0xFC, 0xFF, 0xFF, 0xC0, 0xC0,
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC0, 0xC0, 0xC0, 0xC0,
+];
+
+/// The first address behind both fonts
+pub(crate) const FONT_END: u16 = BIG_SPRITES_START + BIG_SPRITES.len() as u16;
+
+/// XO-CHIP can address 64KB, the original chip only used the first 4KB
+const RAM_SIZE: usize = 0x10000;
+
 pub struct Chip {
-    pub(super) ram: [u8; 0xfff],
+    pub(super) ram: [u8; RAM_SIZE],
     pub(super) v: [u8; 16],
     pub(super) dt: u8,
     pub(super) st: u8,
@@ -29,15 +59,40 @@ pub struct Chip {
     pub(super) stack: [u16; 16],
     pub(super) sp: u8,
     pub(super) display: ChipDisplay,
-    pressed_key: Option<ChipKey>,
+    /// The persistent flag registers of SCHIP
+    pub(super) flags: [u8; 16],
+    /// The display planes selected by XO-CHIP, plane 1 is the only one by default
+    pub(super) planes: u8,
+    /// The audio pattern and pitch of XO-CHIP
+    pub(super) pattern: [u8; 16],
+    pub(super) pitch: u8,
+    pub(super) quirks: Quirks,
+    /// Only recorded while a coverage report is wanted
+    pub(super) coverage: Option<Coverage>,
+    /// Only recorded while the drawn sprites are wanted
+    pub(super) sprites: Option<SpriteFinder>,
+    /// Random numbers for `RND`, which repeat when it is seeded
+    pub(super) rng: StdRng,
+    /// The keys that are held down, one bit for each
+    pressed_keys: u16,
+    /// The keys that were let go by the last update, `LDKR` waits for one of them
+    released_keys: u16,
     rom_read: bool,
 }
 
 impl Chip {
     pub(crate) fn new() -> Self {
         let mut chip = Chip {
-            ram: [0; 0xfff],
+            ram: [0; RAM_SIZE],
             display: ChipDisplay::new(),
+            flags: [0; 16],
+            planes: 1,
+            pattern: [0; 16],
+            pitch: 64,
+            quirks: Quirks::default(),
+            coverage: None,
+            sprites: None,
+            rng: StdRng::from_entropy(),
             v: [0; 16],
             dt: 0,
             st: 0,
@@ -45,7 +100,8 @@ impl Chip {
             pc: 0,
             stack: [0; 16],
             sp: 0,
-            pressed_key: None,
+            pressed_keys: 0,
+            released_keys: 0,
             rom_read: false,
         };
         chip.init();
@@ -57,6 +113,9 @@ impl Chip {
         for (i, sprite) in SPRITES.iter().enumerate() {
             self.ram[i] = *sprite;
         }
+        for (i, sprite) in BIG_SPRITES.iter().enumerate() {
+            self.ram[BIG_SPRITES_START as usize + i] = *sprite;
+        }
     }
 
     /// Reads the ROM from a file and stores it into the RAM starting from address `0x200`
@@ -78,6 +137,21 @@ impl Chip {
         }
     }
 
+    /// Fails if the ROM doesn't fit into the RAM starting from address `0x200`
+    pub(crate) fn check_rom(rom: &[Byte]) -> Result<(), std::io::Error> {
+        if rom.len() > RAM_SIZE - 0x200 {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!(
+                    "The ROM has {} bytes, but only {} fit into the RAM",
+                    rom.len(),
+                    RAM_SIZE - 0x200
+                ),
+            ));
+        }
+        Ok(())
+    }
+
     /// Gets the ROM as a vector of bytes and stores it into the RAM starting from address `0x200`
     pub(crate) fn read_rom_bytes(&mut self, file: Vec<Byte>) {
         for (i, nn) in file.iter().enumerate() {
@@ -89,7 +163,7 @@ impl Chip {
 
     /// Resets the chip and makes it ready to read another ROM
     pub(crate) fn reset(&mut self) {
-        self.ram = [0; 0xfff];
+        self.ram = [0; RAM_SIZE];
         self.v = [0; 16];
         self.dt = 0;
         self.st = 0;
@@ -97,15 +171,59 @@ impl Chip {
         self.pc = 0;
         self.stack = [0; 16];
         self.sp = 0;
-        self.pressed_key = None;
+        self.pressed_keys = 0;
+        self.released_keys = 0;
+        self.planes = 1;
+        self.pattern = [0; 16];
+        self.pitch = 64;
+        if self.coverage.is_some() {
+            self.coverage = Some(Coverage::new());
+        }
+        if self.sprites.is_some() {
+            self.sprites = Some(SpriteFinder::new());
+        }
         self.init();
-        self.display.clear();
+        self.display.resize(64, 32);
         self.rom_read = false;
     }
 
+    /// Records that `length` bytes starting at `address` were read or written
+    pub(super) fn cover(&mut self, address: u16, length: usize, access: Access) {
+        if let Some(coverage) = &mut self.coverage {
+            coverage.cover(address as usize, length, access);
+        }
+    }
+
+    /// Makes `RND` return the same numbers in every run with the same seed
+    pub(crate) fn set_seed(&mut self, seed: u64) {
+        self.rng = StdRng::seed_from_u64(seed);
+    }
+
     /// Sets the key thats currently pressed
     pub(crate) fn set_key(&mut self, key: Option<ChipKey>) {
-        self.pressed_key = key;
+        self.set_keys(key.map_or(0, |key| 1 << key as u16));
+    }
+
+    /// Sets all keys that are held down, one bit for each
+    pub(crate) fn set_keys(&mut self, keys: u16) {
+        self.released_keys = self.pressed_keys & !keys;
+        self.pressed_keys = keys;
+    }
+
+    pub(super) fn is_pressed(&self, key: ChipKey) -> bool {
+        self.pressed_keys & 1 << key as u16 != 0
+    }
+
+    /// Takes the lowest key that was just released
+    pub(super) fn take_released(&mut self) -> Option<ChipKey> {
+        match self.released_keys {
+            0 => None,
+            keys => {
+                let key = keys.trailing_zeros() as u8;
+                self.released_keys &= !(1 << key);
+                Some(ChipKey::from(key))
+            }
+        }
     }
 
     /// Is like tick but keeps executing instructions for the given duration
@@ -116,9 +234,7 @@ impl Chip {
         let mut instructions = Vec::new();
         let start = Instant::now();
         while Instant::now() - start < duration {
-            let l_byte = self.ram[self.pc as usize];
-            let r_byte = self.ram[self.pc as usize + 1];
-            let instruction = Instruction::from([l_byte, r_byte]);
+            let instruction = self.fetch();
             instruction.execute(self);
             instructions.push(instruction);
         }
@@ -128,20 +244,41 @@ impl Chip {
     /// Executes the Instruction that its currently stored at position pc and pc+1
     /// and returns it
     pub(crate) fn tick(&mut self) -> Instruction {
-        let l_byte = self.ram[self.pc as usize];
-        let r_byte = self.ram[self.pc as usize + 1];
-        let instruction = Instruction::from([l_byte, r_byte]);
+        let instruction = self.fetch();
         instruction.execute(self);
         instruction
     }
 
+    /// Decodes the Instruction at position pc and pc+1 without executing it
+    pub(crate) fn fetch(&self) -> Instruction {
+        let l_byte = self.ram[self.pc as usize];
+        let r_byte = self.ram[(self.pc as usize + 1) % RAM_SIZE];
+        Instruction::from([l_byte, r_byte])
+    }
+
+    /// Decrements the delay and sound timer, should be called at 60Hz
+    pub(crate) fn dec_timers(&mut self) {
+        if self.dt > 0 {
+            self.dt -= 1;
+        }
+        if self.st > 0 {
+            self.st -= 1;
+        }
+    }
+
     /// Goes to the next Instruction by adding 2 to the Program Counter
     pub(super) fn next(&mut self) {
-        self.pc += 1 * 2;
+        self.pc = self.pc.wrapping_add(1 * 2);
     }
 
-    /// Skips the next Instruction by adding 4 to the Program Counter
+    /// Skips the next Instruction by adding 4 to the Program Counter, or 6 if the next
+    /// Instruction is the 4 byte long `LD4NI`, the addresses wrap around at the end of the RAM
     pub(super) fn skip(&mut self) {
-        self.pc += 2 * 2;
+        let next = self.pc.wrapping_add(2) as usize;
+        if self.ram[next] == 0xf0 && self.ram[(next + 1) % RAM_SIZE] == 0x00 {
+            self.pc = self.pc.wrapping_add(3 * 2);
+        } else {
+            self.pc = self.pc.wrapping_add(2 * 2);
+        }
     }
 }
@@ -0,0 +1,131 @@
+use std::fmt::{Display, Formatter, Result as FmtResult};
+
+/// The behaviours that differ between the CHIP-8 interpreters ROMs were written for
+///
+/// The default keeps how this emulator always behaved, which matches SCHIP except for
+/// `jumping`.
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub struct Quirks {
+    /// `OR`, `AND` and `XOR` reset vF to 0
+    pub vf_reset: bool,
+    /// `LDRRL` and `LDLRR` increment I by the amount of registers
+    pub memory: bool,
+    /// `SHR` and `SHL` shift vx in place instead of shifting vy into vx
+    pub shifting: bool,
+    /// `JP3N` jumps to nnn + vx, where x is the highest nibble of nnn, instead of nnn + v0
+    pub jumping: bool,
+    /// Sprites are cut off at the edges of the display instead of wrapping around
+    pub clipping: bool,
+}
+
+impl Default for Quirks {
+    fn default() -> Self {
+        Quirks {
+            vf_reset: false,
+            memory: false,
+            shifting: true,
+            jumping: false,
+            clipping: true,
+        }
+    }
+}
+
+impl Quirks {
+    /// The original interpreter of the COSMAC VIP
+    pub const CHIP8: Quirks = Quirks {
+        vf_reset: true,
+        memory: true,
+        shifting: false,
+        jumping: false,
+        clipping: true,
+    };
+
+    /// SUPER-CHIP 1.1 of the HP48 calculators
+    pub const SCHIP: Quirks = Quirks {
+        vf_reset: false,
+        memory: false,
+        shifting: true,
+        jumping: true,
+        clipping: true,
+    };
+
+    /// XO-CHIP as implemented by Octo
+    pub const XOCHIP: Quirks = Quirks {
+        vf_reset: false,
+        memory: true,
+        shifting: false,
+        jumping: false,
+        clipping: false,
+    };
+
+    /// The profile with the given name: `chip8`, `schip` or `xochip`
+    pub fn profile(name: &str) -> Option<Quirks> {
+        match name.to_lowercase().replace('-', "").as_str() {
+            "chip8" | "vip" => Some(Quirks::CHIP8),
+            "schip" | "superchip" => Some(Quirks::SCHIP),
+            "xochip" | "octo" => Some(Quirks::XOCHIP),
+            _ => None,
+        }
+    }
+
+    /// Changes the quirks in a list like `memory,-clipping`, a name enables the quirk and a `-`
+    /// in front of it disables it
+    pub fn set(&mut self, list: &str) -> Result<(), String> {
+        for name in list
+            .split(',')
+            .map(str::trim)
+            .filter(|name| !name.is_empty())
+        {
+            let (enabled, name) = match name.strip_prefix('-') {
+                Some(name) => (false, name),
+                None => (true, name.trim_start_matches('+')),
+            };
+            match name {
+                "vf_reset" => self.vf_reset = enabled,
+                "memory" => self.memory = enabled,
+                "shifting" => self.shifting = enabled,
+                "jumping" => self.jumping = enabled,
+                "clipping" => self.clipping = enabled,
+                _ => {
+                    return Err(format!(
+                    "{} is not a quirk, they are vf_reset, memory, shifting, jumping and clipping",
+                    name
+                ))
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// The name of the profile these quirks belong to, if there is one
+    pub fn name(&self) -> Option<&'static str> {
+        match *self {
+            Quirks::CHIP8 => Some("chip8"),
+            Quirks::SCHIP => Some("schip"),
+            Quirks::XOCHIP => Some("xochip"),
+            _ => None,
+        }
+    }
+}
+
+/// Lists the enabled quirks, like `vf_reset memory clipping`
+impl Display for Quirks {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        let quirks = [
+            (self.vf_reset, "vf_reset"),
+            (self.memory, "memory"),
+            (self.shifting, "shifting"),
+            (self.jumping, "jumping"),
+            (self.clipping, "clipping"),
+        ];
+        let enabled: Vec<&str> = quirks
+            .iter()
+            .filter(|(enabled, _)| *enabled)
+            .map(|(_, name)| *name)
+            .collect();
+        match enabled.is_empty() {
+            true => write!(f, "none"),
+            false => write!(f, "{}", enabled.join(" ")),
+        }
+    }
+}
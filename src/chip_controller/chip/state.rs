@@ -0,0 +1,176 @@
+use super::display::ChipDisplay;
+use super::{Chip, RAM_SIZE};
+use std::convert::TryInto;
+use std::fmt::{Display, Formatter, Result as FmtResult};
+use std::io::{Error, ErrorKind};
+
+const MAGIC: &[u8] = b"CHIP8STATE1";
+
+/// Everything a ROM can change, so it can continue from here later
+///
+/// The quirks, recorders and keys belong to the emulator and are kept when a state is loaded.
+#[derive(Clone, PartialEq, Eq)]
+pub(crate) struct State {
+    ram: Vec<u8>,
+    v: [u8; 16],
+    dt: u8,
+    st: u8,
+    i: u16,
+    pc: u16,
+    stack: [u16; 16],
+    sp: u8,
+    display: ChipDisplay,
+    flags: [u8; 16],
+    planes: u8,
+    pattern: [u8; 16],
+    pitch: u8,
+}
+
+impl State {
+    /// The state in a file, the registers come first and the display and RAM last
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend_from_slice(&self.v);
+        bytes.extend_from_slice(&[self.dt, self.st, self.sp, self.planes, self.pitch]);
+        bytes.extend_from_slice(&self.i.to_be_bytes());
+        bytes.extend_from_slice(&self.pc.to_be_bytes());
+        for address in &self.stack {
+            bytes.extend_from_slice(&address.to_be_bytes());
+        }
+        bytes.extend_from_slice(&self.flags);
+        bytes.extend_from_slice(&self.pattern);
+        bytes.extend_from_slice(&[self.display.get_width(), self.display.get_height()]);
+        bytes.extend_from_slice(&self.display.get_pixels());
+        bytes.extend_from_slice(&self.ram);
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<State, Error> {
+        let invalid = |reason: &str| Error::new(ErrorKind::InvalidData, reason.to_owned());
+        let mut rest = bytes
+            .strip_prefix(MAGIC)
+            .ok_or_else(|| invalid("It is not a saved state of this emulator"))?;
+        let mut take = |length: usize| {
+            let (taken, left) = match rest.len() >= length {
+                true => rest.split_at(length),
+                false => return Err(invalid("The saved state is cut off")),
+            };
+            rest = left;
+            Ok(taken)
+        };
+        let v = take(16)?.try_into().unwrap();
+        let [dt, st, sp, planes, pitch]: [u8; 5] = take(5)?.try_into().unwrap();
+        // The chip would index out of its stack with anything else, and `PLN` only selects 4 bits
+        if sp >= 16 {
+            return Err(invalid(
+                "The stack pointer of the saved state is out of range",
+            ));
+        }
+        if planes > 0xf {
+            return Err(invalid("The saved state selects planes that don't exist"));
+        }
+        let mut word = || take(2).map(|bytes| u16::from_be_bytes([bytes[0], bytes[1]]));
+        let (i, pc) = (word()?, word()?);
+        let mut stack = [0; 16];
+        for address in stack.iter_mut() {
+            *address = word()?;
+        }
+        let flags = take(16)?.try_into().unwrap();
+        let pattern = take(16)?.try_into().unwrap();
+        let (width, height) = match take(2)? {
+            [width, height] => (*width, *height),
+            _ => unreachable!(),
+        };
+        if ![(64, 32), (128, 64)].contains(&(width, height)) {
+            return Err(invalid(
+                "The display of the saved state has an unknown size",
+            ));
+        }
+        let pixels = take(width as usize * height as usize)?.to_vec();
+        let ram = take(RAM_SIZE)?.to_vec();
+        Ok(State {
+            ram,
+            v,
+            dt,
+            st,
+            i,
+            pc,
+            stack,
+            sp,
+            display: ChipDisplay::with_pixels(width, height, pixels),
+            flags,
+            planes,
+            pattern,
+            pitch,
+        })
+    }
+}
+
+/// The registers, timers and stack as text, the RAM and display are left out
+impl Display for State {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        writeln!(
+            f,
+            "PC {:04X}  I {:04X}  SP {}  DT {:02X}  ST {:02X}",
+            self.pc, self.i, self.sp, self.dt, self.st
+        )?;
+        for (first, registers) in self.v.chunks(8).enumerate() {
+            let bytes: Vec<String> = registers.iter().map(|v| format!("{:02X}", v)).collect();
+            writeln!(
+                f,
+                "V{:X}-V{:X}  {}",
+                first * 8,
+                first * 8 + 7,
+                bytes.join(" ")
+            )?;
+        }
+        let stack: Vec<String> = self.stack[1..=(self.sp as usize).min(15)]
+            .iter()
+            .map(|address| format!("{:04X}", address))
+            .collect();
+        writeln!(f, "Stack  {}", stack.join(" "))?;
+        write!(
+            f,
+            "Display {}x{}  Planes {}",
+            self.display.get_width(),
+            self.display.get_height(),
+            self.planes
+        )
+    }
+}
+
+impl Chip {
+    pub(crate) fn state(&self) -> State {
+        State {
+            ram: self.ram.to_vec(),
+            v: self.v,
+            dt: self.dt,
+            st: self.st,
+            i: self.i,
+            pc: self.pc,
+            stack: self.stack,
+            sp: self.sp,
+            display: self.display.clone(),
+            flags: self.flags,
+            planes: self.planes,
+            pattern: self.pattern,
+            pitch: self.pitch,
+        }
+    }
+
+    pub(crate) fn restore(&mut self, state: &State) {
+        self.ram.copy_from_slice(&state.ram);
+        self.v = state.v;
+        self.dt = state.dt;
+        self.st = state.st;
+        self.i = state.i;
+        self.pc = state.pc;
+        self.stack = state.stack;
+        self.sp = state.sp;
+        self.display = state.display.clone();
+        self.flags = state.flags;
+        self.planes = state.planes;
+        self.pattern = state.pattern;
+        self.pitch = state.pitch;
+    }
+}
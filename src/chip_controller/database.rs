@@ -0,0 +1,201 @@
+use super::Quirks;
+use serde_json::{Map, Value};
+use std::fs;
+use std::io::{Error, ErrorKind};
+use std::path::{Path, PathBuf};
+
+/// The files of the database, like in the `database` directory of the community CHIP-8 database
+const FILES: [&str; 3] = ["programs.json", "sha1-hashes.json", "platforms.json"];
+
+/// What the database knows about a ROM
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Program {
+    pub title: String,
+    pub authors: Vec<String>,
+    /// The name of the platform it was written for, like `SUPER-CHIP 1.1`
+    pub platform: Option<String>,
+    pub quirks: Option<Quirks>,
+    /// Instructions per frame
+    pub tickrate: Option<usize>,
+    /// Buttons like `up` or `a` and the CHIP-8 keys they press
+    pub keys: Vec<(String, u8)>,
+    /// The colours of the pixels like `#000000`, the background comes first
+    pub colors: Vec<String>,
+}
+
+/// ROMs by their SHA-1 in the format of the community CHIP-8 database, which lists the
+/// programs with their ROMs, the hashes of the ROMs and the platforms they were written for
+///
+/// A small part of it is bundled and `Database::install` replaces that with the whole database.
+#[derive(Debug)]
+pub(crate) struct Database {
+    programs: Vec<Value>,
+    /// The index into `programs` for each SHA-1
+    hashes: Map<String, Value>,
+    platforms: Vec<Value>,
+}
+
+impl Database {
+    pub fn bundled() -> Database {
+        Database::parse([
+            include_str!("database/programs.json"),
+            include_str!("database/sha1-hashes.json"),
+            include_str!("database/platforms.json"),
+        ])
+        .unwrap()
+    }
+
+    /// Where an installed database is kept, `$XDG_DATA_HOME/chip_8/database`
+    pub fn path() -> Option<PathBuf> {
+        let directory = match std::env::var_os("XDG_DATA_HOME") {
+            Some(directory) if !directory.is_empty() => PathBuf::from(directory),
+            _ => PathBuf::from(std::env::var_os("HOME")?).join(".local/share"),
+        };
+        Some(directory.join("chip_8").join("database"))
+    }
+
+    /// The installed database, or the bundled one if none was installed
+    pub fn installed() -> Result<Database, Error> {
+        match Database::path().filter(|path| path.exists()) {
+            Some(path) => Database::load(path),
+            None => Ok(Database::bundled()),
+        }
+    }
+
+    /// Reads the files of a database from a directory, which can be a checkout of the community
+    /// database with the files in its `database` directory
+    pub fn load<P: AsRef<Path>>(directory: P) -> Result<Database, Error> {
+        let mut directory = directory.as_ref().to_owned();
+        if !directory.join(FILES[0]).exists() && directory.join("database").is_dir() {
+            directory.push("database");
+        }
+        let mut texts = Vec::new();
+        for file in &FILES {
+            let path = directory.join(file);
+            let text = fs::read_to_string(&path)
+                .map_err(|e| Error::new(e.kind(), format!("{}: {}", path.display(), e)))?;
+            texts.push(text);
+        }
+        Database::parse([&texts[0], &texts[1], &texts[2]])
+    }
+
+    /// Checks the database in a directory and copies it to where `installed` finds it, it
+    /// returns where that is
+    pub fn install<P: AsRef<Path>>(directory: P) -> Result<PathBuf, Error> {
+        let directory = directory.as_ref();
+        let database = Database::load(directory)?;
+        let target = Database::path()
+            .ok_or_else(|| Error::new(ErrorKind::NotFound, "There is no home directory"))?;
+        fs::create_dir_all(&target)?;
+        let json = [
+            Value::Array(database.programs),
+            Value::Object(database.hashes),
+            Value::Array(database.platforms),
+        ];
+        for (file, json) in FILES.iter().zip(json.iter()) {
+            fs::write(target.join(file), json.to_string())?;
+        }
+        Ok(target)
+    }
+
+    /// Reads the programs, hashes and platforms
+    pub fn parse(texts: [&str; 3]) -> Result<Database, Error> {
+        let json = |index: usize| {
+            serde_json::from_str::<Value>(texts[index]).map_err(|e| invalid(FILES[index], e))
+        };
+        let wrong = |index: usize| invalid(FILES[index], "it has the wrong format");
+        let programs = match json(0)? {
+            Value::Array(programs) => programs,
+            _ => return Err(wrong(0)),
+        };
+        let hashes = match json(1)? {
+            Value::Object(hashes) => hashes,
+            _ => return Err(wrong(1)),
+        };
+        let platforms = match json(2)? {
+            Value::Array(platforms) => platforms,
+            _ => return Err(wrong(2)),
+        };
+        Ok(Database {
+            programs,
+            hashes,
+            platforms,
+        })
+    }
+
+    /// The amount of known ROMs
+    pub fn len(&self) -> usize {
+        self.hashes.len()
+    }
+
+    /// What is known about the ROM with the given SHA-1, the quirks and tickrate of its platform
+    /// are used unless the ROM overrides them
+    pub fn program(&self, sha1: &str) -> Option<Program> {
+        let index = self.hashes.get(sha1)?.as_u64()? as usize;
+        let program = self.programs.get(index)?;
+        let rom = &program["roms"][sha1];
+        let platform_id = rom["platforms"][0].as_str();
+        let platform = platform_id.and_then(|id| {
+            self.platforms
+                .iter()
+                .find(|platform| platform["id"].as_str() == Some(id))
+        });
+        let mut flags = platform.and_then(|platform| platform["quirks"].as_object().cloned());
+        // The ROM can change some quirks of its platform
+        if let Some(changed) = platform_id.and_then(|id| rom["quirkyPlatforms"][id].as_object()) {
+            flags.get_or_insert_with(Map::new).extend(changed.clone());
+        }
+        let strings = |value: &Value| -> Vec<String> {
+            value
+                .as_array()
+                .map(|values| {
+                    values
+                        .iter()
+                        .filter_map(|value| value.as_str().map(str::to_owned))
+                        .collect()
+                })
+                .unwrap_or_default()
+        };
+        let keys = rom["keys"]
+            .as_object()
+            .map(|keys| {
+                keys.iter()
+                    .filter_map(|(button, key)| Some((button.to_owned(), key.as_u64()? as u8)))
+                    .filter(|(_, key)| *key < 16)
+                    .collect()
+            })
+            .unwrap_or_default();
+        Some(Program {
+            title: program["title"].as_str().unwrap_or(sha1).to_owned(),
+            authors: strings(&program["authors"]),
+            platform: platform
+                .and_then(|platform| platform["name"].as_str())
+                .or(platform_id)
+                .map(str::to_owned),
+            quirks: flags.as_ref().map(quirks),
+            tickrate: rom["tickrate"]
+                .as_u64()
+                .or_else(|| platform?["defaultTickrate"].as_u64())
+                .map(|tickrate| tickrate as usize),
+            keys,
+            colors: strings(&rom["colors"]["pixels"]),
+        })
+    }
+}
+
+/// The quirks for the flags of the database, `vblank` isn't emulated and incrementing I by x
+/// instead of x + 1 is the closest to incrementing it
+fn quirks(flags: &Map<String, Value>) -> Quirks {
+    let flag = |name: &str| flags.get(name).and_then(Value::as_bool).unwrap_or(false);
+    Quirks {
+        vf_reset: flag("logic"),
+        memory: !flag("memoryLeaveIUnchanged"),
+        shifting: flag("shift"),
+        jumping: flag("jump"),
+        clipping: !flag("wrap"),
+    }
+}
+
+fn invalid(file: &str, e: impl std::fmt::Display) -> Error {
+    Error::new(ErrorKind::InvalidData, format!("{}: {}", file, e))
+}
@@ -1,37 +1,157 @@
+mod cheats;
 #[allow(dead_code)]
 mod chip;
+mod database;
+mod hash;
+mod patch;
+mod schedule;
 use super::Byte;
+pub(crate) use cheats::{Cheat, Cheats, Condition, Effect, RamSearch};
+pub(crate) use chip::debug::analyze::{Analysis, Platform};
+use chip::debug::profile::Profiler;
+pub(crate) use chip::debug::profile::Weight;
+pub(crate) use chip::debug::rom::{DataStyle, Rom, Syntax};
+pub(crate) use chip::debug::sprite::{SpriteFinder, SpriteSheet};
+pub(crate) use chip::debug::symbols::SymbolMap;
+pub(crate) use chip::debug::Crash;
+use chip::debug::{coverage::Coverage, dap::DapServer, fault, gdb::GdbServer, trace::Tracer};
 use chip::Chip;
 pub use chip::ChipKey;
+pub(crate) use chip::Instruction;
+pub(crate) use chip::Quirks;
+pub(crate) use chip::State;
+pub(crate) use chip::FONT_END;
+pub(crate) use database::{Database, Program};
+pub(crate) use hash::{crc32, sha1};
+pub(crate) use patch::Patch;
+pub(crate) use schedule::Schedule;
+use std::collections::BTreeSet;
+use std::io::Error;
 
 pub struct ChipController {
     chip: Chip,
+    dap: Option<DapServer>,
+    trace: Option<Tracer>,
+    profiler: Option<Profiler>,
+    cheats: Cheats,
+    patches: Vec<Patch>,
+    /// The loaded ROM with the patches applied
+    rom: Vec<Byte>,
+    database: Option<Database>,
+    /// What the database knows about the loaded ROM
+    program: Option<Program>,
 }
 
 impl ChipController {
     pub fn new() -> Self {
-        ChipController { chip: Chip::new() }
+        ChipController {
+            chip: Chip::new(),
+            dap: None,
+            trace: None,
+            profiler: None,
+            cheats: Cheats::new(),
+            patches: Vec::new(),
+            rom: Vec::new(),
+            database: None,
+            program: None,
+        }
     }
 
-    pub fn tick(&mut self, instructions: Option<usize>) {
-        match instructions {
-            Some(is) => {
-                for _ in 0..is {
+    /// Executes the given amount of instructions or one, it stops before an instruction that
+    /// would crash the chip and leaves PC at it
+    pub fn tick(&mut self, instructions: Option<usize>) -> Result<(), Crash> {
+        for _ in 0..instructions.unwrap_or(1) {
+            match &mut self.dap {
+                Some(dap) => dap.tick(&mut self.chip),
+                None => {
+                    let instruction = self.chip.fetch();
+                    if let Some(fault) = fault(&self.chip, &instruction) {
+                        let pc = self.chip.pc;
+                        return Err(Crash { fault, pc });
+                    }
+                    if let Some(trace) = &mut self.trace {
+                        trace.trace(&self.chip, &instruction);
+                    }
+                    if let Some(profiler) = &mut self.profiler {
+                        profiler.record(&instruction);
+                    }
                     self.chip.tick();
                 }
             }
-            None => {
-                self.chip.tick();
+            if !self.cheats.is_empty() {
+                self.cheats.apply(&mut self.chip.ram);
+            }
+        }
+        Ok(())
+    }
+
+    /// Runs like `tick`, but stops as soon as PC reaches one of the breakpoints and returns
+    /// whether it did
+    pub fn tick_until(
+        &mut self,
+        instructions: usize,
+        breakpoints: &BTreeSet<u16>,
+    ) -> Result<bool, Crash> {
+        for _ in 0..instructions {
+            self.tick(None)?;
+            if breakpoints.contains(&self.chip.pc) {
+                return Ok(true);
             }
         }
+        Ok(false)
     }
 
     pub fn set_pressed_key(&mut self, key: Option<ChipKey>) {
         self.chip.set_key(key);
     }
 
-    pub fn set_rom(&mut self, file: Vec<Byte>) {
-        self.chip.read_rom_bytes(file);
+    /// Sets all keys that are held down, one bit for each
+    pub fn set_pressed_keys(&mut self, keys: u16) {
+        self.chip.set_keys(keys);
+    }
+
+    /// Loads a ROM after applying the patches to it, nothing is loaded if one of them fails or
+    /// the patched ROM doesn't fit into the RAM after 0x200
+    ///
+    /// The quirks are set to the ones the database knows the ROM needs, it is looked up before
+    /// patching because patches usually keep what a ROM needs.
+    pub fn set_rom(&mut self, file: Vec<Byte>) -> Result<(), Error> {
+        let program = match &self.database {
+            Some(database) => database.program(&sha1(&file)),
+            None => None,
+        };
+        let mut rom = file;
+        for patch in &self.patches {
+            rom = patch.apply(&rom)?;
+        }
+        Chip::check_rom(&rom)?;
+        if let Some(quirks) = program.as_ref().and_then(|program| program.quirks) {
+            self.chip.quirks = quirks;
+        }
+        self.program = program;
+        self.rom = rom;
+        self.reset();
+        Ok(())
+    }
+
+    /// Lets `set_rom` configure the ROMs that are in the database
+    pub fn set_database(&mut self, database: Database) {
+        self.database = Some(database);
+    }
+
+    /// What the database knows about the loaded ROM, like the speed and keys it needs
+    pub fn program(&self) -> Option<&Program> {
+        self.program.as_ref()
+    }
+
+    /// Adds a patch which is applied by every following `set_rom`, in the order they were added
+    pub fn add_patch(&mut self, patch: Patch) {
+        self.patches.push(patch);
+    }
+
+    /// The loaded ROM as it was after patching
+    pub fn rom(&self) -> &[Byte] {
+        &self.rom
     }
 
     pub fn delay_timer(&mut self) -> u8 {
@@ -43,22 +163,236 @@ impl ChipController {
     }
 
     pub fn dec_delay_timer(&mut self) {
+        if self.paused() {
+            return;
+        }
         if self.chip.dt > 0 {
             self.chip.dt -= 1;
         }
     }
 
     pub fn dec_sound_timer(&mut self) {
+        if self.paused() {
+            return;
+        }
         if self.chip.st > 0 {
             self.chip.st -= 1;
         }
     }
 
+    /// Decrements both timers and closes the profiler's frame, should be called at 60Hz
+    pub fn end_frame(&mut self) {
+        self.dec_delay_timer();
+        self.dec_sound_timer();
+        if let Some(profiler) = &mut self.profiler {
+            profiler.frame();
+        }
+    }
+
+    /// Runs frames of `instructions` each without a terminal, the keys of the schedule are held
+    /// in the frames they are scheduled for
+    pub fn run_frames(
+        &mut self,
+        frames: usize,
+        instructions: usize,
+        schedule: &Schedule,
+    ) -> Result<(), Crash> {
+        for frame in 0..frames {
+            self.set_pressed_keys(schedule.keys(frame));
+            self.tick(Some(instructions))?;
+            self.end_frame();
+        }
+        Ok(())
+    }
+
+    pub fn set_quirks(&mut self, quirks: Quirks) {
+        self.chip.quirks = quirks;
+    }
+
+    pub fn set_seed(&mut self, seed: u64) {
+        self.chip.set_seed(seed);
+    }
+
+    pub fn quirks(&self) -> Quirks {
+        self.chip.quirks
+    }
+
+    /// The whole RAM of the chip
+    pub fn ram(&self) -> &[u8] {
+        &self.chip.ram
+    }
+
+    /// Overwrites a byte of RAM
+    pub fn poke(&mut self, address: u16, byte: u8) {
+        self.chip.ram[address as usize] = byte;
+    }
+
+    /// The instruction that is executed next
+    pub fn next_instruction(&self) -> Instruction {
+        self.chip.fetch()
+    }
+
+    /// The instruction in RAM at an address
+    pub fn instruction_at(&self, address: u16) -> Instruction {
+        let ram = &self.chip.ram;
+        Instruction::from([ram[address as usize], ram[address.wrapping_add(1) as usize]])
+    }
+
+    pub fn pc(&self) -> u16 {
+        self.chip.pc
+    }
+
+    pub fn index(&self) -> u16 {
+        self.chip.i
+    }
+
+    /// The addresses of the `CALL`s on the stack, the outermost first
+    pub fn call_stack(&self) -> &[u16] {
+        &self.chip.stack[1..=(self.chip.sp as usize).min(15)]
+    }
+
+    /// Starts the loaded ROM over, the quirks and cheats stay as they are
     pub fn reset(&mut self) {
         self.chip.reset();
+        if !self.rom.is_empty() {
+            self.chip.read_rom_bytes(self.rom.clone());
+        }
+        self.cheats.reload();
+        self.cheats.apply(&mut self.chip.ram);
+    }
+
+    /// Everything the ROM changed, to continue from there with `restore`
+    pub(crate) fn state(&self) -> State {
+        self.chip.state()
+    }
+
+    pub(crate) fn restore(&mut self, state: &State) {
+        self.chip.restore(state);
+        self.cheats.apply(&mut self.chip.ram);
+    }
+
+    /// Replaces the cheats, their patches are applied right away and whenever a ROM is loaded
+    pub fn set_cheats(&mut self, cheats: Cheats) {
+        self.cheats = cheats;
+        self.cheats.apply(&mut self.chip.ram);
+    }
+
+    pub fn cheats(&self) -> &Cheats {
+        &self.cheats
+    }
+
+    pub fn add_cheat(&mut self, cheat: Cheat) {
+        self.cheats.push(cheat);
+        self.cheats.apply(&mut self.chip.ram);
+    }
+
+    /// Enables or disables a cheat, a disabled patch restores the bytes it replaced
+    pub fn toggle_cheat(&mut self, index: usize) -> bool {
+        let toggled = self.cheats.toggle(index);
+        self.cheats.apply(&mut self.chip.ram);
+        toggled
     }
 
     pub fn get_display(&self) -> Vec<u8> {
         self.chip.display.get_pixels()
     }
+
+    /// The display as text with a line for each row of pixels
+    pub fn screen(&self) -> String {
+        self.chip.display.to_string()
+    }
+
+    /// The display as a PBM image
+    pub fn image(&self) -> Vec<u8> {
+        self.chip.display.image().into_bytes()
+    }
+
+    /// Width and height of the display, which changes when a ROM switches to hires mode
+    pub fn get_dimension(&self) -> (u8, u8) {
+        (
+            self.chip.display.get_width(),
+            self.chip.display.get_height(),
+        )
+    }
+
+    /// Whether a debugger holds the chip, in that case `tick` does not execute anything
+    pub fn paused(&self) -> bool {
+        self.dap.as_ref().is_some_and(DapServer::paused)
+    }
+
+    /// Whether the attached debugger ended the session
+    pub fn finished(&self) -> bool {
+        self.dap.as_ref().is_some_and(DapServer::terminated)
+    }
+
+    /// Lets an editor debug the chip through the Debug Adapter Protocol on stdin and stdout,
+    /// the ROM is loaded by the editor's launch request
+    pub fn attach_dap(&mut self) {
+        self.dap = Some(DapServer::new());
+    }
+
+    /// Like `attach_dap`, but the requests are read from `input` and answered on `output`
+    pub fn connect_dap<R, W>(&mut self, input: R, output: W)
+    where
+        R: std::io::BufRead + Send + 'static,
+        W: std::io::Write + 'static,
+    {
+        self.dap = Some(DapServer::connect(input, output));
+    }
+
+    /// Starts recording which bytes of RAM are executed, read and written
+    pub fn enable_coverage(&mut self) {
+        self.chip.coverage = Some(Coverage::new());
+    }
+
+    /// The annotated listing of `rom` with the recorded coverage, if it was enabled
+    pub fn coverage_report(&self, rom: &Rom, symbols: &SymbolMap) -> Option<String> {
+        self.chip
+            .coverage
+            .as_ref()
+            .map(|coverage| coverage.report(rom, symbols))
+    }
+
+    /// Starts recording the sprites that are drawn in addition to the ones `finder` already
+    /// knows, like the ones found by `SpriteFinder::scan`
+    pub fn enable_sprites(&mut self, finder: SpriteFinder) {
+        self.chip.sprites = Some(finder);
+    }
+
+    /// The recorded sprites as they are currently stored in RAM, if recording was enabled
+    pub fn sprite_sheet(&self) -> Option<SpriteSheet> {
+        let ram = &self.chip.ram;
+        self.chip
+            .sprites
+            .as_ref()
+            .map(|sprites| sprites.sheet(|address| ram[address as usize]))
+    }
+
+    /// Starts attributing executed instructions to the routines named in `symbols`
+    pub fn enable_profiler(&mut self, symbols: SymbolMap) {
+        self.profiler = Some(Profiler::new(symbols));
+    }
+
+    /// The flat profile, call tree and frame statistics, if the profiler was enabled
+    pub fn profile_report(&self) -> Option<String> {
+        self.profiler.as_ref().map(Profiler::report)
+    }
+
+    /// The profile as folded stacks for flamegraph tools, if the profiler was enabled
+    pub fn folded_stacks(&self, weight: Weight) -> Option<String> {
+        self.profiler
+            .as_ref()
+            .map(|profiler| profiler.folded(weight))
+    }
+
+    /// Logs every executed instruction to `output`, addresses are named after the symbols
+    pub fn trace_to(&mut self, output: Box<dyn std::io::Write>, symbols: SymbolMap) {
+        self.trace = Some(Tracer::new(output, symbols));
+    }
+
+    /// Waits for a GDB client on the given localhost port and lets it drive the chip, `freq`
+    /// is the amount of instructions per second while the chip is continued
+    pub fn serve_gdb(&mut self, port: u16, freq: usize, symbols: SymbolMap) -> std::io::Result<()> {
+        GdbServer::bind(port, freq, symbols)?.serve(&mut self.chip)
+    }
 }
@@ -0,0 +1,286 @@
+use super::hash::crc32;
+use std::fs;
+use std::io::{Error, ErrorKind};
+use std::path::Path;
+
+const IPS_HEADER: &[u8] = b"PATCH";
+const IPS_FOOTER: &[u8] = b"EOF";
+const BPS_HEADER: &[u8] = b"BPS1";
+/// The most bytes an IPS record can hold
+const IPS_RECORD: usize = 0xffff;
+
+/// An IPS or BPS patch which is applied to a ROM before it is loaded
+#[derive(Debug, Clone)]
+pub(crate) struct Patch {
+    /// Usually the file the patch was read from, it is named in errors
+    name: String,
+    bytes: Vec<u8>,
+}
+
+impl Patch {
+    pub fn new(name: &str, bytes: Vec<u8>) -> Patch {
+        Patch {
+            name: name.to_owned(),
+            bytes,
+        }
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Patch, Error> {
+        let bytes = fs::read(&path)?;
+        Ok(Patch::new(&path.as_ref().display().to_string(), bytes))
+    }
+
+    /// The patched copy of `rom`, BPS patches are checked against the checksums they contain
+    pub fn apply(&self, rom: &[u8]) -> Result<Vec<u8>, Error> {
+        let result = if self.bytes.starts_with(IPS_HEADER) {
+            apply_ips(rom, &self.bytes)
+        } else if self.bytes.starts_with(BPS_HEADER) {
+            apply_bps(rom, &self.bytes)
+        } else {
+            Err("it is neither an IPS nor a BPS patch".to_owned())
+        };
+        result.map_err(|reason| {
+            Error::new(
+                ErrorKind::InvalidData,
+                format!("Can't apply {}: {}", self.name, reason),
+            )
+        })
+    }
+
+    /// An IPS patch which turns `original` into `modified`
+    pub fn ips(original: &[u8], modified: &[u8]) -> Vec<u8> {
+        let mut patch = IPS_HEADER.to_vec();
+        let mut offset = 0;
+        while offset < modified.len() {
+            if original.get(offset) == Some(&modified[offset]) {
+                offset += 1;
+                continue;
+            }
+            let start = offset;
+            while offset < modified.len()
+                && offset - start < IPS_RECORD
+                && original.get(offset) != Some(&modified[offset])
+            {
+                offset += 1;
+            }
+            patch.extend_from_slice(&(start as u32).to_be_bytes()[1..]);
+            patch.extend_from_slice(&((offset - start) as u16).to_be_bytes());
+            patch.extend_from_slice(&modified[start..offset]);
+        }
+        patch.extend_from_slice(IPS_FOOTER);
+        // The common extension to shorten the ROM
+        if modified.len() < original.len() {
+            patch.extend_from_slice(&(modified.len() as u32).to_be_bytes()[1..]);
+        }
+        patch
+    }
+
+    /// A BPS patch which turns `original` into `modified`, it copies equal bytes from the
+    /// original and stores the others
+    pub fn bps(original: &[u8], modified: &[u8]) -> Vec<u8> {
+        let mut patch = BPS_HEADER.to_vec();
+        write_number(&mut patch, original.len() as u64);
+        write_number(&mut patch, modified.len() as u64);
+        write_number(&mut patch, 0);
+        let mut offset = 0;
+        while offset < modified.len() {
+            let same = |offset: usize| original.get(offset) == Some(&modified[offset]);
+            let read = same(offset);
+            let start = offset;
+            while offset < modified.len() && same(offset) == read {
+                offset += 1;
+            }
+            let action = if read { 0 } else { 1 };
+            write_number(&mut patch, ((offset - start - 1) as u64) << 2 | action);
+            if !read {
+                patch.extend_from_slice(&modified[start..offset]);
+            }
+        }
+        patch.extend_from_slice(&crc32(original).to_le_bytes());
+        patch.extend_from_slice(&crc32(modified).to_le_bytes());
+        let checksum = crc32(&patch);
+        patch.extend_from_slice(&checksum.to_le_bytes());
+        patch
+    }
+}
+
+fn apply_ips(rom: &[u8], patch: &[u8]) -> Result<Vec<u8>, String> {
+    let truncated = || "the patch ends in the middle of a record".to_owned();
+    let mut target = rom.to_vec();
+    let mut position = IPS_HEADER.len();
+    let mut take = |length: usize| {
+        let bytes = patch
+            .get(position..position + length)
+            .ok_or_else(truncated)?;
+        position += length;
+        Ok::<&[u8], String>(bytes)
+    };
+    loop {
+        let offset = take(3)?;
+        if offset == IPS_FOOTER {
+            break;
+        }
+        let offset = u32::from_be_bytes([0, offset[0], offset[1], offset[2]]) as usize;
+        let size = u16::from_be_bytes([take(1)?[0], take(1)?[0]]) as usize;
+        let bytes = match size {
+            // Run length encoded records repeat one byte
+            0 => {
+                let run = u16::from_be_bytes([take(1)?[0], take(1)?[0]]) as usize;
+                vec![take(1)?[0]; run]
+            }
+            _ => take(size)?.to_vec(),
+        };
+        if target.len() < offset + bytes.len() {
+            target.resize(offset + bytes.len(), 0);
+        }
+        target[offset..offset + bytes.len()].copy_from_slice(&bytes);
+    }
+    if let Ok(length) = take(3) {
+        target.truncate(u32::from_be_bytes([0, length[0], length[1], length[2]]) as usize);
+    }
+    Ok(target)
+}
+
+fn apply_bps(source: &[u8], patch: &[u8]) -> Result<Vec<u8>, String> {
+    if patch.len() < BPS_HEADER.len() + 12 {
+        return Err("the patch is too short".to_owned());
+    }
+    let footer = patch.len() - 12;
+    let checksum =
+        |at: usize| u32::from_le_bytes([patch[at], patch[at + 1], patch[at + 2], patch[at + 3]]);
+    let (source_crc, target_crc, patch_crc) =
+        (checksum(footer), checksum(footer + 4), checksum(footer + 8));
+    let actual = crc32(&patch[..footer + 8]);
+    if actual != patch_crc {
+        return Err(format!(
+            "the patch is damaged, its checksum is {:08X} instead of {:08X}",
+            actual, patch_crc
+        ));
+    }
+
+    let mut position = BPS_HEADER.len();
+    let mut number = || read_number(patch, &mut position, footer);
+    let source_size = number()? as usize;
+    let target_size = number()? as usize;
+    let metadata = number()? as usize;
+    if source_size != source.len() {
+        return Err(format!(
+            "it is meant for a ROM of {} bytes, but this one has {}",
+            source_size,
+            source.len()
+        ));
+    }
+    let actual = crc32(source);
+    if actual != source_crc {
+        return Err(format!(
+            "it is meant for a different ROM, the checksum is {:08X} instead of {:08X}",
+            actual, source_crc
+        ));
+    }
+
+    let out_of_bounds = || "an action reaches outside of the ROM".to_owned();
+    let mut position = position + metadata;
+    // The size is only trusted as far as a ROM can be large
+    let mut target = Vec::with_capacity(target_size.min(0x10000));
+    let (mut source_offset, mut target_offset) = (0i64, 0i64);
+    while position < footer {
+        let command = read_number(patch, &mut position, footer)?;
+        let length = (command >> 2) as usize + 1;
+        if target.len() + length > target_size {
+            return Err(format!(
+                "an action writes past the {} bytes of the patched ROM",
+                target_size
+            ));
+        }
+        match command & 3 {
+            // SourceRead
+            0 => {
+                let start = target.len();
+                target.extend_from_slice(
+                    source
+                        .get(start..start + length)
+                        .ok_or_else(out_of_bounds)?,
+                );
+            }
+            // TargetRead
+            1 => {
+                target.extend_from_slice(
+                    patch
+                        .get(position..position + length)
+                        .filter(|_| position + length <= footer)
+                        .ok_or_else(out_of_bounds)?,
+                );
+                position += length;
+            }
+            // SourceCopy and TargetCopy move their offset first
+            action => {
+                let data = read_number(patch, &mut position, footer)?;
+                let relative = match data & 1 {
+                    1 => -((data >> 1) as i64),
+                    _ => (data >> 1) as i64,
+                };
+                let offset = match action {
+                    2 => &mut source_offset,
+                    _ => &mut target_offset,
+                };
+                *offset += relative;
+                for _ in 0..length {
+                    let byte = match action {
+                        2 => source.get(*offset as usize),
+                        // The copied bytes may be ones that were just written
+                        _ => target.get(*offset as usize),
+                    };
+                    let byte = *byte.filter(|_| *offset >= 0).ok_or_else(out_of_bounds)?;
+                    target.push(byte);
+                    *offset += 1;
+                }
+            }
+        }
+    }
+    if target.len() != target_size {
+        return Err(format!(
+            "the patched ROM has {} bytes instead of {}",
+            target.len(),
+            target_size
+        ));
+    }
+    let actual = crc32(&target);
+    if actual != target_crc {
+        return Err(format!(
+            "the patched ROM has the checksum {:08X} instead of {:08X}",
+            actual, target_crc
+        ));
+    }
+    Ok(target)
+}
+
+/// Reads a variable length number of BPS, which keeps adding 7 bits until the highest bit is set
+fn read_number(patch: &[u8], position: &mut usize, end: usize) -> Result<u64, String> {
+    let (mut number, mut shift) = (0u64, 1u64);
+    loop {
+        let byte = *patch
+            .get(*position)
+            .filter(|_| *position < end && shift < 1 << 56)
+            .ok_or_else(|| "a number in the patch is cut off".to_owned())?;
+        *position += 1;
+        number += (byte & 0x7f) as u64 * shift;
+        if byte & 0x80 != 0 {
+            return Ok(number);
+        }
+        shift <<= 7;
+        number += shift;
+    }
+}
+
+fn write_number(patch: &mut Vec<u8>, mut number: u64) {
+    loop {
+        let bits = (number & 0x7f) as u8;
+        number >>= 7;
+        if number == 0 {
+            patch.push(0x80 | bits);
+            return;
+        }
+        patch.push(bits);
+        number -= 1;
+    }
+}
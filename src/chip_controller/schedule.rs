@@ -0,0 +1,61 @@
+use std::fs;
+use std::io::{Error, ErrorKind};
+use std::path::Path;
+
+/// Which keys are held in which frames when a ROM runs without a terminal
+///
+/// Every press is written like `60-90:5` for holding key 5 from frame 60 to frame 90, a single
+/// frame like `120:4A` holds keys 4 and A for one frame. Files have a press on each line, empty
+/// lines and lines starting with `#` are ignored.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub(crate) struct Schedule {
+    /// The first and last frame and the keys with a bit for each
+    presses: Vec<(usize, usize, u16)>,
+}
+
+impl Schedule {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Schedule, Error> {
+        let mut schedule = Schedule::default();
+        for (number, line) in fs::read_to_string(path)?.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            schedule.add(line).map_err(|_| {
+                Error::new(
+                    ErrorKind::InvalidData,
+                    format!("Invalid press in line {}: {}", number + 1, line),
+                )
+            })?;
+        }
+        Ok(schedule)
+    }
+
+    /// Adds a press like `60-90:5`
+    pub fn add(&mut self, press: &str) -> Result<(), String> {
+        let invalid = || format!("Invalid press {}, it has to be like 60-90:5", press);
+        let (frames, keys) = press.split_once(':').ok_or_else(invalid)?;
+        let frame = |text: &str| text.trim().parse::<usize>().map_err(|_| invalid());
+        let (first, last) = match frames.split_once('-') {
+            Some((first, last)) => (frame(first)?, frame(last)?),
+            None => (frame(frames)?, frame(frames)?),
+        };
+        let mut bits = 0;
+        for key in keys.trim().chars() {
+            bits |= 1 << key.to_digit(16).ok_or_else(invalid)?;
+        }
+        if first > last || bits == 0 {
+            return Err(invalid());
+        }
+        self.presses.push((first, last, bits));
+        Ok(())
+    }
+
+    /// The keys held in a frame with a bit for each
+    pub fn keys(&self, frame: usize) -> u16 {
+        self.presses
+            .iter()
+            .filter(|(first, last, _)| (*first..=*last).contains(&frame))
+            .fold(0, |keys, (_, _, bits)| keys | bits)
+    }
+}
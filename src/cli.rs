@@ -0,0 +1,185 @@
+use std::fmt::{Display, Formatter, Result as FmtResult};
+use std::str::FromStr;
+
+/// An option of a command, `value` names its argument if it takes one
+pub(crate) struct Opt {
+    pub name: &'static str,
+    pub value: Option<&'static str>,
+    pub help: &'static str,
+}
+
+/// A subcommand with the arguments and options it accepts
+pub(crate) struct Command {
+    pub name: &'static str,
+    /// The positional arguments like `<ROM> [FREQ]`, the ones in brackets are optional
+    pub args: &'static [&'static str],
+    pub about: &'static str,
+    /// The options in groups, so commands can share some of them
+    pub options: &'static [&'static [Opt]],
+    pub run: fn(&Args) -> Result<(), Error>,
+}
+
+/// Why a command failed, it decides the exit code
+#[derive(Debug)]
+pub(crate) enum Error {
+    /// `--help` was given, which isn't a failure
+    Help(String),
+    /// The arguments are wrong, like the exit code 2 of most tools
+    Usage(String),
+    /// Reading or running the ROM failed
+    Failed(String),
+    /// The ROM reached an instruction that crashes the chip
+    Crashed(String),
+}
+
+impl Error {
+    pub fn code(&self) -> i32 {
+        match self {
+            Error::Help(_) => 0,
+            Error::Usage(_) => 2,
+            Error::Failed(_) => 1,
+            Error::Crashed(_) => 3,
+        }
+    }
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        match self {
+            Error::Help(help) => write!(f, "{}", help),
+            Error::Usage(message) | Error::Failed(message) | Error::Crashed(message) => {
+                write!(f, "{}", message)
+            }
+        }
+    }
+}
+
+/// The arguments of a command after they were checked against its options
+pub(crate) struct Args {
+    command: &'static Command,
+    positional: Vec<String>,
+    options: Vec<(&'static str, Option<String>)>,
+}
+
+impl Command {
+    pub fn parse(&'static self, args: &[String]) -> Result<Args, Error> {
+        let mut parsed = Args {
+            command: self,
+            positional: Vec::new(),
+            options: Vec::new(),
+        };
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            if arg == "--help" || arg == "-h" {
+                return Err(Error::Help(self.help()));
+            }
+            if !arg.starts_with("--") {
+                parsed.positional.push(arg.to_owned());
+                continue;
+            }
+            let option = self
+                .options
+                .iter()
+                .flat_map(|group| group.iter())
+                .find(|option| option.name == arg)
+                .ok_or_else(|| self.usage(&format!("Unknown option {}", arg)))?;
+            let value = match option.value {
+                Some(value) => Some(args.next().cloned().ok_or_else(|| {
+                    self.usage(&format!("{} needs a value: {} {}", arg, arg, value))
+                })?),
+                None => None,
+            };
+            parsed.options.push((option.name, value));
+        }
+        let required = self.args.iter().filter(|arg| arg.starts_with('<')).count();
+        if parsed.positional.len() < required {
+            return Err(self.usage(&format!(
+                "Missing {}",
+                self.args[parsed.positional.len()..required].join(" ")
+            )));
+        }
+        if parsed.positional.len() > self.args.len() {
+            return Err(self.usage(&format!(
+                "Unexpected argument {}",
+                parsed.positional[self.args.len()]
+            )));
+        }
+        Ok(parsed)
+    }
+
+    /// A usage error that points to the help
+    pub fn usage(&self, message: &str) -> Error {
+        Error::Usage(format!(
+            "{}\nSee `chip_8 {} --help` for how to use it",
+            message, self.name
+        ))
+    }
+
+    pub fn help(&self) -> String {
+        let mut help = format!("Usage: chip_8 {}", self.name);
+        for arg in self.args {
+            help += &format!(" {}", arg);
+        }
+        if !self.options.is_empty() {
+            help += " [OPTIONS]";
+        }
+        help += &format!("\n\n{}\n\nOptions:\n", self.about);
+        let options = self.options.iter().flat_map(|group| group.iter());
+        let mut lines: Vec<(String, &str)> = options
+            .map(|option| match option.value {
+                Some(value) => (format!("{} <{}>", option.name, value), option.help),
+                None => (option.name.to_owned(), option.help),
+            })
+            .collect();
+        lines.push(("-h, --help".to_owned(), "Prints this help"));
+        let width = lines.iter().map(|(name, _)| name.len()).max().unwrap_or(0);
+        for (name, text) in lines {
+            help += &format!("  {:width$}  {}\n", name, text, width = width);
+        }
+        help
+    }
+}
+
+impl Args {
+    /// The positional argument at `index`
+    pub fn get(&self, index: usize) -> Option<&str> {
+        self.positional.get(index).map(String::as_str)
+    }
+
+    pub fn flag(&self, name: &str) -> bool {
+        self.options.iter().any(|(option, _)| *option == name)
+    }
+
+    /// The value of an option, the last one counts if it is given several times
+    pub fn value(&self, name: &str) -> Option<&str> {
+        self.values(name).last().copied()
+    }
+
+    /// All values of an option that can be given several times
+    pub fn values(&self, name: &str) -> Vec<&str> {
+        self.options
+            .iter()
+            .filter(|(option, _)| *option == name)
+            .filter_map(|(_, value)| value.as_deref())
+            .collect()
+    }
+
+    /// The positional argument at `index` converted to a number or the like
+    pub fn parse_arg<T: FromStr>(&self, index: usize) -> Result<Option<T>, Error> {
+        let name = self.command.args[index].trim_matches(|c| "<>[]".contains(c));
+        self.get(index)
+            .map(|value| self.convert(name, value))
+            .transpose()
+    }
+
+    fn convert<T: FromStr>(&self, name: &str, value: &str) -> Result<T, Error> {
+        value
+            .parse()
+            .map_err(|_| self.command.usage(&format!("Invalid {}: {}", name, value)))
+    }
+
+    /// A usage error of the command these are the arguments of
+    pub fn usage(&self, message: &str) -> Error {
+        self.command.usage(message)
+    }
+}
@@ -0,0 +1,122 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use toml::{Table, Value};
+
+/// The settings in `$XDG_CONFIG_HOME/chip_8/config.toml`, which are the defaults for every ROM
+/// unless the section of a ROM overrides them:
+/// ```toml
+/// freq = 1000
+/// theme = "amber"
+/// rom_dirs = ["~/roms"] # Where the browser looks for ROMs
+///
+/// [roms.0a1b2c...] # The SHA-1 of the ROM, `chip_8 info` prints it
+/// name = "Pong"
+/// platform = "chip8"
+/// quirks = "-clipping"
+/// ```
+#[derive(Debug, Default)]
+pub(crate) struct Config {
+    table: Table,
+}
+
+impl Config {
+    /// Where the config is read from by default
+    pub fn path() -> Option<PathBuf> {
+        let directory = match std::env::var_os("XDG_CONFIG_HOME") {
+            Some(directory) if !directory.is_empty() => PathBuf::from(directory),
+            _ => PathBuf::from(std::env::var_os("HOME")?).join(".config"),
+        };
+        Some(directory.join("chip_8").join("config.toml"))
+    }
+
+    /// Reads the config from a file, which has to exist
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Config, String> {
+        let path = path.as_ref();
+        match fs::read_to_string(path) {
+            Ok(text) => Config::parse(&text).map_err(|e| format!("{}: {}", path.display(), e)),
+            Err(e) => Err(format!("Can't read {}: {}", path.display(), e)),
+        }
+    }
+
+    pub fn parse(text: &str) -> Result<Config, String> {
+        let table: Table = text
+            .parse()
+            .map_err(|e: toml::de::Error| e.message().to_owned())?;
+        match table.get("roms") {
+            Some(Value::Table(_)) | None => Ok(Config { table }),
+            Some(_) => Err("roms has to be a table of ROM sections".to_owned()),
+        }
+    }
+
+    /// The options for the ROM with the given SHA-1 as name and value, the ones of its section
+    /// come after the global ones so they override them
+    pub fn options(&self, sha1: &str) -> Result<Vec<(String, String)>, String> {
+        let global = self.table.iter().filter(|(name, _)| *name != "rom_dirs");
+        let mut options = values(global, "")?;
+        if let Some(Value::Table(roms)) = self.table.get("roms") {
+            match roms.get(sha1) {
+                // The name only helps finding the section
+                Some(Value::Table(rom)) => options.extend(
+                    values(rom, &format!("roms.{}.", sha1))?
+                        .into_iter()
+                        .filter(|(name, _)| name != "name"),
+                ),
+                Some(_) => return Err(format!("roms.{} has to be a table", sha1)),
+                None => {}
+            }
+        }
+        Ok(options)
+    }
+
+    /// The name given to the ROM with the given SHA-1 in its section
+    pub fn name(&self, sha1: &str) -> Option<&str> {
+        self.table.get("roms")?.get(sha1)?.get("name")?.as_str()
+    }
+
+    /// The directories the browser lists the ROMs of, `~` stands for the home directory
+    pub fn rom_dirs(&self) -> Result<Vec<PathBuf>, String> {
+        let invalid = || "rom_dirs has to be a list of directories".to_owned();
+        let directories = match self.table.get("rom_dirs") {
+            None => return Ok(Vec::new()),
+            Some(Value::String(directory)) => vec![directory.as_str()],
+            Some(Value::Array(directories)) => directories
+                .iter()
+                .map(|directory| directory.as_str().ok_or_else(invalid))
+                .collect::<Result<_, _>>()?,
+            Some(_) => return Err(invalid()),
+        };
+        let home = std::env::var_os("HOME").map(PathBuf::from);
+        Ok(directories
+            .into_iter()
+            .map(|directory| match (directory.strip_prefix("~/"), &home) {
+                (Some(directory), Some(home)) => home.join(directory),
+                _ => PathBuf::from(directory),
+            })
+            .collect())
+    }
+}
+
+/// The values of a section without the nested sections
+fn values<'a>(
+    table: impl IntoIterator<Item = (&'a String, &'a Value)>,
+    section: &str,
+) -> Result<Vec<(String, String)>, String> {
+    let mut options = Vec::new();
+    for (name, value) in table {
+        let value = match value {
+            Value::String(text) => text.to_owned(),
+            Value::Integer(number) => number.to_string(),
+            Value::Float(number) => number.to_string(),
+            Value::Boolean(flag) => flag.to_string(),
+            Value::Table(_) => continue,
+            _ => {
+                return Err(format!(
+                    "{}{} has to be a string or a number",
+                    section, name
+                ))
+            }
+        };
+        options.push((name.to_owned(), value));
+    }
+    Ok(options)
+}
@@ -1,11 +1,877 @@
+mod assembler;
 #[allow(dead_code)]
 mod chip_controller;
+mod cli;
+mod config;
 mod tests;
 mod ui;
 
+use chip_controller::{
+    crc32, sha1, Analysis, Cheats, ChipController, Crash, DataStyle, Database, Patch, Quirks, Rom,
+    Schedule, SpriteFinder, SymbolMap, Syntax, Weight,
+};
+use cli::{Args, Command, Error, Opt};
+use config::Config;
+use std::fs::OpenOptions;
+use std::io::{IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, Instant};
+use ui::{Browser, Settings};
+
 type Byte = u8;
 
+/// The options of every command that loads a ROM
+const MACHINE: &[Opt] = &[
+    Opt {
+        name: "--patch",
+        value: Some("FILE"),
+        help: "Applies an IPS or BPS patch to the ROM, can be given several times",
+    },
+    Opt {
+        name: "--ips",
+        value: Some("FILE"),
+        help: "The same as --patch",
+    },
+    Opt {
+        name: "--platform",
+        value: Some("NAME"),
+        help: "Uses the quirks of chip8, schip or xochip",
+    },
+    Opt {
+        name: "--quirks",
+        value: Some("LIST"),
+        help: "Enables quirks like memory,clipping or disables them like -clipping",
+    },
+    Opt {
+        name: "--seed",
+        value: Some("N"),
+        help: "Makes the random numbers the same in every run",
+    },
+    Opt {
+        name: "--config",
+        value: Some("FILE"),
+        help: "Reads the settings from this file instead of ~/.config/chip_8/config.toml",
+    },
+    Opt {
+        name: "--no-config",
+        value: None,
+        help: "Ignores the config file",
+    },
+    Opt {
+        name: "--no-database",
+        value: None,
+        help: "Doesn't set the quirks, speed and keys of ROMs the ROM database knows",
+    },
+];
+
+const SYMBOLS: &[Opt] = &[Opt {
+    name: "--symbols",
+    value: Some("FILE"),
+    help: "The symbol map, by default the one next to the ROM with a .sym extension",
+}];
+
+/// The speed of the commands that run a ROM without a terminal
+const FREQ: &[Opt] = &[Opt {
+    name: "--freq",
+    value: Some("N"),
+    help: "Instructions per second, 1000 or what the ROM database knows by default",
+}];
+
+const DISPLAY: &[Opt] = &[
+    Opt {
+        name: "--freq",
+        value: Some("N"),
+        help: "Instructions per second, 1000 by default",
+    },
+    Opt {
+        name: "--theme",
+        value: Some("THEME"),
+        help: "default, octo, amber, green, lcd or 2 to 4 colours like #000000,#FFFFFF",
+    },
+    Opt {
+        name: "--keymap",
+        value: Some("KEYMAP"),
+        help: "qwerty, hex, azerty, arrows or a file with a custom keymap",
+    },
+    Opt {
+        name: "--phosphor",
+        value: Some("MODE"),
+        help: "Lets erased pixels fade to reduce the flicker: off, blend or decay",
+    },
+    Opt {
+        name: "--renderer",
+        value: Some("NAME"),
+        help: "auto, blocks, halfblocks, sextants or braille",
+    },
+    Opt {
+        name: "--hold",
+        value: Some("MS"),
+        help: "How long a key stays pressed in terminals that don't report releases",
+    },
+    Opt {
+        name: "--delay",
+        value: Some("MS"),
+        help: "How long a key stays pressed until the terminal repeats it, --hold by default",
+    },
+];
+
+static COMMANDS: [Command; 16] = [
+    Command {
+        name: "run",
+        args: &["<ROM>", "[FREQ]"],
+        about: "Plays a ROM in the terminal, Escape opens the menu",
+        options: &[MACHINE, DISPLAY, SYMBOLS],
+        run,
+    },
+    Command {
+        name: "browse",
+        args: &["[DIR]"],
+        about: "Lists the ROMs in a directory or the rom_dirs of the config to play them",
+        options: &[MACHINE, DISPLAY],
+        run: browse,
+    },
+    Command {
+        name: "info",
+        args: &["<ROM>"],
+        about: "Prints the size, checksum and likely platform of a ROM",
+        options: &[],
+        run: info,
+    },
+    Command {
+        name: "disasm",
+        args: &["<ROM>"],
+        about: "Prints the code and data of a ROM",
+        options: &[
+            &[
+                Opt {
+                    name: "--octo",
+                    value: None,
+                    help: "Writes Octo assembly instead of mnemonics",
+                },
+                Opt {
+                    name: "--bytes",
+                    value: None,
+                    help: "Writes sprites as plain bytes",
+                },
+            ],
+            SYMBOLS,
+        ],
+        run: disasm,
+    },
+    Command {
+        name: "asm",
+        args: &["<SOURCE>", "[ROM]"],
+        about: "Assembles Octo source into a ROM next to it and a symbol map for the debuggers",
+        options: &[],
+        run: asm,
+    },
+    Command {
+        name: "bench",
+        args: &["<ROM>", "[INSTRUCTIONS]"],
+        about: "Measures how fast a ROM runs, 10000000 instructions by default",
+        options: &[MACHINE, FREQ],
+        run: bench,
+    },
+    Command {
+        name: "test",
+        args: &["<ROM>", "[FRAMES]"],
+        about: "Runs a ROM without a terminal and prints the display after 600 frames or the given ones",
+        options: &[
+            MACHINE,
+            FREQ,
+            &[
+                Opt {
+                    name: "--press",
+                    value: Some("FRAMES:KEYS"),
+                    help: "Holds keys in some frames like 60-90:5 or 120:4A, can be given several times",
+                },
+                Opt {
+                    name: "--input",
+                    value: Some("FILE"),
+                    help: "Reads presses like the ones of --press from a file, one on each line",
+                },
+                Opt {
+                    name: "--print",
+                    value: Some("WHAT"),
+                    help: "display, state for the registers or image for a PBM image of the display",
+                },
+                Opt {
+                    name: "--expect",
+                    value: Some("FILE"),
+                    help: "Fails if the display differs from the one in the file",
+                },
+            ],
+        ],
+        run: test,
+    },
+    Command {
+        name: "trace",
+        args: &["<ROM>", "[INSTRUCTIONS]"],
+        about: "Prints every executed instruction, the first 1000 by default",
+        options: &[MACHINE, FREQ, SYMBOLS],
+        run: trace,
+    },
+    Command {
+        name: "coverage",
+        args: &["<ROM>", "[INSTRUCTIONS]"],
+        about: "Prints the executed code and accessed data after 100000 instructions",
+        options: &[MACHINE, FREQ, SYMBOLS],
+        run: coverage,
+    },
+    Command {
+        name: "profile",
+        args: &["<ROM>", "[FRAMES]"],
+        about: "Prints where the time of 600 frames or the given ones was spent",
+        options: &[
+            MACHINE,
+            FREQ,
+            SYMBOLS,
+            &[
+                Opt {
+                    name: "--folded",
+                    value: Some("FILE"),
+                    help: "Writes folded stacks for flamegraph tools",
+                },
+                Opt {
+                    name: "--cycles",
+                    value: None,
+                    help: "Weights the folded stacks by VIP cycles",
+                },
+            ],
+        ],
+        run: profile,
+    },
+    Command {
+        name: "analyze",
+        args: &["<ROM>"],
+        about: "Prints the instructions and quirks a ROM most likely expects",
+        options: &[],
+        run: analyze,
+    },
+    Command {
+        name: "sprites",
+        args: &["<ROM>", "[INSTRUCTIONS]"],
+        about: "Prints the sprites in the code and the ones drawn in 100000 instructions",
+        options: &[
+            MACHINE,
+            FREQ,
+            &[Opt {
+                name: "--image",
+                value: Some("FILE"),
+                help: "Also writes them as a PBM image",
+            }],
+        ],
+        run: sprites,
+    },
+    Command {
+        name: "diff",
+        args: &["<ORIGINAL>", "<MODIFIED>", "<PATCH>"],
+        about: "Writes a patch from one ROM to another, IPS if it ends with .ips and BPS otherwise",
+        options: &[],
+        run: diff,
+    },
+    Command {
+        name: "database",
+        args: &["[DIR]"],
+        about: "Installs the community CHIP-8 database in a directory or shows the one in use",
+        options: &[],
+        run: database,
+    },
+    Command {
+        name: "gdb",
+        args: &["<ROM>", "[PORT]"],
+        about: "Waits for a GDB client on localhost, on port 1234 by default",
+        options: &[MACHINE, FREQ, SYMBOLS],
+        run: gdb,
+    },
+    Command {
+        name: "dap",
+        args: &[],
+        about: "Lets an editor debug a ROM over stdin and stdout",
+        options: &[],
+        run: dap,
+    },
+];
+
 fn main() {
-    let mut ui = ui::UI::new();
-    ui.run();
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if let Err(e) = execute(&args) {
+        match e {
+            Error::Help(_) => print!("{}", e),
+            _ => eprintln!("error: {}", e),
+        }
+        std::process::exit(e.code());
+    }
+}
+
+fn execute(args: &[String]) -> Result<(), Error> {
+    let name = match args.first() {
+        Some(name) => name.as_str(),
+        // Without a command the ROMs are listed to choose one, if there is a terminal for that
+        None if std::io::stdout().is_terminal() => return browse(&COMMANDS[1].parse(args)?),
+        None => {
+            eprint!("{}", help());
+            return Err(Error::Usage("No command given".to_owned()));
+        }
+    };
+    match name {
+        "--help" | "-h" | "help" => return Err(Error::Help(help())),
+        "--version" | "-V" => {
+            println!("chip_8 {}", env!("CARGO_PKG_VERSION"));
+            return Ok(());
+        }
+        _ => {}
+    }
+    match COMMANDS.iter().find(|command| command.name == name) {
+        Some(command) => (command.run)(&command.parse(&args[1..])?),
+        // `chip_8 <ROM>` is short for `chip_8 run <ROM>`
+        None if Path::new(name).is_file() => run(&COMMANDS[0].parse(args)?),
+        None if Path::new(name).is_dir() => browse(&COMMANDS[1].parse(args)?),
+        None => Err(Error::Usage(format!(
+            "Unknown command {}\nSee `chip_8 --help` for the commands",
+            name
+        ))),
+    }
+}
+
+/// The commands with a line about each
+fn help() -> String {
+    let mut help = "Usage: chip_8 <COMMAND> [ARGS] [OPTIONS]\n\nCommands:\n".to_owned();
+    for command in &COMMANDS {
+        help += &format!("  {:10}{}\n", command.name, command.about);
+    }
+    help + "\n`chip_8 <ROM>` is short for `chip_8 run <ROM>` and `chip_8 <DIR>` for \
+        `chip_8 browse <DIR>`, see `chip_8 <COMMAND> --help` for the options of a command\n"
+}
+
+fn run(args: &Args) -> Result<(), Error> {
+    play(args, Path::new(args.get(0).unwrap()), args.get(1))
+}
+
+/// Lists the ROMs and plays the chosen ones until the list is quit
+fn browse(args: &Args) -> Result<(), Error> {
+    let config = config(args)?;
+    let mut directories: Vec<PathBuf> = args.get(0).map(PathBuf::from).into_iter().collect();
+    if let (true, Some((file, config))) = (directories.is_empty(), &config) {
+        directories = config
+            .rom_dirs()
+            .map_err(|e| Error::Failed(format!("{}: {}", file.display(), e)))?;
+    }
+    if directories.is_empty() {
+        directories.push(PathBuf::from("."));
+    }
+    let database = rom_database(args)?;
+    let mut browser = Browser::scan(directories);
+    // A name in the config is how the user wants to call a ROM, so it wins over the database
+    for entry in browser.entries_mut() {
+        let name = config
+            .as_ref()
+            .and_then(|(_, config)| config.name(entry.sha1()));
+        entry.title = match name {
+            Some(name) => Some(name.to_owned()),
+            None => database
+                .as_ref()
+                .and_then(|database| database.program(entry.sha1()))
+                .map(|program| program.title),
+        };
+    }
+    while let Some(path) = browser.choose() {
+        if let Err(e) = play(args, &path, None) {
+            browser.message = e.to_string().lines().next().unwrap_or("").to_owned();
+        }
+    }
+    Ok(())
+}
+
+/// Plays a ROM with the settings of the config and `args`, `freq` overrides them
+fn play(args: &Args, path: &Path, freq: Option<&str>) -> Result<(), Error> {
+    let rom = std::fs::read(path).map_err(|e| failed(path, e))?;
+    let options = options(args, &rom)?;
+    let mut controller = machine(args, rom, &options)?;
+    let mut settings = Settings::default();
+    if let Some(program) = controller.program() {
+        settings.recommend(program);
+    }
+    for option in options.iter().filter(|option| option.for_ui()) {
+        settings
+            .set(&option.name, &option.value)
+            .map_err(|e| option.error(args, e))?;
+    }
+    if let Some(freq) = freq {
+        settings.set("freq", freq).map_err(|e| args.usage(&e))?;
+    }
+    // Cheats for the ROM are kept next to it
+    let cheats = path.with_extension("cht");
+    if cheats.exists() {
+        controller.set_cheats(Cheats::load(&cheats).map_err(|e| failed(&cheats, e))?);
+    }
+    let mut ui = ui::UI::new(controller, &settings);
+    ui.set_rom_file(path.to_owned());
+    // The debugger names addresses with the symbol map next to the ROM, if there is one
+    let symbols = match args.value("--symbols") {
+        Some(symbols) => Some(PathBuf::from(symbols)),
+        None => Some(path.with_extension("sym")).filter(|symbols| symbols.exists()),
+    };
+    if let Some(symbols) = symbols {
+        ui.set_symbols(SymbolMap::load(&symbols).map_err(|e| failed(&symbols, e))?);
+    }
+    ui.emulate();
+    Ok(())
+}
+
+fn info(args: &Args) -> Result<(), Error> {
+    let path = args.get(0).unwrap();
+    let bytes = std::fs::read(path).map_err(|e| failed(path, e))?;
+    let analysis = Analysis::new(&Rom::from_bytes(bytes.clone()));
+    println!("File:     {}", path);
+    println!("Size:     {} bytes", bytes.len());
+    println!("CRC32:    {:08X}", crc32(&bytes));
+    println!("SHA-1:    {}", sha1(&bytes));
+    let program = Database::installed()
+        .map_err(|e| Error::Failed(e.to_string()))?
+        .program(&sha1(&bytes));
+    match program {
+        Some(program) => {
+            println!("Title:    {}", program.title);
+            if !program.authors.is_empty() {
+                println!("Authors:  {}", program.authors.join(", "));
+            }
+            let platform = program.platform.unwrap_or_else(|| "unknown".to_owned());
+            println!("Platform: {} according to the database", platform);
+            let quirks = program.quirks.unwrap_or_else(|| analysis.quirks());
+            println!("Quirks:   {}", quirks);
+            if let Some(tickrate) = program.tickrate {
+                println!("Speed:    {} instructions per second", tickrate * 60);
+            }
+        }
+        None => {
+            println!("Platform: {}", analysis.platform());
+            println!("Quirks:   {}", analysis.quirks());
+        }
+    }
+    for (name, extension) in [("Symbols:", "sym"), ("Cheats:", "cht")] {
+        let file = Path::new(path).with_extension(extension);
+        if file.exists() {
+            println!("{:9} {}", name, file.display());
+        }
+    }
+    Ok(())
+}
+
+fn disasm(args: &Args) -> Result<(), Error> {
+    let symbols = symbols(args)?;
+    let path = args.get(0).unwrap();
+    let rom = Rom::new(path.to_owned()).map_err(|e| failed(path, e))?;
+    let syntax = match args.flag("--octo") {
+        true => Syntax::Octo,
+        false => Syntax::Mnemonic,
+    };
+    let style = match args.flag("--bytes") {
+        true => DataStyle::Bytes,
+        false => DataStyle::Sprites,
+    };
+    let disassembly = rom.disassemble().with_symbols(&symbols);
+    print!("{}", disassembly.listing(syntax, style));
+    Ok(())
+}
+
+fn asm(args: &Args) -> Result<(), Error> {
+    let source = Path::new(args.get(0).unwrap());
+    let text = std::fs::read_to_string(source).map_err(|e| failed(source, e))?;
+    let output = match args.get(1) {
+        Some(output) => PathBuf::from(output),
+        None => source.with_extension("ch8"),
+    };
+    let file = source.file_name().unwrap_or_default().to_string_lossy();
+    let program = assembler::assemble(&text, &file)
+        .map_err(|e| Error::Failed(format!("{}:{}", source.display(), e)))?;
+    let symbols = output.with_extension("sym");
+    std::fs::write(&output, &program.rom).map_err(|e| failed(&output, e))?;
+    program
+        .symbols
+        .save(&symbols)
+        .map_err(|e| failed(&symbols, e))?;
+    println!("Wrote {} bytes to {}", program.rom.len(), output.display());
+    Ok(())
+}
+
+fn bench(args: &Args) -> Result<(), Error> {
+    let (mut controller, freq) = load(args)?;
+    let count = args.parse_arg(1)?.unwrap_or(10_000_000);
+    let start = Instant::now();
+    headless(&mut controller, count, freq).map_err(crashed)?;
+    let seconds = start.elapsed().as_secs_f64();
+    println!(
+        "Executed {} instructions in {:.3}s, that is {:.0} per second or {:.0} times as fast \
+         as {} per second",
+        count,
+        seconds,
+        count as f64 / seconds,
+        count as f64 / seconds / freq as f64,
+        freq
+    );
+    Ok(())
+}
+
+/// Runs a ROM like `run` does, but as fast as possible and without a terminal, for scripts and
+/// continuous integration
+fn test(args: &Args) -> Result<(), Error> {
+    let path = args.get(0).unwrap();
+    let rom = std::fs::read(path).map_err(|e| failed(path, e))?;
+    let options = options(args, &rom)?;
+    let mut controller = machine(args, rom, &options)?;
+    let frames: usize = args.parse_arg(1)?.unwrap_or(600);
+    let freq = freq(args, &controller, &options)?;
+    let mut schedule = match args.value("--input") {
+        Some(file) => Schedule::load(file).map_err(|e| failed(file, e))?,
+        None => Schedule::default(),
+    };
+    for press in args.values("--press") {
+        schedule.add(press).map_err(|e| args.usage(&e))?;
+    }
+    controller
+        .run_frames(frames, (freq / 60).max(1), &schedule)
+        .map_err(crashed)?;
+    let screen = controller.screen();
+    match args.value("--print").unwrap_or("display") {
+        "display" => println!("{}", screen),
+        "state" => println!("{}\nQuirks {}", controller.state(), controller.quirks()),
+        "image" => std::io::stdout()
+            .write_all(&controller.image())
+            .map_err(|e| Error::Failed(format!("Can't print the image: {}", e)))?,
+        other => {
+            return Err(args.usage(&format!(
+                "Invalid --print {}, it has to be display, state or image",
+                other
+            )))
+        }
+    }
+    if let Some(path) = args.value("--expect") {
+        let expected = std::fs::read_to_string(path).map_err(|e| failed(path, e))?;
+        if expected.trim_end_matches('\n') != screen {
+            return Err(Error::Failed(format!(
+                "The display differs from {} after {} frames",
+                path, frames
+            )));
+        }
+    }
+    Ok(())
+}
+
+fn trace(args: &Args) -> Result<(), Error> {
+    let symbols = symbols(args)?;
+    let (mut controller, freq) = load(args)?;
+    let count = args.parse_arg(1)?.unwrap_or(1000);
+    controller.trace_to(Box::new(std::io::stdout()), symbols);
+    headless(&mut controller, count, freq).map_err(crashed)
+}
+
+fn coverage(args: &Args) -> Result<(), Error> {
+    let symbols = symbols(args)?;
+    let (mut controller, freq) = load(args)?;
+    let count = args.parse_arg(1)?.unwrap_or(100_000);
+    let rom = Rom::from_bytes(controller.rom().to_vec());
+    controller.enable_coverage();
+    // What ran until a crash is still reported
+    let result = headless(&mut controller, count, freq);
+    print!("{}", controller.coverage_report(&rom, &symbols).unwrap());
+    result.map_err(crashed)
+}
+
+fn profile(args: &Args) -> Result<(), Error> {
+    let symbols = symbols(args)?;
+    let (mut controller, freq) = load(args)?;
+    let frames: usize = args.parse_arg(1)?.unwrap_or(600);
+    controller.enable_profiler(symbols);
+    let result = controller.run_frames(frames, (freq / 60).max(1), &Schedule::default());
+    print!("{}", controller.profile_report().unwrap());
+    if let Some(path) = args.value("--folded") {
+        let weight = match args.flag("--cycles") {
+            true => Weight::Cycles,
+            false => Weight::Instructions,
+        };
+        std::fs::write(path, controller.folded_stacks(weight).unwrap())
+            .map_err(|e| failed(path, e))?;
+    }
+    result.map_err(crashed)
+}
+
+fn analyze(args: &Args) -> Result<(), Error> {
+    let path = args.get(0).unwrap();
+    let rom = Rom::new(path.to_owned()).map_err(|e| failed(path, e))?;
+    print!("{}", Analysis::new(&rom));
+    Ok(())
+}
+
+fn sprites(args: &Args) -> Result<(), Error> {
+    let (mut controller, freq) = load(args)?;
+    let count = args.parse_arg(1)?.unwrap_or(100_000);
+    let rom = Rom::from_bytes(controller.rom().to_vec());
+    controller.enable_sprites(SpriteFinder::scan(&rom));
+    let result = headless(&mut controller, count, freq);
+    let sheet = controller.sprite_sheet().unwrap();
+    print!("{}", sheet);
+    if let Some(path) = args.value("--image") {
+        std::fs::write(path, sheet.image()).map_err(|e| failed(path, e))?;
+    }
+    result.map_err(crashed)
+}
+
+fn diff(args: &Args) -> Result<(), Error> {
+    let read = |path: &str| std::fs::read(path).map_err(|e| failed(path, e));
+    let original = read(args.get(0).unwrap())?;
+    let modified = read(args.get(1).unwrap())?;
+    let output = Path::new(args.get(2).unwrap());
+    let patch = match output.extension().and_then(|e| e.to_str()) {
+        Some(e) if e.eq_ignore_ascii_case("ips") => Patch::ips(&original, &modified),
+        _ => Patch::bps(&original, &modified),
+    };
+    std::fs::write(output, &patch).map_err(|e| failed(output, e))?;
+    println!("Wrote {} bytes to {}", patch.len(), output.display());
+    Ok(())
+}
+
+fn database(args: &Args) -> Result<(), Error> {
+    let path = Database::path();
+    if let Some(directory) = args.get(0) {
+        let installed = Database::install(directory)
+            .map_err(|e| Error::Failed(format!("Can't install the database: {}", e)))?;
+        println!("Installed the database in {}", installed.display());
+    }
+    let database = Database::installed().map_err(|e| Error::Failed(e.to_string()))?;
+    match path.filter(|path| path.exists()) {
+        Some(path) => println!("Using the database in {}", path.display()),
+        None => println!("Using the bundled database"),
+    }
+    println!("It knows {} ROMs", database.len());
+    Ok(())
+}
+
+fn gdb(args: &Args) -> Result<(), Error> {
+    let symbols = symbols(args)?;
+    let (mut controller, freq) = load(args)?;
+    let port = args.parse_arg(1)?.unwrap_or(1234);
+    println!("Waiting for GDB on localhost:{}", port);
+    controller
+        .serve_gdb(port, freq, symbols)
+        .map_err(|e| Error::Failed(format!("GDB session ended: {}", e)))
+}
+
+fn dap(_: &Args) -> Result<(), Error> {
+    let mut controller = ChipController::new();
+    controller.attach_dap();
+    match OpenOptions::new().write(true).open("/dev/tty") {
+        Ok(tty) => ui::UI::with(controller, Box::new(tty), &Settings::default()).emulate(),
+        // Editors usually start adapters without a terminal, so the game is not shown at all
+        Err(_) => {
+            const FRAME: Duration = Duration::from_micros(16_667);
+            while !controller.finished() {
+                let start = Instant::now();
+                controller.tick(Some(1000 / 60)).map_err(crashed)?;
+                controller.end_frame();
+                if let Some(rest) = FRAME.checked_sub(start.elapsed()) {
+                    thread::sleep(rest);
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// An error for a file that couldn't be read or written
+fn failed(path: impl AsRef<Path>, e: std::io::Error) -> Error {
+    Error::Failed(format!("Can't use {}: {}", path.as_ref().display(), e))
+}
+
+/// An error for a ROM that reached an instruction the chip can't execute
+fn crashed(crash: Crash) -> Error {
+    Error::Crashed(crash.to_string())
+}
+
+/// The options that configure the chip instead of the UI
+const MACHINE_OPTIONS: [&str; 3] = ["platform", "quirks", "seed"];
+
+/// An option from the config file or the command line
+struct Setting {
+    name: String,
+    value: String,
+    /// The config file it is from, it was given on the command line if there is none
+    file: Option<PathBuf>,
+}
+
+impl Setting {
+    fn for_ui(&self) -> bool {
+        !MACHINE_OPTIONS.contains(&self.name.as_str())
+    }
+
+    /// Explains where the option with a wrong value came from
+    fn error(&self, args: &Args, message: String) -> Error {
+        match &self.file {
+            Some(file) => Error::Failed(format!("{} in {}", message, file.display())),
+            None => args.usage(&message),
+        }
+    }
+}
+
+/// The options for a ROM, first the global ones of the config file, then the ones of the ROM's
+/// section and last the ones given on the command line, so each overrides the ones before
+///
+/// A platform comes before the quirks in every layer, because it replaces all of them.
+fn options(args: &Args, rom: &[u8]) -> Result<Vec<Setting>, Error> {
+    let mut options = Vec::new();
+    if let Some((file, config)) = config(args)? {
+        let layer = config
+            .options(&sha1(rom))
+            .map_err(|e| Error::Failed(format!("{}: {}", file.display(), e)))?;
+        for (name, value) in layer {
+            if !MACHINE_OPTIONS.contains(&name.as_str())
+                && !Settings::OPTIONS.contains(&name.as_str())
+            {
+                return Err(Error::Failed(format!(
+                    "Unknown option {} in {}",
+                    name,
+                    file.display()
+                )));
+            }
+            options.push(Setting {
+                name,
+                value,
+                file: Some(file.clone()),
+            });
+        }
+    }
+    for name in MACHINE_OPTIONS.iter().chain(Settings::OPTIONS.iter()) {
+        if let Some(value) = args.value(&format!("--{}", name)) {
+            options.push(Setting {
+                name: name.to_string(),
+                value: value.to_owned(),
+                file: None,
+            });
+        }
+    }
+    Ok(options)
+}
+
+/// The config file given in `args` or the default one, unless `--no-config` is given
+fn config(args: &Args) -> Result<Option<(PathBuf, Config)>, Error> {
+    let file = match args.value("--config") {
+        Some(file) => Some(PathBuf::from(file)),
+        // Only the default file may be missing, which is the same as an empty one
+        None => Config::path().filter(|file| file.exists()),
+    };
+    match file.filter(|_| !args.flag("--no-config")) {
+        Some(file) => {
+            let config = Config::load(&file).map_err(Error::Failed)?;
+            Ok(Some((file, config)))
+        }
+        None => Ok(None),
+    }
+}
+
+/// A chip with the ROM in `args` loaded and configured by the config file and the `MACHINE`
+/// options, along with the instructions per second it runs at
+fn load(args: &Args) -> Result<(ChipController, usize), Error> {
+    let path = args.get(0).unwrap();
+    let rom = std::fs::read(path).map_err(|e| failed(path, e))?;
+    let options = options(args, &rom)?;
+    let controller = machine(args, rom, &options)?;
+    let freq = freq(args, &controller, &options)?;
+    Ok((controller, freq))
+}
+
+/// The instructions per second of `--freq` or the config, otherwise what the ROM database
+/// knows or 1000, only the speed of the UI options matters without a terminal
+fn freq(args: &Args, controller: &ChipController, options: &[Setting]) -> Result<usize, Error> {
+    let mut freq = controller
+        .program()
+        .and_then(|program| program.tickrate)
+        .map_or(1000, |tickrate| tickrate * 60);
+    for option in options.iter().filter(|option| option.name == "freq") {
+        freq = option.value.parse().map_err(|_| {
+            option.error(
+                args,
+                format!("Invalid freq {}, it has to be a number", option.value),
+            )
+        })?;
+    }
+    Ok(freq)
+}
+
+/// A chip with the patches in `args` applied to the ROM and the machine options set, the
+/// quirks the ROM database knows for the ROM come first so the options can change them
+fn machine(args: &Args, rom: Vec<u8>, options: &[Setting]) -> Result<ChipController, Error> {
+    let mut controller = ChipController::new();
+    if let Some(database) = rom_database(args)? {
+        controller.set_database(database);
+    }
+    let mut patches = args.values("--patch");
+    patches.extend(args.values("--ips"));
+    for patch in patches {
+        controller.add_patch(Patch::load(patch).map_err(|e| failed(patch, e))?);
+    }
+    controller
+        .set_rom(rom)
+        .map_err(|e| Error::Failed(e.to_string()))?;
+    let mut quirks = controller.quirks();
+    for option in options.iter().filter(|option| !option.for_ui()) {
+        let value = option.value.as_str();
+        match option.name.as_str() {
+            "platform" => {
+                quirks = Quirks::profile(value).ok_or_else(|| {
+                    option.error(
+                        args,
+                        format!(
+                            "Unknown platform {}, it has to be chip8, schip or xochip",
+                            value
+                        ),
+                    )
+                })?
+            }
+            "quirks" => quirks.set(value).map_err(|e| option.error(args, e))?,
+            _ => controller.set_seed(value.parse().map_err(|_| {
+                option.error(
+                    args,
+                    format!("Invalid seed {}, it has to be a number", value),
+                )
+            })?),
+        }
+    }
+    controller.set_quirks(quirks);
+    Ok(controller)
+}
+
+/// The installed ROM database, unless `--no-database` is given
+fn rom_database(args: &Args) -> Result<Option<Database>, Error> {
+    match args.flag("--no-database") {
+        true => Ok(None),
+        false => Database::installed()
+            .map(Some)
+            .map_err(|e| Error::Failed(format!("Can't read the ROM database: {}", e))),
+    }
+}
+
+/// Executes the given amount of instructions as fast as possible, the timers are decremented
+/// as if the chip ran at `freq` instructions per second, it stops early if the chip crashes
+fn headless(
+    controller: &mut ChipController,
+    instructions: usize,
+    freq: usize,
+) -> Result<(), Crash> {
+    let per_frame = (freq / 60).max(1);
+    controller.run_frames(instructions / per_frame, per_frame, &Schedule::default())?;
+    controller.tick(Some(instructions % per_frame))
+}
+
+/// The symbol map of the ROM, which is either given with `--symbols <FILE>` or lies next to the
+/// ROM with a `.sym` extension
+fn symbols(args: &Args) -> Result<SymbolMap, Error> {
+    let path = match args.value("--symbols") {
+        Some(path) => PathBuf::from(path),
+        None => match Path::new(args.get(0).unwrap()).with_extension("sym") {
+            path if path.exists() => path,
+            _ => return Ok(SymbolMap::new()),
+        },
+    };
+    SymbolMap::load(&path).map_err(|e| failed(&path, e))
 }
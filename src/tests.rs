@@ -1,9 +1,1152 @@
 #[cfg(test)]
 use crate::chip_controller::ChipController;
 
-/// This should run infinetly
+/// A paddle that keys 4 and 6 move, which keeps running while the keys are held
 #[test]
 fn breakout() {
+    use crate::chip_controller::Schedule;
     let mut controller = ChipController::new();
-    controller.set_rom(std::fs::read("./assets/games/br8kout.ch8").unwrap());
+    controller
+        .set_rom(vec![
+            0x6A, 0x20, // LDBR A 20
+            0x6B, 0x1E, // LDBR B 1E
+            0xA2, 0x22, // LD3NI 222
+            0xDA, 0xB1, // DRW A B 1
+            0x60, 0x04, // LDBR 0 4
+            0xE0, 0x9E, // SKP 0
+            0x12, 0x14, // JP 214
+            0xDA, 0xB1, // DRW A B 1
+            0x7A, 0xFF, // ADDBR A FF
+            0xDA, 0xB1, // DRW A B 1
+            0x60, 0x06, // LDBR 0 6
+            0xE0, 0x9E, // SKP 0
+            0x12, 0x20, // JP 220
+            0xDA, 0xB1, // DRW A B 1
+            0x7A, 0x01, // ADDBR A 1
+            0xDA, 0xB1, // DRW A B 1
+            0x12, 0x08, // JP 208
+            0xFF, // sprite
+        ])
+        .unwrap();
+    controller
+        .run_frames(1, 1000 / 60, &Schedule::default())
+        .unwrap();
+    let start = controller.screen().lines().nth(30).unwrap().to_owned();
+    // Ten seconds of moving the paddle left and right
+    let mut schedule = Schedule::default();
+    schedule.add("60-180:4").unwrap();
+    schedule.add("240-300:6").unwrap();
+    controller.run_frames(600, 1000 / 60, &schedule).unwrap();
+    let paddle = controller.screen().lines().nth(30).unwrap().to_owned();
+    assert_eq!(paddle.matches('█').count(), 8);
+    assert_ne!(paddle, start);
+}
+
+#[test]
+fn disassembler_separates_code_and_sprites() {
+    use crate::chip_controller::{DataStyle, Rom, Syntax};
+    let rom = Rom::from_bytes(vec![
+        0xA2, 0x08, // LD3NI 208
+        0x22, 0x06, // CALL 206
+        0x12, 0x04, // JP 204
+        0xD0, 0x12, // DRW 0 1 2
+        0xF0, 0x90, // sprite
+    ]);
+    let disassembly = rom.disassemble();
+    assert!(disassembly.is_code(0x206));
+    assert!(!disassembly.is_code(0x208));
+    assert_eq!(disassembly.label(0x206).as_deref(), Some("sub_206"));
+    assert_eq!(disassembly.label(0x208).as_deref(), Some("sprite_208"));
+    let octo = disassembly.listing(Syntax::Octo, DataStyle::Sprites);
+    assert!(octo.contains("i := sprite_208\n\tsub_206\n"));
+    assert!(octo.contains(": sprite_208\n\t0b11110000\n\t0b10010000\n"));
+}
+
+#[test]
+fn disassembler_reaches_the_end_of_the_ram() {
+    use crate::chip_controller::{Analysis, DataStyle, Rom, SymbolMap, Syntax};
+    let mut bytes = vec![0; 0x10000 - 0x200];
+    bytes[..2].copy_from_slice(&[0x12, 0x00]); // JP 200
+    bytes[0xfdfc..].copy_from_slice(&[0xF0, 0x00, 0x02, 0x00]); // LD4NI 200 at FFFC
+    let rom = Rom::from_bytes(bytes.clone());
+    assert_eq!(rom.end(), 0x10000);
+    let disassembly = rom.disassemble_from(&[0x200, 0xfffc]);
+    assert!(disassembly.is_code(0xfffc));
+    let listing = disassembly.listing(Syntax::Mnemonic, DataStyle::Bytes);
+    assert!(listing.ends_with("FFFA:        DB 00 00\nFFFC-FFFF:   LD4NI label_200\n"));
+    Analysis::new(&rom);
+
+    let mut controller = ChipController::new();
+    controller.set_rom(bytes).unwrap();
+    controller.enable_coverage();
+    controller.tick(Some(2)).unwrap();
+    let report = controller.coverage_report(&rom, &SymbolMap::new()).unwrap();
+    assert!(report.starts_with("Code: 1 of 1 instructions executed"));
+}
+
+#[test]
+fn flags_are_written_after_the_result() {
+    let mut controller = ChipController::new();
+    controller
+        .set_rom(vec![
+            0x60, 0x05, // LDBR 0 5
+            0x61, 0x05, // LDBR 1 5
+            0x62, 0x08, // LDBR 2 8
+            0x80, 0x15, // SUB 0 1, v0 = 0 and no borrow
+            0xFF, 0x29, // LDSI F
+            0xD0, 0x05, // DRW 0 0 5
+            0x6F, 0xFF, // LDBR F FF
+            0x63, 0x01, // LDBR 3 1
+            0x8F, 0x34, // ADDRR F 3, the carry replaces the sum
+            0xFF, 0x29, // LDSI F
+            0xD2, 0x05, // DRW 2 0 5
+            0x12, 0x16, // JP 216
+        ])
+        .unwrap();
+    controller.tick(Some(12)).unwrap();
+    let display = controller.get_display();
+    let row = |x: usize, y: usize| (0..8).fold(0, |byte, i| byte << 1 | display[y * 64 + x + i]);
+    for (y, sprite) in [0x20, 0x60, 0x20, 0x20, 0x70].iter().enumerate() {
+        assert_eq!(row(0, y), *sprite);
+        assert_eq!(row(8, y), *sprite);
+    }
+}
+
+#[test]
+fn schip_and_xo_chip_instructions_draw_in_hires() {
+    let mut controller = ChipController::new();
+    let mut rom = vec![
+        0x00, 0xFF, // HIGH
+        0xF0, 0x00, 0x02, 0x0C, // LD4NI 20C
+        0xD0, 0x00, // DRW 0 0 0, a 16x16 sprite
+        0x00, 0xFB, // SCR
+        0x12, 0x0A, // JP 20A
+    ];
+    rom.extend([0xFF; 32]);
+    controller.set_rom(rom).unwrap();
+    controller.tick(Some(5)).unwrap();
+    assert_eq!(controller.get_dimension(), (128, 64));
+    let display = controller.get_display();
+    for y in 0..64 {
+        for x in 0..128 {
+            let drawn = (4..20).contains(&x) && y < 16;
+            assert_eq!(display[y * 128 + x], drawn as u8, "pixel at {}, {}", x, y);
+        }
+    }
+}
+
+#[test]
+fn assembler_encodes_what_the_decoder_reads() {
+    use crate::chip_controller::Instruction;
+    let program = crate::assembler::assemble(
+        ": main\n  loop\n    v0 += 1\n    while v0 != 8\n  again\n  jump main\n",
+        "test.8o",
+    )
+    .unwrap();
+    assert_eq!(
+        program.rom,
+        vec![0x70, 0x01, 0x40, 0x08, 0x12, 0x08, 0x12, 0x00, 0x12, 0x00]
+    );
+    assert_eq!(program.symbols.address("main"), Some(0x200));
+    for word in program.rom.chunks(2) {
+        let instruction = Instruction::from([word[0], word[1]]);
+        assert_eq!(<[u8; 2]>::from(&instruction), [word[0], word[1]]);
+    }
+    // Data in front of main is jumped over
+    let program = crate::assembler::assemble(
+        ": dot 0x80 : main i := dot sprite v0 v0 1 loop again",
+        "test.8o",
+    )
+    .unwrap();
+    assert_eq!(
+        program.rom,
+        vec![0x12, 0x03, 0x80, 0xA2, 0x02, 0xD0, 0x01, 0x12, 0x07]
+    );
+    assert!(crate::assembler::assemble(": start jump start", "test.8o").is_err());
+}
+
+#[test]
+fn symbols_name_addresses_and_operands() {
+    use crate::chip_controller::{Instruction, SymbolMap};
+    let symbols = SymbolMap::parse("label draw_player 2A4\nline 2A4 12 game.8o\n").unwrap();
+    assert_eq!(symbols.describe(0x2A8), "draw_player+4");
+    assert_eq!(symbols.describe(0x200), "0x200");
+    assert_eq!(
+        symbols.instruction(&Instruction::CALL(0x2A4)).trim(),
+        "CALL draw_player"
+    );
+    assert_eq!(symbols.line_address("src/game.8o", 10), Some((0x2A4, 12)));
+}
+
+#[test]
+fn coverage_marks_code_that_never_ran() {
+    use crate::chip_controller::{Rom, SymbolMap};
+    let bytes = vec![
+        0x30, 0x00, // SIREB 0 0
+        0x60, 0x01, // LDBR 0 1, always skipped
+        0x12, 0x04, // JP 204
+    ];
+    let mut controller = ChipController::new();
+    controller.set_rom(bytes.clone()).unwrap();
+    controller.enable_coverage();
+    controller.tick(Some(10)).unwrap();
+    let report = controller
+        .coverage_report(&Rom::from_bytes(bytes), &SymbolMap::new())
+        .unwrap();
+    assert!(report.starts_with("Code: 2 of 3 instructions executed (66.7%)"));
+    assert!(report.contains("#####   202-203:"));
+}
+
+#[test]
+fn profiler_attributes_instructions_to_routines() {
+    use crate::chip_controller::{SymbolMap, Weight};
+    let mut controller = ChipController::new();
+    controller
+        .set_rom(vec![
+            0x22, 0x04, // CALL 204
+            0x12, 0x00, // JP 200
+            0x60, 0x01, // LDBR 0 1
+            0x00, 0xEE, // RET
+        ])
+        .unwrap();
+    let symbols = SymbolMap::parse("label update 204\n").unwrap();
+    controller.enable_profiler(symbols);
+    controller.tick(Some(8)).unwrap();
+    assert_eq!(
+        controller.folded_stacks(Weight::Instructions).unwrap(),
+        "main 4\nmain;update 4\n"
+    );
+}
+
+#[test]
+fn quirks_decide_whether_sprites_wrap() {
+    use crate::chip_controller::Quirks;
+    let rom = vec![
+        0x60, 0x7E, // LDBR 0 7E, wraps to column 62
+        0x61, 0x00, // LDBR 1 0
+        0xF1, 0x29, // LDSI 1
+        0xD0, 0x15, // DRW 0 1 5
+        0x12, 0x08, // JP 208
+    ];
+    for (quirks, wrapped) in [(Quirks::default(), 0), (Quirks::XOCHIP, 1)] {
+        let mut controller = ChipController::new();
+        controller.set_quirks(quirks);
+        controller.set_rom(rom.clone()).unwrap();
+        controller.tick(Some(4)).unwrap();
+        let display = controller.get_display();
+        assert_eq!(display[62..64], [1, 1]);
+        assert_eq!(display[0..2], [wrapped, wrapped]);
+    }
+}
+
+#[test]
+fn analyzer_finds_quirks_and_self_modifying_code() {
+    use crate::chip_controller::{Analysis, Quirks, Rom};
+    let analysis = Analysis::new(&Rom::from_bytes(vec![
+        0xA2, 0x08, // LD3NI 208
+        0xF0, 0x55, // LDRRL 0, writes over the loop
+        0x82, 0x36, // SHR 2 3
+        0x00, 0xFF, // HIGH
+        0x12, 0x08, // JP 208
+    ]));
+    // Shifting v3 into v2 only makes sense without the shifting quirk of SCHIP
+    let mut quirks = Quirks::SCHIP;
+    quirks.shifting = false;
+    assert_eq!(analysis.quirks(), quirks);
+    let report = analysis.to_string();
+    assert!(report.contains("202: writes to code at 208"));
+    assert!(report.contains("204: 8XY6 with v2 != v3 depends on the shifting quirk"));
+    assert!(report.contains("Suggested quirks: custom (jumping clipping)"));
+
+    let analysis = Analysis::new(&Rom::from_bytes(vec![
+        0x62, 0x3E, // LDBR 2 3E, the sprite wraps around both edges
+        0xD2, 0x25, // DRW 2 2 5
+        0x61, 0x02, // LDBR 1 2
+        0xB1, 0x00, // JP3N 100
+    ]));
+    let mut quirks = Quirks::CHIP8;
+    quirks.clipping = false;
+    quirks.jumping = true;
+    assert_eq!(analysis.quirks(), quirks);
+}
+
+#[test]
+fn sprites_are_found_in_code_and_while_running() {
+    use crate::chip_controller::{Rom, SpriteFinder};
+    let bytes = vec![
+        0xA2, 0x08, // LD3NI 208
+        0xD0, 0x02, // DRW 0 0 2
+        0xF0, 0x1E, // ADDRI 0, only known while running
+        0xD0, 0x01, // DRW 0 0 1
+        0xFF, 0x81, // sprite
+    ];
+    let mut controller = ChipController::new();
+    controller.set_rom(bytes.clone()).unwrap();
+    controller.enable_sprites(SpriteFinder::scan(&Rom::from_bytes(bytes)));
+    controller.tick(Some(4)).unwrap();
+    let sheet = controller.sprite_sheet().unwrap().to_string();
+    assert_eq!(sheet, "208: 8x2\n████████\n█      █\n\n");
+}
+
+#[test]
+fn cheats_freeze_patch_and_find_values() {
+    use crate::chip_controller::{Cheats, Condition, RamSearch};
+    let cheats = Cheats::parse("[x] Lives: freeze 210 03\n[x] patch 202 60 09\n").unwrap();
+    assert_eq!(
+        cheats.to_string(),
+        "[x] Lives: freeze 210 03\n[x] patch 202 60 09\n"
+    );
+    let mut controller = ChipController::new();
+    controller.set_cheats(cheats);
+    controller
+        .set_rom(vec![
+            0x60, 0x01, // LDBR 0 1
+            0x60, 0x02, // LDBR 0 2, patched to LDBR 0 9
+            0xA2, 0x10, // LD3NI 210
+            0xF0, 0x55, // LDRRL 0, overwrites the frozen byte
+            0x12, 0x08, // JP 208
+        ])
+        .unwrap();
+    let mut search = RamSearch::new(controller.ram());
+    controller.tick(Some(4)).unwrap();
+    assert_eq!(controller.ram()[0x210], 3);
+    search.filter(controller.ram(), Condition::Unchanged);
+    search.filter(controller.ram(), Condition::Equals(0x60));
+    assert!(search.candidates().contains(&0x202));
+    controller.toggle_cheat(1);
+    assert_eq!(controller.ram()[0x203], 0x02);
+}
+
+#[test]
+fn patches_are_applied_and_checked() {
+    use crate::chip_controller::Patch;
+    let original = vec![0x60, 0x01, 0x12, 0x00, 0xAA, 0xBB];
+    let modified = vec![0x60, 0x05, 0x12, 0x00, 0xAA, 0xBB, 0xCC];
+    for bytes in [
+        Patch::ips(&original, &modified),
+        Patch::bps(&original, &modified),
+    ] {
+        let mut controller = ChipController::new();
+        controller.add_patch(Patch::new("fix", bytes));
+        controller.set_rom(original.clone()).unwrap();
+        assert_eq!(controller.rom(), &modified[..]);
+    }
+    let mut controller = ChipController::new();
+    controller.add_patch(Patch::new("fix.bps", Patch::bps(&original, &modified)));
+    let error = controller.set_rom(modified.clone()).unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "Can't apply fix.bps: it is meant for a ROM of 6 bytes, but this one has 7"
+    );
+    // A patched ROM of 2 bytes with a TargetRead of 1 and a TargetCopy of 3
+    let mut bps = b"BPS1".to_vec();
+    bps.extend([0x86, 0x82, 0x80, 0x81, 0x12, 0x8B, 0x80]);
+    bps.extend(crate::chip_controller::crc32(&original).to_le_bytes());
+    bps.extend([0; 4]);
+    bps.extend(crate::chip_controller::crc32(&bps).to_le_bytes());
+    let error = Patch::new("long.bps", bps).apply(&original).unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "Can't apply long.bps: an action writes past the 2 bytes of the patched ROM"
+    );
+}
+
+#[test]
+fn keys_are_held_and_released() {
+    let mut controller = ChipController::new();
+    controller
+        .set_rom(vec![
+            0x60, 0x05, // LDBR 0 5
+            0x61, 0x08, // LDBR 1 8
+            0xE0, 0x9E, // SKP 0
+            0x12, 0x04, // JP 204
+            0xE1, 0xA1, // SKNP 1
+            0x12, 0x08, // JP 208
+            0xF2, 0x0A, // LDKR 2
+            0xA3, 0x00, // LD3NI 300
+            0xF2, 0x55, // LDRRL 2
+            0x12, 0x12, // JP 212
+        ])
+        .unwrap();
+    controller.set_pressed_keys(1 << 5 | 1 << 8);
+    controller.tick(Some(7)).unwrap();
+    // Both keys are held, so it waits for 8 to be let go
+    assert_eq!(controller.pc(), 0x208);
+    controller.set_pressed_keys(1 << 5);
+    controller.tick(Some(5)).unwrap();
+    assert_eq!(controller.pc(), 0x212);
+    assert_eq!(controller.ram()[0x302], 8);
+}
+
+#[test]
+fn command_line_is_checked_and_seeds_repeat() {
+    use crate::chip_controller::Quirks;
+    use crate::cli::{Command, Error, Opt};
+    static COMMAND: Command = Command {
+        name: "test",
+        args: &["<ROM>", "[FRAMES]"],
+        about: "",
+        options: &[&[Opt {
+            name: "--patch",
+            value: Some("FILE"),
+            help: "",
+        }]],
+        run: |_| Ok(()),
+    };
+    let args =
+        |args: &[&str]| COMMAND.parse(&args.iter().map(|a| a.to_string()).collect::<Vec<_>>());
+    let parsed = args(&["a.ch8", "--patch", "x.ips", "20", "--patch", "y.bps"]).unwrap();
+    assert_eq!(parsed.values("--patch"), ["x.ips", "y.bps"]);
+    assert_eq!(parsed.parse_arg::<usize>(1).unwrap(), Some(20));
+    assert!(matches!(args(&[]), Err(Error::Usage(_))));
+    assert!(matches!(
+        args(&["a.ch8", "--seed", "1"]),
+        Err(Error::Usage(_))
+    ));
+    assert!(matches!(args(&["a.ch8", "--patch"]), Err(Error::Usage(_))));
+    assert!(matches!(args(&["a.ch8", "--help"]), Err(Error::Help(_))));
+    assert!(args(&["a.ch8", "x"])
+        .unwrap()
+        .parse_arg::<usize>(1)
+        .is_err());
+
+    let mut quirks = Quirks::CHIP8;
+    quirks.set("-clipping,jumping").unwrap();
+    assert!(!quirks.clipping && quirks.jumping && quirks.memory);
+    assert!(quirks.set("wrapping").is_err());
+
+    let random = |seed| {
+        let mut controller = ChipController::new();
+        controller.set_seed(seed);
+        controller
+            .set_rom(vec![0xC0, 0xFF, 0xA3, 0x00, 0xF0, 0x55]) // RND 0 FF, LD3NI 300, LDRRL 0
+            .unwrap();
+        controller.tick(Some(3)).unwrap();
+        controller.ram()[0x300]
+    };
+    assert_eq!(random(7), random(7));
+    assert!((0..8).any(|seed| random(seed) != random(7)));
+}
+
+#[test]
+fn config_sections_override_the_defaults() {
+    use crate::chip_controller::sha1;
+    use crate::config::Config;
+    assert_eq!(sha1(b"abc"), "a9993e364706816aba3e25717850c26c9cd0d89d");
+    let rom = sha1(&[0x12, 0x00]);
+    let config = Config::parse(&format!(
+        "freq = 700\ntheme = \"amber\"\n\n[roms.{}]\nname = \"Loop\"\nfreq = 1500\n",
+        rom
+    ))
+    .unwrap();
+    let options = |sha1: &str| config.options(sha1).unwrap();
+    assert_eq!(
+        options(&rom),
+        [("freq", "700"), ("theme", "amber"), ("freq", "1500")]
+            .map(|(name, value)| (name.to_owned(), value.to_owned()))
+    );
+    assert_eq!(options("0").len(), 2);
+    assert!(Config::parse("roms = 1").is_err());
+    assert!(Config::parse("freq = [1]").unwrap().options(&rom).is_err());
+
+    // A config file that was asked for has to exist
+    let args = ["a.ch8", "--config", "/missing/config.toml"].map(str::to_owned);
+    let args = crate::COMMANDS[0].parse(&args).unwrap();
+    assert!(crate::config(&args).is_err());
+    assert!(Config::load("/missing/config.toml").is_err());
+}
+
+#[test]
+fn reset_keeps_the_rom_and_states_are_restored() {
+    use crate::chip_controller::State;
+    let mut controller = ChipController::new();
+    // LD V0 5, ADD V0 1, LD I 300, LD [I] V0, JP 202
+    let rom = vec![0x60, 0x05, 0x70, 0x01, 0xA3, 0x00, 0xF0, 0x55, 0x12, 0x02];
+    controller.set_rom(rom.clone()).unwrap();
+    controller.tick(Some(5)).unwrap();
+    assert_eq!(controller.ram()[0x300], 6);
+    let bytes = controller.state().to_bytes();
+
+    controller.reset();
+    assert_eq!((controller.pc(), controller.ram()[0x300]), (0x200, 0));
+    assert_eq!(&controller.ram()[0x200..0x20A], &rom[..]);
+    controller.tick(Some(9)).unwrap();
+    assert_eq!(controller.ram()[0x300], 7);
+
+    controller.restore(&State::from_bytes(&bytes).unwrap());
+    assert_eq!((controller.pc(), controller.ram()[0x300]), (0x202, 6));
+    controller.tick(Some(4)).unwrap();
+    assert_eq!(controller.ram()[0x300], 7);
+    assert!(State::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+    assert!(State::from_bytes(b"CHIP8").is_err());
+    // A stack pointer past the stack, planes PLN can't select and a display of no known size
+    for (offset, byte) in [(29, 16), (30, 0x10), (100, 65)] {
+        let mut broken = bytes.clone();
+        broken[offset] = byte;
+        let error = State::from_bytes(&broken).err().unwrap();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+    }
+}
+
+#[test]
+fn browser_finds_roms_and_config_names_them() {
+    use crate::config::Config;
+    use crate::ui::Browser;
+    use std::fs;
+    use std::path::PathBuf;
+    let directory = std::env::temp_dir().join(format!("chip_8_browser_{}", std::process::id()));
+    fs::create_dir_all(directory.join("schip")).unwrap();
+    fs::create_dir_all(directory.join(".hidden")).unwrap();
+    for file in [
+        "b.ch8",
+        "a.CH8",
+        "schip/c.sc8",
+        "notes.txt",
+        ".hidden/d.ch8",
+    ] {
+        fs::write(directory.join(file), [0x12, 0x00]).unwrap();
+    }
+    let mut browser = Browser::scan(vec![directory.clone()]);
+    let found: Vec<PathBuf> = browser
+        .entries_mut()
+        .map(|entry| entry.path.clone())
+        .collect();
+    assert_eq!(
+        found,
+        ["a.CH8", "b.ch8", "schip/c.sc8"].map(|file| directory.join(file))
+    );
+    fs::remove_dir_all(&directory).unwrap();
+
+    let config =
+        Config::parse("rom_dirs = [\"~/roms\", \"/srv/roms\"]\n[roms.1234]\nname = \"Loop\"\n")
+            .unwrap();
+    let home = PathBuf::from(std::env::var_os("HOME").unwrap());
+    assert_eq!(
+        config.rom_dirs().unwrap(),
+        [home.join("roms"), PathBuf::from("/srv/roms")]
+    );
+    assert!(config.options("1234").unwrap().is_empty());
+    assert_eq!(config.name("1234"), Some("Loop"));
+    assert!(Config::parse("rom_dirs = 1").unwrap().rom_dirs().is_err());
+}
+
+#[test]
+fn database_configures_the_roms_it_knows() {
+    use crate::chip_controller::{sha1, Database, Quirks};
+    let rom = vec![0x12, 0x00];
+    let programs = format!(
+        r##"[{{"title": "Loop", "authors": ["Someone"], "roms": {{"{}": {{
+            "platforms": ["superchip"], "quirkyPlatforms": {{"superchip": {{"wrap": true}}}},
+            "keys": {{"up": 5, "a": 16}}, "colors": {{"pixels": ["#000000", "#FFFFFF"]}}
+        }}}}}}]"##,
+        sha1(&rom)
+    );
+    let hashes = format!(r#"{{"{}": 0}}"#, sha1(&rom));
+    let platforms = include_str!("chip_controller/database/platforms.json");
+    let database = Database::parse([&programs, &hashes, platforms]).unwrap();
+    assert_eq!(database.len(), 1);
+    assert!(database.program(&sha1(&[0x00])).is_none());
+
+    let mut controller = ChipController::new();
+    controller.set_database(database);
+    controller.set_rom(rom).unwrap();
+    let program = controller.program().unwrap();
+    assert_eq!(
+        (program.title.as_str(), program.platform.as_deref()),
+        ("Loop", Some("SUPER-CHIP 1.1"))
+    );
+    assert_eq!(
+        (program.tickrate, &program.keys[..]),
+        (Some(30), &[("up".to_owned(), 5)][..])
+    );
+    let mut quirks = Quirks::SCHIP;
+    quirks.clipping = false;
+    assert_eq!(controller.quirks(), quirks);
+    assert!(Database::parse(["{}", "{}", "[]"]).is_err());
+    assert_eq!(Database::bundled().program("0"), None);
+
+    // The IBM logo that most emulators are tried with first
+    let ibm: Vec<u8> = [
+        "00E0A22A600C6108D01F7009A239D01FA2487008D01F7004A257D01F7008A266D01F7008A275D01F1228",
+        "FF00FF003C003C003C003C00FF00FFFF00FF0038003F003F003800FF00FF8000E000E00080008000E000E000",
+        "80F800FC003E003F003B003900F800F8030007000F00BF00FB00F300E30043E000E000800080008000800",
+        "0E000E0",
+    ]
+    .concat()
+    .as_bytes()
+    .chunks(2)
+    .map(|digits| u8::from_str_radix(std::str::from_utf8(digits).unwrap(), 16).unwrap())
+    .collect();
+    let program = Database::bundled().program(&sha1(&ibm)).unwrap();
+    assert_eq!(program.title, "IBM Logo");
+    assert_eq!(program.quirks, Some(Quirks::CHIP8));
+}
+
+#[test]
+fn scheduled_keys_are_pressed_without_a_terminal() {
+    use crate::chip_controller::Schedule;
+    let mut controller = ChipController::new();
+    // loop: LD V1 K, LD I hex V1, DRW V0 V0 5, ADD V0 6, JP 200
+    let rom = vec![0xF1, 0x0A, 0xF1, 0x29, 0xD0, 0x05, 0x70, 0x06, 0x12, 0x00];
+    controller.set_rom(rom).unwrap();
+    let mut schedule = Schedule::default();
+    schedule.add("10-20:7").unwrap();
+    schedule.add("30:a").unwrap();
+    assert_eq!((schedule.keys(9), schedule.keys(20)), (0, 1 << 7));
+    assert!(schedule.add("20-10:7").is_err() && schedule.add("5:x").is_err());
+    controller.run_frames(60, 16, &schedule).unwrap();
+
+    let state = controller.state().to_string();
+    assert!(state.starts_with("PC 0200  I 0032"), "{}", state);
+    assert!(state.contains("V0-V7  0C 0A 00"), "{}", state);
+    assert!(state.contains("\nStack  \n"), "{}", state);
+    let screen: Vec<String> = controller.screen().lines().map(str::to_owned).collect();
+    assert_eq!((&screen[0][..12], &screen[6][6..18]), ("████", "████"));
+    let image = String::from_utf8(controller.image()).unwrap();
+    assert!(image.starts_with("P1\n64 32\n1 1 1 1 0 0"));
+
+    // CALL 202, CALL 204, JP 204
+    controller
+        .set_rom(vec![0x22, 0x02, 0x22, 0x04, 0x12, 0x04])
+        .unwrap();
+    controller.tick(Some(3)).unwrap();
+    let state = controller.state().to_string();
+    assert!(state.contains("\nStack  0200 0202\n"), "{}", state);
+}
+
+#[test]
+fn gdb_stub_resumes_threads_and_writes_binary_memory() {
+    use crate::chip_controller::SymbolMap;
+    use std::io::{Read, Write};
+    use std::net::TcpStream;
+    let port = 41_268;
+    let server = std::thread::spawn(move || {
+        let mut controller = ChipController::new();
+        // LDBR 0 1, LDBR 1 2, JP 204
+        controller
+            .set_rom(vec![0x60, 0x01, 0x61, 0x02, 0x12, 0x04])
+            .unwrap();
+        controller.serve_gdb(port, 600, SymbolMap::new()).unwrap();
+    });
+    let mut stream = (0..100)
+        .find_map(|_| {
+            std::thread::sleep(std::time::Duration::from_millis(10));
+            TcpStream::connect(("127.0.0.1", port)).ok()
+        })
+        .unwrap();
+    let mut request = |packet: &[u8]| {
+        let checksum = packet.iter().fold(0u8, |sum, b| sum.wrapping_add(*b));
+        let mut message = b"$".to_vec();
+        message.extend(packet);
+        message.extend(format!("#{:02x}", checksum).bytes());
+        stream.write_all(&message).unwrap();
+        let mut reply = Vec::new();
+        let mut byte = [0];
+        while reply.last() != Some(&b'#') {
+            stream.read_exact(&mut byte).unwrap();
+            if byte[0] != b'+' && (byte[0] != b'$' || !reply.is_empty()) {
+                reply.push(byte[0]);
+            }
+        }
+        stream.read_exact(&mut [0; 2]).unwrap();
+        reply.pop();
+        String::from_utf8(reply).unwrap()
+    };
+    assert_eq!(request(b"vCont?"), "vCont;c;C;s;S");
+    assert_eq!(request(b"vCont;s:1;c"), "S05");
+    assert_eq!(request(b"p11"), "0202");
+    assert_eq!(request(b"Z0,204,2"), "OK");
+    assert_eq!(request(b"vCont;c:1"), "T05swbreak:;");
+    assert_eq!(request(b"p0"), "01");
+    assert_eq!(request(b"X300,3:\x80\xff}\x5d"), "OK");
+    assert_eq!(request(b"m300,3"), "80ff7d");
+    stream.write_all(b"$k#6b").unwrap();
+    server.join().unwrap();
+}
+
+#[test]
+fn dap_server_answers_requests_and_stops_at_breakpoints() {
+    use serde_json::{json, Value};
+    use std::io::{BufRead, BufReader, Read, Write};
+    let (input, mut requests) = std::io::pipe().unwrap();
+    let (responses, output) = std::io::pipe().unwrap();
+    let mut responses = BufReader::new(responses);
+    let mut controller = ChipController::new();
+    // LDBR 0 1, LDBR 1 2, JP 204
+    controller
+        .set_rom(vec![0x60, 0x01, 0x61, 0x02, 0x12, 0x04])
+        .unwrap();
+    controller.connect_dap(BufReader::new(input), output);
+    // A broken message doesn't end the session
+    requests
+        .write_all(b"Content-Length: 5\r\n\r\n{oops")
+        .unwrap();
+    let rom = std::env::temp_dir().join(format!("chip_8_dap_{}.ch8", std::process::id()));
+    std::fs::write(&rom, vec![0; 0x10000 - 0x1ff]).unwrap();
+    let mut request = |seq: i64, command: &str, arguments: Value| {
+        let content =
+            json!({ "seq": seq, "type": "request", "command": command, "arguments": arguments });
+        let content = content.to_string();
+        write!(
+            requests,
+            "Content-Length: {}\r\n\r\n{}",
+            content.len(),
+            content
+        )
+        .unwrap();
+        for _ in 0..50 {
+            std::thread::sleep(std::time::Duration::from_millis(1));
+            controller.tick(None).unwrap();
+        }
+    };
+    request(1, "initialize", json!({}));
+    request(
+        2,
+        "setInstructionBreakpoints",
+        json!({ "breakpoints": [{ "instructionReference": "0x204" }, { "instructionReference": "x" }] }),
+    );
+    request(3, "configurationDone", json!({}));
+    request(4, "variables", json!({ "variablesReference": 1 }));
+    request(5, "evaluate", json!({}));
+    request(6, "launch", json!({ "program": rom }));
+    std::fs::remove_file(&rom).unwrap();
+    let mut message = || {
+        let mut length = 0;
+        loop {
+            let mut header = String::new();
+            responses.read_line(&mut header).unwrap();
+            match header.trim().strip_prefix("Content-Length:") {
+                Some(value) => length = value.trim().parse().unwrap(),
+                None if header.trim().is_empty() => break,
+                None => {}
+            }
+        }
+        let mut content = vec![0; length];
+        responses.read_exact(&mut content).unwrap();
+        serde_json::from_slice::<Value>(&content).unwrap()
+    };
+    let initialize = message();
+    assert_eq!(
+        (
+            initialize["command"].as_str(),
+            initialize["success"].as_bool()
+        ),
+        (Some("initialize"), Some(true))
+    );
+    assert_eq!(message()["event"], "initialized");
+    assert_eq!(
+        message()["body"]["breakpoints"],
+        json!([{ "verified": true }, { "verified": false }])
+    );
+    assert_eq!(message()["request_seq"], 3);
+    let stopped = message();
+    assert_eq!(
+        (
+            stopped["event"].as_str(),
+            stopped["body"]["reason"].as_str()
+        ),
+        (Some("stopped"), Some("breakpoint"))
+    );
+    let variables = message()["body"]["variables"].clone();
+    assert_eq!(
+        variables[1],
+        json!({ "name": "V1", "value": "0x02", "variablesReference": 0 })
+    );
+    assert_eq!(variables[17]["value"], "0x204");
+    let unsupported = message();
+    assert_eq!(
+        (
+            unsupported["success"].as_bool(),
+            unsupported["message"].as_str()
+        ),
+        (Some(false), Some("Unsupported request evaluate"))
+    );
+    let launch = message()["message"].as_str().unwrap().to_owned();
+    assert!(
+        launch.ends_with("only 65024 fit into the RAM"),
+        "{}",
+        launch
+    );
+    drop(controller);
+    assert_eq!(message()["event"], "terminated");
+}
+
+#[test]
+fn addresses_wrap_around_at_the_end_of_the_ram() {
+    use crate::chip_controller::State;
+    let mut controller = ChipController::new();
+    // LD4NI FFFF, AUDIO
+    controller
+        .set_rom(vec![0xF0, 0x00, 0xFF, 0xFF, 0xF0, 0x02])
+        .unwrap();
+    controller.tick(Some(2)).unwrap();
+    let mut bytes = controller.state().to_bytes();
+    // The PC follows the magic, the registers, the timers, sp, planes, pitch and I
+    bytes[34..36].copy_from_slice(&[0xFF, 0xFC]);
+    let ram = bytes.len() - 0x10000;
+    // SIREB 0 0 skips the LD4NI at FFFE, which ends at 0002
+    bytes[ram + 0xFFFC..].copy_from_slice(&[0x30, 0x00, 0xF0, 0x00]);
+    controller.restore(&State::from_bytes(&bytes).unwrap());
+    controller.tick(None).unwrap();
+    assert!(controller.state().to_string().starts_with("PC 0002"));
+}
+
+#[test]
+fn debugger_lists_instructions_with_labels_and_calls() {
+    use crate::chip_controller::SymbolMap;
+    use crate::ui::debugger::listing;
+    let mut controller = ChipController::new();
+    controller
+        .set_rom(vec![
+            0x22, 0x04, // CALL 204
+            0x12, 0x02, // JP 202
+            0xF0, 0x00, 0x03, 0x00, // LD4NI 300
+            0x00, 0xEE, // RET
+        ])
+        .unwrap();
+    let symbols = SymbolMap::parse("label main 200\nlabel draw 204\nlabel sprite 300\n").unwrap();
+    let rows = listing(controller.ram(), 0x200, 5, &symbols);
+    let text: Vec<&str> = rows.iter().map(|row| row.text.as_str()).collect();
+    assert_eq!(
+        text,
+        [
+            "main:",
+            "0200  22 04       CALL draw",
+            "0202  12 02       JP 202",
+            "draw:",
+            "0204  F0 00 03 00 LD4NI sprite",
+        ]
+    );
+    assert_eq!(rows[4].address, Some(0x204));
+    controller.tick(Some(1)).unwrap();
+    assert_eq!(controller.call_stack(), &[0x200]);
+    // The breakpoint at the RET is reached after the LD4NI
+    let breakpoints = std::iter::once(0x208).collect();
+    assert!(controller.tick_until(10, &breakpoints).unwrap());
+    assert_eq!(controller.pc(), 0x208);
+    assert!(!controller.tick_until(1, &breakpoints).unwrap());
+}
+
+#[test]
+fn keys_are_held_until_the_terminal_starts_repeating_them() {
+    use crate::chip_controller::ChipKey;
+    use crate::ui::keyboard::Keyboard;
+    use std::time::{Duration, Instant};
+    let mut keyboard = Keyboard::new();
+    keyboard.delay = Duration::from_millis(600);
+    let start = Instant::now();
+    let after = |ms| start + Duration::from_millis(ms);
+    keyboard.press(ChipKey::Five, start);
+    // A terminal waits about 500ms before it repeats a held key the first time
+    assert_eq!(keyboard.keys(after(500)), 1 << 5);
+    keyboard.press(ChipKey::Five, after(500));
+    assert_eq!(keyboard.keys(after(700)), 1 << 5);
+    // After that the repeats come quickly, so a missing one means the key was let go
+    assert_eq!(keyboard.keys(after(800)), 0);
+    keyboard.press(ChipKey::Five, after(900));
+    assert_eq!(keyboard.keys(after(1400)), 1 << 5);
+    assert_eq!(keyboard.keys(after(1600)), 0);
+}
+
+#[test]
+fn tapped_keys_are_released_after_the_hold() {
+    use crate::chip_controller::ChipKey;
+    use crate::ui::keyboard::Keyboard;
+    use std::time::{Duration, Instant};
+    let mut keyboard = Keyboard::new();
+    let start = Instant::now();
+    keyboard.press(ChipKey::A, start);
+    assert_eq!(keyboard.keys(start + Keyboard::HOLD), 1 << 0xA);
+    assert_eq!(
+        keyboard.keys(start + Keyboard::HOLD + Duration::from_millis(1)),
+        0
+    );
+}
+
+#[test]
+fn renderers_map_pixels_to_characters() {
+    use crate::ui::render::Renderer;
+    // A cell of the renderer with only the given pixels set, in the first plane
+    let character = |renderer: Renderer, lit: &[(usize, usize)]| {
+        let mut display = [0; 8];
+        for (x, y) in lit {
+            display[y * 2 + x] = 1;
+        }
+        renderer.render(&display, (2, 4))[0][0].character
+    };
+    assert_eq!(character(Renderer::Sextants, &[(0, 0)]), '\u{1FB00}');
+    assert_eq!(
+        character(Renderer::Sextants, &[(0, 0), (0, 1), (0, 2)]),
+        '▌'
+    );
+    // Sextants 2, 4 and 5 come after the left half, which the block skips
+    assert_eq!(
+        character(Renderer::Sextants, &[(1, 0), (1, 1), (0, 2)]),
+        '\u{1FB18}'
+    );
+    let all: Vec<(usize, usize)> = (0..6).map(|i| (i % 2, i / 2)).collect();
+    assert_eq!(character(Renderer::Sextants, &all), '█');
+    assert_eq!(character(Renderer::Sextants, &all[1..]), '\u{1FB3B}');
+    // Braille numbers its dots down the left column and puts the bottom row last
+    assert_eq!(character(Renderer::Braille, &[(0, 2)]), '⠄');
+    assert_eq!(character(Renderer::Braille, &[(1, 0)]), '⠈');
+    assert_eq!(character(Renderer::Braille, &[(0, 3), (1, 3)]), '⣀');
+    assert_eq!(character(Renderer::Braille, &[]), ' ');
+    let cell = Renderer::HalfBlocks.render(&[1, 2], (1, 2))[0][0];
+    assert_eq!(
+        (cell.character, cell.foreground, cell.background),
+        ('▀', 1, 2)
+    );
+}
+
+#[test]
+fn screen_draws_only_the_cells_that_changed() {
+    use crate::ui::render::{Renderer, Screen};
+    let mut screen = Screen::default();
+    let row = |lit: &[usize]| {
+        let mut display = [0; 16];
+        for x in lit {
+            display[*x] = 1;
+        }
+        Renderer::Blocks.render(&display, (16, 1))
+    };
+    let runs = screen.update(row(&[]));
+    assert_eq!((runs.len(), runs[0].0, runs[0].2.len()), (1, 0, 16));
+    assert!(screen.update(row(&[])).is_empty());
+    // Close changes are drawn as one run, far ones as two
+    let runs = screen.update(row(&[2, 6]));
+    assert_eq!((runs.len(), runs[0].0, runs[0].2.len()), (1, 2, 5));
+    let runs: Vec<(u16, usize)> = screen
+        .update(row(&[2, 6, 0, 15]))
+        .iter()
+        .map(|(x, _, cells)| (*x, cells.len()))
+        .collect();
+    assert_eq!(runs, [(0, 1), (15, 1)]);
+}
+
+#[test]
+fn themes_are_parsed_and_approximated_with_the_colours_of_the_terminal() {
+    use crate::ui::theme::{Depth, Theme};
+    use crossterm::style::Color;
+    assert_eq!(
+        Depth::TrueColor.color((1, 2, 3)),
+        Color::Rgb { r: 1, g: 2, b: 3 }
+    );
+    // Colours go to the cube of 256 colour terminals, greys to their grey ramp
+    assert_eq!(Depth::Ansi256.color((255, 0, 0)), Color::AnsiValue(196));
+    assert_eq!(Depth::Ansi256.color((0, 0, 0)), Color::AnsiValue(16));
+    assert_eq!(Depth::Ansi256.color((128, 128, 128)), Color::AnsiValue(244));
+    assert_eq!(Depth::Ansi16.color((250, 10, 10)), Color::Red);
+    assert_eq!(Depth::Ansi16.color((100, 100, 100)), Color::DarkGrey);
+
+    let amber = Theme::parse("Amber").unwrap();
+    assert_eq!(amber.colors[1], Some((0xFF, 0xB0, 0x00)));
+    let theme = Theme::parse("#000000, FFFFFF").unwrap();
+    assert_eq!(
+        theme.colors,
+        [
+            Some((0, 0, 0)),
+            Some((255, 255, 255)),
+            Theme::default().colors[2],
+            Theme::default().colors[3]
+        ]
+    );
+    for text in ["#FFFFFF", "#12345,#000000", "#000000,#GGGGGG", "a,b,c,d,e"] {
+        assert_eq!(Theme::parse(text), None, "{}", text);
+    }
+    // A pixel that fades out since one of the three frames is a quarter of the way to black
+    assert_eq!(
+        theme.color(0x11, Depth::TrueColor),
+        Color::Rgb {
+            r: 191,
+            g: 191,
+            b: 191
+        }
+    );
+    assert_eq!(Theme::default().color(1, Depth::TrueColor), Color::Reset);
+}
+
+#[test]
+fn phosphor_lets_erased_pixels_fade_out() {
+    use crate::ui::phosphor::{Mode, Phosphor};
+    let fade = |mode: Mode| {
+        let mut phosphor = Phosphor::new(mode);
+        phosphor.push(&[2, 1]);
+        (0..4)
+            .map(|_| {
+                phosphor.push(&[0, 1]);
+                phosphor.pixels(&[0, 1])[0]
+            })
+            .collect::<Vec<u8>>()
+    };
+    assert_eq!(fade(Mode::Off), [0, 0, 0, 0]);
+    assert_eq!(fade(Mode::Blend), [0x12, 0, 0, 0]);
+    // The age is counted above the plane bits until the pixel is gone after three frames
+    assert_eq!(fade(Mode::Decay), [0x12, 0x22, 0x32, 0]);
+
+    let mut phosphor = Phosphor::new(Mode::Decay);
+    phosphor.push(&[1, 0]);
+    // Drawing the pixel again ends the fading, a new resolution starts over
+    phosphor.push(&[0, 0]);
+    phosphor.push(&[3, 0]);
+    assert_eq!(phosphor.pixels(&[0, 0]), [3, 0]);
+    phosphor.push(&[0, 0, 0]);
+    assert_eq!(phosphor.pixels(&[0, 0, 0]), [0, 0, 0]);
+    assert_eq!(phosphor.pixels(&[1]), [1]);
+}
+
+#[test]
+fn keymaps_are_parsed_and_presets_cover_the_keypad() {
+    use crate::chip_controller::ChipKey;
+    use crate::ui::keymap::Keymap;
+    use crossterm::event::KeyCode;
+    let char = KeyCode::Char;
+    let qwerty = Keymap::preset("qwerty").unwrap();
+    assert_eq!(qwerty, Keymap::default());
+    assert_eq!(
+        ['1', '4', 'q', 'V', 'x'].map(|c| qwerty.key(char(c))),
+        [
+            ChipKey::One,
+            ChipKey::C,
+            ChipKey::Four,
+            ChipKey::F,
+            ChipKey::Zero
+        ]
+        .map(Some)
+    );
+    assert_eq!(qwerty.key(char('0')), None);
+    assert_eq!(
+        Keymap::preset("hex").unwrap().key(char('a')),
+        Some(ChipKey::A)
+    );
+    let azerty = Keymap::preset("azerty").unwrap();
+    assert_eq!(
+        (azerty.key(char('é')), azerty.key(char('a'))),
+        (Some(ChipKey::Two), Some(ChipKey::Four))
+    );
+    let arrows = Keymap::preset("arrows").unwrap();
+    assert_eq!(arrows.key(KeyCode::Left), Some(ChipKey::Four));
+    assert!(Keymap::preset("dvorak").is_none());
+
+    let mut keymap = Keymap::parse("# Pong\n\n1: w up\nC: S DOWN\n").unwrap();
+    assert_eq!(keymap.key(KeyCode::Up), Some(ChipKey::One));
+    assert_eq!(keymap.key(char('s')), Some(ChipKey::C));
+    assert_eq!(keymap.key(KeyCode::Down), Some(ChipKey::C));
+    assert_eq!(keymap.key(char('1')), None);
+    for text in ["g: w", "10: w", "5 w", "5: upp"] {
+        assert!(Keymap::parse(text).is_err(), "{}", text);
+    }
+    // A button of the database takes the key over from the keymap
+    keymap.bind("up", ChipKey::Five);
+    keymap.bind("a", ChipKey::Six);
+    assert_eq!(keymap.key(KeyCode::Up), Some(ChipKey::Five));
+    assert_eq!(keymap.key(char(' ')), Some(ChipKey::Six));
+}
+
+#[test]
+fn hex_panel_goes_to_addresses_searches_and_edits_bytes() {
+    use crate::ui::hex::HexView;
+    use crate::ui::keymap::Keymap;
+    use crossterm::event::KeyCode;
+    let mut controller = ChipController::new();
+    controller
+        .set_rom(vec![0xA2, 0x08, 0x12, 0x00, 0xDE, 0xAD, 0xBE, 0xEF])
+        .unwrap();
+    let (mut hex, mut paused) = (HexView::new(), false);
+    let mut type_keys = |hex: &mut HexView, controller: &mut ChipController, keys: &str| {
+        for c in keys.chars() {
+            let key = match c {
+                '\n' => KeyCode::Enter,
+                '\t' => KeyCode::Tab,
+                c => KeyCode::Char(c),
+            };
+            assert!(hex.handle(key, controller, &mut paused, &Keymap::default()));
+        }
+    };
+    let drawn = |hex: &mut HexView, controller: &ChipController| {
+        let mut output = Vec::new();
+        hex.draw(&mut output, controller, 0, false).unwrap();
+        String::from_utf8(output).unwrap()
+    };
+    type_keys(&mut hex, &mut controller, "\t/be ef\n");
+    assert!(drawn(&mut hex, &controller).contains("Found BE EF at 0206"));
+    // The next match wraps around the RAM back to the same one
+    type_keys(&mut hex, &mut controller, "n");
+    assert!(drawn(&mut hex, &controller).contains("Found BE EF at 0206"));
+    type_keys(&mut hex, &mut controller, "/a\n");
+    assert!(drawn(&mut hex, &controller).contains("a are no bytes"));
+
+    type_keys(&mut hex, &mut controller, "g800\n");
+    let panel = drawn(&mut hex, &controller);
+    assert!(panel.contains("0800  ") && !panel.contains("0200  "));
+    // Bytes are only typed over while the chip is paused
+    type_keys(&mut hex, &mut controller, " c3");
+    assert_eq!(controller.ram()[0x800..0x802], [0xC3, 0x00]);
+    // While the chip runs the keys of the keymap are left to the game
+    type_keys(&mut hex, &mut controller, " ");
+    let keymap = Keymap::default();
+    assert!(!hex.handle(KeyCode::Char('x'), &mut controller, &mut paused, &keymap));
+    assert!(hex.handle(KeyCode::Char('n'), &mut controller, &mut paused, &keymap));
+}
+
+#[test]
+fn crashing_roms_stop_with_an_error() {
+    use crate::cli::Error;
+    let mut controller = ChipController::new();
+    controller.set_rom(vec![0x00, 0xE0, 0x00, 0x10]).unwrap();
+    let crash = controller.tick(Some(3)).unwrap_err();
+    assert_eq!(crash.to_string(), "Illegal instruction at 202");
+    assert_eq!(controller.pc(), 0x202);
+    controller.set_rom(vec![0x00, 0xEE]).unwrap();
+    let crash = controller.tick(None).unwrap_err();
+    assert_eq!(crash.to_string(), "Stack underflow at 200");
+
+    let path = std::env::temp_dir().join(format!("chip_8_crash_{}.ch8", std::process::id()));
+    std::fs::write(&path, [0x00, 0xEE]).unwrap();
+    let args: Vec<String> = [path.to_str().unwrap(), "1", "--no-config", "--no-database"]
+        .iter()
+        .map(|arg| arg.to_string())
+        .collect();
+    let command = crate::COMMANDS.iter().find(|c| c.name == "test").unwrap();
+    let result = crate::test(&command.parse(&args).unwrap());
+    std::fs::remove_file(&path).unwrap();
+    let error = result.unwrap_err();
+    assert!(matches!(error, Error::Crashed(_)));
+    assert_eq!(error.code(), 3);
+    assert_eq!(error.to_string(), "Stack underflow at 200");
+}
+
+#[test]
+fn roms_have_to_fit_into_the_ram() {
+    let mut controller = ChipController::new();
+    controller.set_rom(vec![0x12, 0x00]).unwrap();
+    let error = controller.set_rom(vec![0; 0x10000 - 0x1ff]).unwrap_err();
+    assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+    // The previous ROM stays loaded
+    assert_eq!(controller.rom(), &[0x12, 0x00]);
+    controller.set_rom(vec![0; 0x10000 - 0x200]).unwrap();
+    assert_eq!(controller.ram()[0xffff], 0);
+}
+
+#[test]
+fn headless_commands_run_at_the_given_freq() {
+    let path = std::env::temp_dir().join(format!("chip_8_freq_{}.ch8", std::process::id()));
+    std::fs::write(&path, [0x12, 0x00]).unwrap();
+    let command = crate::COMMANDS
+        .iter()
+        .find(|c| c.name == "profile")
+        .unwrap();
+    let load = |extra: &[&str]| {
+        let mut args = vec![path.to_str().unwrap(), "--no-config", "--no-database"];
+        args.extend(extra);
+        let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+        crate::load(&command.parse(&args).unwrap()).map(|(_, freq)| freq)
+    };
+    let default = load(&[]);
+    let given = load(&["--freq", "120"]);
+    let invalid = load(&["--freq", "fast"]);
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(default.unwrap(), 1000);
+    assert_eq!(given.unwrap(), 120);
+    assert!(invalid.is_err());
 }
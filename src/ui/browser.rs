@@ -0,0 +1,285 @@
+use super::super::chip_controller::{sha1, Analysis, Platform, Rom};
+use crossterm::{
+    cursor::{Hide, MoveTo, Show},
+    event::{read, Event, KeyCode, KeyEventKind, KeyModifiers},
+    execute, queue,
+    style::{Attribute, Print, SetAttribute},
+    terminal::{
+        disable_raw_mode, enable_raw_mode, size, Clear, ClearType, EnterAlternateScreen,
+        LeaveAlternateScreen, SetTitle,
+    },
+};
+use std::fs;
+use std::io::{stdout, Result as crossResult, Stdout, Write};
+use std::path::{Path, PathBuf};
+
+/// The extensions of the files that are listed
+const EXTENSIONS: [&str; 3] = ["ch8", "sc8", "xo8"];
+/// The rows below the list for the selected ROM and the help
+const DETAILS: u16 = 7;
+
+/// A ROM file with what is known about it
+pub(crate) struct Entry {
+    pub path: PathBuf,
+    /// The path relative to the directory it was found in
+    name: String,
+    size: usize,
+    sha1: String,
+    platform: Platform,
+    pub title: Option<String>,
+}
+
+impl Entry {
+    fn read(path: &Path, directory: &Path) -> Option<Entry> {
+        let bytes = fs::read(path).ok()?;
+        let name = path.strip_prefix(directory).unwrap_or(path);
+        Some(Entry {
+            path: path.to_owned(),
+            name: name.display().to_string(),
+            size: bytes.len(),
+            sha1: sha1(&bytes),
+            platform: Analysis::new(&Rom::from_bytes(bytes)).platform(),
+            title: None,
+        })
+    }
+
+    pub fn sha1(&self) -> &str {
+        &self.sha1
+    }
+}
+
+/// A list of the ROMs in some directories to pick one to play
+///
+/// Typing searches the file names and titles, Tab shows only the ROMs of a platform, the arrow
+/// and page keys move the selection, Enter plays the selected ROM and Escape quits.
+pub(crate) struct Browser {
+    directories: Vec<PathBuf>,
+    entries: Vec<Entry>,
+    search: String,
+    /// Only ROMs of this platform are shown if there is one
+    platform: Option<Platform>,
+    /// The index of the selected ROM among the shown ones
+    selected: usize,
+    /// The first shown row of the list
+    top: usize,
+    /// Shown instead of the help, like why the last ROM couldn't be played
+    pub message: String,
+    output: Stdout,
+}
+
+impl Browser {
+    /// Lists the ROMs in the directories and all directories in them, hidden ones are skipped
+    pub fn scan(directories: Vec<PathBuf>) -> Browser {
+        let mut entries = Vec::new();
+        for directory in &directories {
+            let mut found = Vec::new();
+            find(directory, &mut found);
+            found.sort();
+            entries.extend(found.iter().filter_map(|path| Entry::read(path, directory)));
+        }
+        Browser {
+            directories,
+            entries,
+            search: String::new(),
+            platform: None,
+            selected: 0,
+            top: 0,
+            message: String::new(),
+            output: stdout(),
+        }
+    }
+
+    pub fn entries_mut(&mut self) -> impl Iterator<Item = &mut Entry> {
+        self.entries.iter_mut()
+    }
+
+    /// Shows the list until a ROM is chosen, or returns nothing if the browser is quit
+    pub fn choose(&mut self) -> Option<PathBuf> {
+        enable_raw_mode().unwrap();
+        execute!(
+            self.output,
+            EnterAlternateScreen,
+            Hide,
+            SetTitle("Chip 8 ROMs")
+        )
+        .unwrap();
+        let chosen = loop {
+            self.draw().unwrap();
+            let key = match read().unwrap() {
+                Event::Key(key) if key.kind != KeyEventKind::Release => key,
+                _ => continue,
+            };
+            let shown = self.shown().len();
+            match key.code {
+                KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => break None,
+                KeyCode::Esc if self.search.is_empty() => break None,
+                KeyCode::Esc => self.search.clear(),
+                KeyCode::Enter => match self.shown().get(self.selected) {
+                    Some(entry) => break Some(entry.path.clone()),
+                    None => continue,
+                },
+                KeyCode::Up => self.selected = self.selected.saturating_sub(1),
+                KeyCode::Down => self.selected += 1,
+                KeyCode::PageUp => self.selected = self.selected.saturating_sub(self.rows()),
+                KeyCode::PageDown => self.selected += self.rows(),
+                KeyCode::Home => self.selected = 0,
+                KeyCode::End => self.selected = shown,
+                KeyCode::Tab => {
+                    self.platform = match self.platform {
+                        None => Some(Platform::Chip8),
+                        Some(Platform::Chip8) => Some(Platform::SChip),
+                        Some(Platform::SChip) => Some(Platform::XoChip),
+                        Some(Platform::XoChip) => None,
+                    };
+                    self.selected = 0;
+                }
+                KeyCode::Backspace => {
+                    self.search.pop();
+                    self.selected = 0;
+                }
+                KeyCode::Char(c) => {
+                    self.search.push(c);
+                    self.selected = 0;
+                }
+                _ => {}
+            }
+            // The message is about the last ROM, so it goes away with the next key
+            self.message.clear();
+        };
+        execute!(self.output, LeaveAlternateScreen, Show).unwrap();
+        disable_raw_mode().unwrap();
+        chosen
+    }
+
+    /// The ROMs that match the search and the platform
+    fn shown(&self) -> Vec<&Entry> {
+        let search = self.search.to_lowercase();
+        self.entries
+            .iter()
+            .filter(|entry| {
+                self.platform
+                    .is_none_or(|platform| entry.platform == platform)
+            })
+            .filter(|entry| {
+                entry.name.to_lowercase().contains(&search)
+                    || entry
+                        .title
+                        .as_ref()
+                        .is_some_and(|title| title.to_lowercase().contains(&search))
+            })
+            .collect()
+    }
+
+    /// The rows of the list
+    fn rows(&self) -> usize {
+        let (_, height) = size().unwrap_or((80, 24));
+        height.saturating_sub(DETAILS + 2).max(1) as usize
+    }
+
+    fn draw(&mut self) -> crossResult<()> {
+        let (width, _) = size().unwrap_or((80, 24));
+        let (width, rows) = (width as usize, self.rows());
+        let shown = self.shown();
+        let selected = self.selected.min(shown.len().saturating_sub(1));
+        // The list scrolls just enough to show the selected ROM
+        let top = self
+            .top
+            .min(selected)
+            .max((selected + 1).saturating_sub(rows));
+        let platform = match self.platform {
+            Some(platform) => platform.to_string(),
+            None => "all".to_owned(),
+        };
+        let mut lines = vec![
+            format!(
+                "Search: {}_  Platform: {} ({} of {} ROMs)",
+                self.search,
+                platform,
+                shown.len(),
+                self.entries.len()
+            ),
+            String::new(),
+        ];
+        for (index, entry) in shown.iter().enumerate().skip(top).take(rows) {
+            let marker = if index == selected { '>' } else { ' ' };
+            let title = entry.title.as_deref().unwrap_or("");
+            lines.push(format!(
+                "{} {:30} {:30} {}",
+                marker, entry.name, title, entry.platform
+            ));
+        }
+        if shown.is_empty() {
+            let directories: Vec<String> = self
+                .directories
+                .iter()
+                .map(|directory| directory.display().to_string())
+                .collect();
+            lines.push(match self.entries.is_empty() {
+                true => format!("There are no ROMs in {}", directories.join(", ")),
+                false => "No ROM matches".to_owned(),
+            });
+        }
+        lines.resize(rows + 2, String::new());
+        if let Some(entry) = shown.get(selected) {
+            lines.push(String::new());
+            lines.push(format!(
+                "Title:    {}",
+                entry.title.as_deref().unwrap_or("-")
+            ));
+            lines.push(format!("File:     {}", entry.path.display()));
+            lines.push(format!("Size:     {} bytes", entry.size));
+            lines.push(format!("Platform: {}", entry.platform));
+            lines.push(format!("SHA-1:    {}", entry.sha1));
+        }
+        lines.resize(rows + 1 + DETAILS as usize, String::new());
+        lines.push(match self.message.is_empty() {
+            true => {
+                "Type to search, Tab filters the platform, Enter plays, Escape quits".to_owned()
+            }
+            false => self.message.clone(),
+        });
+        let (selected_row, selected_line) = (2 + selected - top, !shown.is_empty());
+        self.top = top;
+        self.selected = selected;
+        queue!(self.output, Clear(ClearType::All))?;
+        for (row, line) in lines.iter().enumerate() {
+            let line: String = line.chars().take(width).collect();
+            queue!(self.output, MoveTo(0, row as u16))?;
+            if row == selected_row && selected_line {
+                queue!(
+                    self.output,
+                    SetAttribute(Attribute::Reverse),
+                    Print(line),
+                    SetAttribute(Attribute::Reset)
+                )?;
+            } else {
+                queue!(self.output, Print(line))?;
+            }
+        }
+        self.output.flush()
+    }
+}
+
+/// Adds the ROMs in a directory and the directories in it to `found`
+fn find(directory: &Path, found: &mut Vec<PathBuf>) {
+    let entries = match fs::read_dir(directory) {
+        Ok(entries) => entries,
+        Err(_) => return,
+    };
+    for entry in entries.filter_map(Result::ok) {
+        let path = entry.path();
+        if entry.file_name().to_string_lossy().starts_with('.') {
+            continue;
+        }
+        match entry.file_type() {
+            Ok(kind) if kind.is_dir() => find(&path, found),
+            Ok(_) => {
+                let extension = path.extension().and_then(|e| e.to_str()).unwrap_or("");
+                if EXTENSIONS.contains(&extension.to_lowercase().as_str()) {
+                    found.push(path);
+                }
+            }
+            Err(_) => {}
+        }
+    }
+}
@@ -0,0 +1,194 @@
+use super::super::chip_controller::{ChipController, Instruction, SymbolMap};
+use crossterm::{
+    cursor::MoveTo,
+    event::KeyCode,
+    queue,
+    style::{Attribute, Print, SetAttribute},
+    terminal::{Clear, ClearType},
+};
+use std::collections::BTreeSet;
+use std::io::{Result as crossResult, Write};
+
+/// Rows of the listing that are shown at once
+const ROWS: u16 = 16;
+/// The columns a row takes, like `*> 0200  A2 1E       LD3NI sprite`
+pub(super) const WIDTH: u16 = 44;
+/// The rows, the call stack line and a blank line above them
+pub(super) const HEIGHT: u16 = ROWS + 2;
+/// The listing scrolls on before PC reaches its last rows, so the next instructions are seen
+const LOOKAHEAD: usize = 3;
+
+/// A row of the listing, either a label or an instruction
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) struct Row {
+    /// Where the instruction starts, labels have none
+    pub address: Option<u16>,
+    pub text: String,
+}
+
+/// A panel showing the instructions around PC as they are in RAM, the labels of the symbol map
+/// and the calls on the stack
+///
+/// F2 shows it. The listing follows PC, it only scrolls when PC leaves it or comes close to
+/// its end, so loops stay in place while they run.
+///
+/// `j` and `k` select the next and previous instruction, `b` sets or removes a breakpoint at the
+/// selected one or at PC and `i` pauses the chip and executes a single instruction. The chip
+/// pauses when it reaches a breakpoint.
+pub(super) struct DebuggerView {
+    pub(super) visible: bool,
+    pub(super) symbols: SymbolMap,
+    pub(super) breakpoints: BTreeSet<u16>,
+    /// The address of the first shown instruction
+    top: u16,
+    /// The instruction chosen with `j` and `k`, the one at PC if there is none
+    selected: Option<u16>,
+    /// The addresses of the instructions as they were drawn last
+    shown: Vec<u16>,
+}
+
+impl DebuggerView {
+    pub fn new() -> DebuggerView {
+        DebuggerView {
+            visible: false,
+            symbols: SymbolMap::new(),
+            breakpoints: BTreeSet::new(),
+            top: 0x200,
+            selected: None,
+            shown: Vec::new(),
+        }
+    }
+
+    /// Handles a key if it is meant for the panel and returns whether it was
+    pub fn handle(&mut self, key: KeyCode, chip: &mut ChipController, paused: &mut bool) -> bool {
+        if !self.visible {
+            return false;
+        }
+        let selected = self.selected.unwrap_or_else(|| chip.pc());
+        match key {
+            KeyCode::Char('j') => {
+                let length = chip.instruction_at(selected).length() as u16;
+                self.selected = Some(selected.wrapping_add(length));
+            }
+            KeyCode::Char('k') => {
+                let previous = self.shown.iter().rev().find(|a| **a < selected);
+                self.selected = Some(*previous.unwrap_or(&selected));
+            }
+            KeyCode::Char('b') => {
+                if !self.breakpoints.remove(&selected) {
+                    self.breakpoints.insert(selected);
+                }
+            }
+            KeyCode::Char('i') => {
+                *paused = true;
+                self.selected = None;
+                // A crash leaves PC at the instruction, where the listing shows it
+                chip.tick(Some(1)).ok();
+            }
+            _ => return false,
+        }
+        true
+    }
+
+    /// Draws the panel at column `x` starting at row `y` of the terminal
+    pub fn draw(
+        &mut self,
+        output: &mut impl Write,
+        chip: &ChipController,
+        (x, y): (u16, u16),
+    ) -> crossResult<()> {
+        let (ram, pc) = (chip.ram(), chip.pc());
+        let followed = self.selected.unwrap_or(pc);
+        let mut rows = listing(ram, self.top, ROWS as usize, &self.symbols);
+        let shown = rows.iter().position(|row| row.address == Some(followed));
+        if !matches!(shown, Some(row) if row + LOOKAHEAD < rows.len()) {
+            self.top = followed;
+            rows = listing(ram, followed, ROWS as usize, &self.symbols);
+        }
+        self.shown = rows.iter().filter_map(|row| row.address).collect();
+        for (row, line) in rows.iter().enumerate() {
+            let breakpoint = line
+                .address
+                .is_some_and(|address| self.breakpoints.contains(&address));
+            let text = format!(
+                "{}{} {}",
+                if breakpoint { '*' } else { ' ' },
+                if line.address == Some(pc) { '>' } else { ' ' },
+                line.text
+            );
+            queue!(output, MoveTo(x, y + 1 + row as u16))?;
+            if line.address == Some(followed) {
+                queue!(output, SetAttribute(Attribute::Reverse))?;
+            }
+            queue!(
+                output,
+                Print(format!("{:1$.1$}", text, WIDTH as usize)),
+                SetAttribute(Attribute::Reset)
+            )?;
+        }
+        let calls: Vec<String> = chip
+            .call_stack()
+            .iter()
+            .map(|address| self.symbols.describe(*address))
+            .collect();
+        let calls = format!("Calls: {}", calls.join(" > "));
+        queue!(
+            output,
+            MoveTo(x, y + HEIGHT - 1),
+            Print(format!("{:1$.1$}", calls, WIDTH as usize))
+        )?;
+        if x == 0 {
+            queue!(output, Clear(ClearType::UntilNewLine))?;
+        }
+        Ok(())
+    }
+}
+
+/// `rows` rows of instructions decoded from RAM starting at `start`, with a row for each label
+/// before the instruction it names
+pub(crate) fn listing(ram: &[u8], start: u16, rows: usize, symbols: &SymbolMap) -> Vec<Row> {
+    let byte = |address: u16| ram[address as usize % ram.len()];
+    let mut listing = Vec::new();
+    let mut address = start;
+    while listing.len() < rows {
+        if let Some(label) = symbols.label(address) {
+            listing.push(Row {
+                address: None,
+                text: format!("{}:", label),
+            });
+            if listing.len() == rows {
+                break;
+            }
+        }
+        let instruction = Instruction::from([byte(address), byte(address.wrapping_add(1))]);
+        let length = instruction.length() as u16;
+        let bytes: Vec<String> = (0..length)
+            .map(|i| format!("{:02X}", byte(address.wrapping_add(i))))
+            .collect();
+        let text = match instruction {
+            Instruction::LD4NI => {
+                let long = u16::from_be_bytes([
+                    byte(address.wrapping_add(2)),
+                    byte(address.wrapping_add(3)),
+                ]);
+                match symbols.label(long) {
+                    Some(label) => format!("LD4NI {}", label),
+                    None => format!("LD4NI {:X}", long),
+                }
+            }
+            _ => symbols.instruction(&instruction),
+        };
+        let text: Vec<&str> = text.split_whitespace().collect();
+        listing.push(Row {
+            address: Some(address),
+            text: format!(
+                "{:04X}  {:<11} {}",
+                address,
+                bytes.join(" "),
+                text.join(" ")
+            ),
+        });
+        address = address.wrapping_add(length);
+    }
+    listing
+}
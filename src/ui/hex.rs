@@ -0,0 +1,420 @@
+use super::super::chip_controller::{
+    Cheat, ChipController, Condition, Effect, RamSearch, FONT_END,
+};
+use super::keymap::Keymap;
+use crossterm::{
+    cursor::MoveTo,
+    event::KeyCode,
+    queue,
+    style::{
+        Attribute, Color, Print, ResetColor, SetAttribute, SetBackgroundColor, SetForegroundColor,
+    },
+    terminal::{Clear, ClearType},
+};
+use std::io::{Result as crossResult, Write};
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+/// Rows of 16 bytes that are shown at once
+const ROWS: u16 = 16;
+/// The columns a row takes, like `0200  A2 08 ... 00  |........|`
+pub(super) const WIDTH: u16 = 75;
+/// The rows, the status line and a blank line above them
+pub(super) const HEIGHT: u16 = ROWS + 2;
+/// How long a changed byte stays coloured
+const CHANGE_FADE: Duration = Duration::from_secs(1);
+/// I is highlighted for the biggest sprite that can be drawn from it
+const INDEX_LENGTH: u16 = 32;
+
+/// What the typed keys are used for
+enum Input {
+    Keys,
+    Goto(String),
+    Search(String),
+    Equals(String),
+}
+
+/// A panel below the display showing the RAM as hex and ASCII, bytes can be edited while the
+/// chip is paused
+///
+/// Tab shows it, the arrow keys and page keys move the cursor, `g` jumps to an address, `/`
+/// searches for bytes like `A2 08` and `n` finds the next match. Space pauses the chip and hex
+/// digits overwrite the byte at the cursor while it is paused.
+///
+/// `s` starts a RAM search with every address as a candidate, which `<` (decreased), `>`
+/// (increased), `~` (changed), `.` (unchanged) and `=` (equals a value) narrow down compared to
+/// the last step and `]` moves to the next candidate. `x` freezes the byte at the cursor to its
+/// value or unfreezes it, which is saved to the cheat file.
+///
+/// Keys the keymap binds still reach the game while it runs, with the default keymap `s`, `x`
+/// and the hex digits only work for the panel while the chip is paused.
+pub(crate) struct HexView {
+    pub(super) visible: bool,
+    cursor: u16,
+    /// The address of the first shown row
+    top: u16,
+    input: Input,
+    /// The high nibble of a byte that is being typed
+    nibble: Option<u8>,
+    pattern: Vec<u8>,
+    message: String,
+    /// The shown bytes when they were drawn last and when they changed
+    shown: Vec<u8>,
+    shown_top: Option<u16>,
+    changed: Vec<Option<Instant>>,
+    search: Option<RamSearch>,
+    /// Where cheats are saved when a byte is frozen
+    pub(super) cheat_file: Option<PathBuf>,
+}
+
+impl HexView {
+    pub fn new() -> HexView {
+        HexView {
+            visible: false,
+            cursor: 0x200,
+            top: 0x200,
+            input: Input::Keys,
+            nibble: None,
+            pattern: Vec::new(),
+            message: String::new(),
+            shown: Vec::new(),
+            shown_top: None,
+            changed: vec![None; (ROWS * 16) as usize],
+            search: None,
+            cheat_file: None,
+        }
+    }
+
+    /// Handles a key if it is meant for the panel and returns whether it was
+    pub fn handle(
+        &mut self,
+        key: KeyCode,
+        chip: &mut ChipController,
+        paused: &mut bool,
+        keymap: &Keymap,
+    ) -> bool {
+        if key == KeyCode::Tab {
+            self.visible = !self.visible;
+            return true;
+        }
+        if !self.visible {
+            return false;
+        }
+        match &mut self.input {
+            Input::Goto(text) | Input::Search(text) | Input::Equals(text) => {
+                match key {
+                    KeyCode::Char(c) if c.is_ascii_hexdigit() || c == ' ' => text.push(c),
+                    KeyCode::Backspace => {
+                        text.pop();
+                    }
+                    KeyCode::Enter => self.submit(chip),
+                    KeyCode::Esc => self.input = Input::Keys,
+                    _ => {}
+                }
+                return true;
+            }
+            Input::Keys => {}
+        }
+        if !*paused && keymap.key(key).is_some() {
+            return false;
+        }
+        match key {
+            KeyCode::Up => self.move_cursor(-16),
+            KeyCode::Down => self.move_cursor(16),
+            KeyCode::Left => self.move_cursor(-1),
+            KeyCode::Right => self.move_cursor(1),
+            KeyCode::PageUp => self.move_cursor(-16 * ROWS as i32),
+            KeyCode::PageDown => self.move_cursor(16 * ROWS as i32),
+            KeyCode::Char(' ') => {
+                *paused = !*paused;
+                self.nibble = None;
+            }
+            KeyCode::Char('g') => self.input = Input::Goto(String::new()),
+            KeyCode::Char('/') => self.input = Input::Search(String::new()),
+            KeyCode::Char('n') => self.search(chip, self.cursor.wrapping_add(1)),
+            KeyCode::Char('s') => {
+                self.search = Some(RamSearch::new(chip.ram()));
+                self.message = "Every address is a candidate".to_owned();
+            }
+            KeyCode::Char('<') => self.narrow(chip, Condition::Decreased),
+            KeyCode::Char('>') => self.narrow(chip, Condition::Increased),
+            KeyCode::Char('~') => self.narrow(chip, Condition::Changed),
+            KeyCode::Char('.') => self.narrow(chip, Condition::Unchanged),
+            KeyCode::Char('=') => self.input = Input::Equals(String::new()),
+            KeyCode::Char(']') => self.next_candidate(),
+            KeyCode::Char('x') => self.freeze(chip),
+            KeyCode::Esc if self.nibble.is_some() => self.nibble = None,
+            KeyCode::Char(c) if *paused && c.is_ascii_hexdigit() => {
+                let digit = c.to_digit(16).unwrap() as u8;
+                match self.nibble.take() {
+                    None => self.nibble = Some(digit),
+                    Some(high) => {
+                        chip.poke(self.cursor, high << 4 | digit);
+                        self.move_cursor(1);
+                    }
+                }
+            }
+            // Everything else still reaches the chip, so games can be played with the panel open
+            _ => return false,
+        }
+        true
+    }
+
+    fn submit(&mut self, chip: &ChipController) {
+        match std::mem::replace(&mut self.input, Input::Keys) {
+            Input::Goto(text) => match u16::from_str_radix(text.trim(), 16) {
+                Ok(address) => self.jump(address),
+                Err(_) => self.message = format!("{} is no address", text.trim()),
+            },
+            Input::Search(text) => match parse_bytes(&text) {
+                Some(pattern) => {
+                    self.pattern = pattern;
+                    self.search(chip, self.cursor);
+                }
+                None => self.message = format!("{} are no bytes", text.trim()),
+            },
+            Input::Equals(text) => match u8::from_str_radix(text.trim(), 16) {
+                Ok(value) => self.narrow(chip, Condition::Equals(value)),
+                Err(_) => self.message = format!("{} is no byte", text.trim()),
+            },
+            Input::Keys => {}
+        }
+    }
+
+    fn narrow(&mut self, chip: &ChipController, condition: Condition) {
+        let search = match &mut self.search {
+            Some(search) => search,
+            None => {
+                self.message = "Start a RAM search with s first".to_owned();
+                return;
+            }
+        };
+        search.filter(chip.ram(), condition);
+        let candidates = search.candidates();
+        let closest = candidates.first().copied();
+        let first: Vec<String> = candidates
+            .iter()
+            .take(6)
+            .map(|a| format!("{:04X}", a))
+            .collect();
+        self.message = format!("{} candidates {}", candidates.len(), first.join(" "));
+        if let Some(address) = closest {
+            self.jump(address);
+        }
+    }
+
+    fn next_candidate(&mut self) {
+        let cursor = self.cursor;
+        let next = self.search.as_ref().and_then(|search| {
+            let candidates = search.candidates();
+            candidates
+                .iter()
+                .find(|a| **a > cursor)
+                .or_else(|| candidates.first())
+                .copied()
+        });
+        match next {
+            Some(address) => self.jump(address),
+            None => self.message = "No candidates".to_owned(),
+        }
+    }
+
+    fn freeze(&mut self, chip: &mut ChipController) {
+        match chip.cheats().frozen(self.cursor) {
+            Some(index) => {
+                chip.toggle_cheat(index);
+            }
+            None => {
+                let value = chip.ram()[self.cursor as usize];
+                let name = format!("Freeze {:04X}", self.cursor);
+                chip.add_cheat(Cheat::new(&name, Effect::Freeze(self.cursor, value)));
+            }
+        }
+        let enabled = chip
+            .cheats()
+            .frozen(self.cursor)
+            .and_then(|index| chip.cheats().iter().nth(index))
+            .is_some_and(|cheat| cheat.enabled);
+        self.message = format!(
+            "{:04X} is {}",
+            self.cursor,
+            if enabled { "frozen" } else { "not frozen" }
+        );
+        if let Some(path) = &self.cheat_file {
+            if let Err(e) = chip.cheats().save(path) {
+                self.message = format!("Can't save cheats: {}", e);
+            }
+        }
+    }
+
+    /// Moves the cursor to the next match of the pattern from `start` on, wrapping around
+    fn search(&mut self, chip: &ChipController, start: u16) {
+        if self.pattern.is_empty() {
+            self.message = "Nothing to search for, press / first".to_owned();
+            return;
+        }
+        let ram = chip.ram();
+        let found = (0..ram.len())
+            .map(|offset| start.wrapping_add(offset as u16))
+            .find(|address| {
+                self.pattern
+                    .iter()
+                    .enumerate()
+                    .all(|(i, byte)| ram[address.wrapping_add(i as u16) as usize] == *byte)
+            });
+        let pattern = hex(&self.pattern);
+        match found {
+            Some(address) => {
+                self.message = format!("Found {} at {:04X}", pattern, address);
+                self.jump(address);
+            }
+            None => self.message = format!("{} was not found", pattern),
+        }
+    }
+
+    fn move_cursor(&mut self, offset: i32) {
+        self.jump(self.cursor.wrapping_add(offset as u16));
+    }
+
+    /// Moves the cursor to `address` and scrolls so it is shown
+    fn jump(&mut self, address: u16) {
+        self.cursor = address;
+        self.nibble = None;
+        let row = address & !0xf;
+        let below = row.wrapping_sub(self.top);
+        if below >= 16 * ROWS {
+            // Scroll by as little as possible when the cursor just left the panel downwards
+            self.top = match below < 2 * 16 * ROWS {
+                true => row.wrapping_sub(16 * (ROWS - 1)),
+                false => row,
+            };
+        }
+    }
+
+    /// Draws the panel starting at row `y` of the terminal
+    pub fn draw(
+        &mut self,
+        output: &mut impl Write,
+        chip: &ChipController,
+        y: u16,
+        paused: bool,
+    ) -> crossResult<()> {
+        let ram = chip.ram();
+        let now = Instant::now();
+        let shown: Vec<u8> = (0..16 * ROWS)
+            .map(|i| ram[self.top.wrapping_add(i) as usize])
+            .collect();
+        match self.shown_top == Some(self.top) {
+            true => {
+                for (i, (old, new)) in self.shown.iter().zip(&shown).enumerate() {
+                    if old != new {
+                        self.changed[i] = Some(now);
+                    }
+                }
+            }
+            // Bytes scrolled into view don't count as changed
+            false => self.changed = vec![None; shown.len()],
+        }
+        self.shown_top = Some(self.top);
+        self.shown = shown;
+
+        let pc = chip.pc();
+        let pc_end = pc.saturating_add(chip.next_instruction().length() as u16);
+        let i = chip.index();
+        let frozen: Vec<u16> = chip
+            .cheats()
+            .iter()
+            .filter(|cheat| cheat.enabled)
+            .filter_map(|cheat| match cheat.effect {
+                Effect::Freeze(address, _) => Some(address),
+                _ => None,
+            })
+            .collect();
+        for row in 0..ROWS {
+            let address = self.top.wrapping_add(row * 16);
+            queue!(
+                output,
+                MoveTo(0, y + 1 + row),
+                Print(format!("{:04X}  ", address))
+            )?;
+            let mut ascii = String::new();
+            for column in 0..16 {
+                let offset = (row * 16 + column) as usize;
+                let (byte, address) = (self.shown[offset], address.wrapping_add(column));
+                let background = if (pc..pc_end).contains(&address) {
+                    Color::DarkBlue
+                } else if address.wrapping_sub(i) < INDEX_LENGTH {
+                    Color::DarkMagenta
+                } else {
+                    Color::Reset
+                };
+                let foreground = match self.changed[offset] {
+                    _ if frozen.contains(&address) => Color::Cyan,
+                    Some(time) if now.duration_since(time) < CHANGE_FADE => Color::Red,
+                    _ if address < FONT_END => Color::DarkGreen,
+                    _ => Color::Reset,
+                };
+                let text = match (address == self.cursor, self.nibble) {
+                    (true, Some(high)) => format!("{:X}_", high),
+                    _ => format!("{:02X}", byte),
+                };
+                queue!(
+                    output,
+                    SetForegroundColor(foreground),
+                    SetBackgroundColor(background)
+                )?;
+                if address == self.cursor {
+                    queue!(output, SetAttribute(Attribute::Reverse))?;
+                }
+                queue!(
+                    output,
+                    Print(text),
+                    SetAttribute(Attribute::Reset),
+                    ResetColor,
+                    Print(" ")
+                )?;
+                ascii.push(match byte {
+                    0x20..=0x7e => byte as char,
+                    _ => '.',
+                });
+            }
+            queue!(output, Print(format!(" |{}|", ascii)))?;
+        }
+
+        let status = match &self.input {
+            Input::Goto(text) => format!("Go to: {}_", text),
+            Input::Search(text) => format!("Search bytes: {}_", text),
+            Input::Equals(text) => format!("Candidates equal to: {}_", text),
+            Input::Keys => format!(
+                "{} PC {:04X} I {:04X} | {}",
+                if paused { "PAUSED" } else { "RUNNING" },
+                pc,
+                i,
+                self.message
+            ),
+        };
+        queue!(
+            output,
+            MoveTo(0, y + HEIGHT - 1),
+            Clear(ClearType::UntilNewLine),
+            Print(status.chars().take(WIDTH as usize).collect::<String>())
+        )
+    }
+}
+
+/// Reads bytes like `A2 08` or `a208`
+fn parse_bytes(text: &str) -> Option<Vec<u8>> {
+    let digits: String = text.split_whitespace().collect();
+    if digits.is_empty() || !digits.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..digits.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&digits[i..i + 2], 16).ok())
+        .collect()
+}
+
+fn hex(bytes: &[u8]) -> String {
+    let bytes: Vec<String> = bytes.iter().map(|byte| format!("{:02X}", byte)).collect();
+    bytes.join(" ")
+}
@@ -0,0 +1,62 @@
+use super::super::chip_controller::ChipKey;
+use std::time::{Duration, Instant};
+
+/// Which keys of the CHIP-8 are held down
+///
+/// Terminals with the kitty keyboard protocol report when a key is let go. Others only repeat
+/// the presses of a held key, so a key counts as released when it wasn't repeated for `hold`.
+/// The first repeat comes later than the others, so until then a key is held for `delay`.
+pub(crate) struct Keyboard {
+    /// Whether the terminal reports releases
+    pub releases: bool,
+    pub hold: Duration,
+    /// How long a key is held after the press before it is repeated the first time
+    pub delay: Duration,
+    /// When each key was last pressed or repeated and whether it was repeated yet, if it is held
+    held: [Option<(Instant, bool)>; 16],
+}
+
+impl Keyboard {
+    /// Longer than the pause between repeats of most terminals, but short enough that a tapped
+    /// key isn't noticeably held
+    pub const HOLD: Duration = Duration::from_millis(250);
+
+    pub fn new() -> Keyboard {
+        Keyboard {
+            releases: false,
+            hold: Keyboard::HOLD,
+            delay: Keyboard::HOLD,
+            held: [None; 16],
+        }
+    }
+
+    pub fn press(&mut self, key: ChipKey, now: Instant) {
+        let held = &mut self.held[key as usize];
+        *held = Some((now, held.is_some()));
+    }
+
+    pub fn release(&mut self, key: ChipKey) {
+        self.held[key as usize] = None;
+    }
+
+    /// The held keys with a bit for each, keys that timed out are released first
+    pub fn keys(&mut self, now: Instant) -> u16 {
+        let (releases, hold, delay) = (self.releases, self.hold, self.delay);
+        let mut keys = 0;
+        for (key, held) in self.held.iter_mut().enumerate() {
+            let timeout = |repeated| match repeated {
+                true => hold,
+                false => delay,
+            };
+            let timed_out = held
+                .is_some_and(|(pressed, repeated)| now.duration_since(pressed) > timeout(repeated));
+            if !releases && timed_out {
+                *held = None;
+            }
+            if held.is_some() {
+                keys |= 1 << key;
+            }
+        }
+        keys
+    }
+}
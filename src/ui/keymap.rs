@@ -0,0 +1,155 @@
+use super::super::chip_controller::ChipKey;
+use crossterm::event::KeyCode;
+use std::fs;
+use std::io::{Error, ErrorKind};
+use std::path::Path;
+
+/// The keys of the CHIP-8 keypad from the top left to the bottom right
+const KEYPAD: [u8; 16] = [
+    0x1, 0x2, 0x3, 0xC, 0x4, 0x5, 0x6, 0xD, 0x7, 0x8, 0x9, 0xE, 0xA, 0x0, 0xB, 0xF,
+];
+
+/// Which keys of the keyboard press which keys of the CHIP-8
+///
+/// Custom keymaps are read from files with a line for each CHIP-8 key and as many keys of the
+/// keyboard as it should have, `up`, `down`, `left`, `right`, `space` and `enter` name the keys
+/// that aren't characters:
+/// ```text
+/// 5: w up
+/// 8: s down
+/// ```
+/// Empty lines and lines starting with `#` are ignored.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Keymap {
+    keys: Vec<(KeyCode, ChipKey)>,
+}
+
+impl Default for Keymap {
+    /// The 4x4 block of 1234, QWER, ASDF and ZXCV which is laid out like the keypad
+    fn default() -> Self {
+        Keymap::grid("1234qwerasdfzxcv")
+    }
+}
+
+impl Keymap {
+    /// The names of the preset keymaps
+    pub const PRESETS: [&'static str; 4] = ["qwerty", "hex", "azerty", "arrows"];
+
+    /// A keymap with one key for each key of the keypad in the same order
+    fn grid(keys: &str) -> Keymap {
+        Keymap {
+            keys: keys
+                .chars()
+                .zip(KEYPAD.iter())
+                .map(|(key, chip)| (KeyCode::Char(key), ChipKey::from(*chip)))
+                .collect(),
+        }
+    }
+
+    pub fn preset(name: &str) -> Option<Keymap> {
+        match name {
+            "qwerty" => Some(Keymap::default()),
+            // The keys are labelled like the ones of the keypad
+            "hex" => Some(Keymap {
+                keys: (0..16u8)
+                    .map(|key| {
+                        let c = std::char::from_digit(key as u32, 16).unwrap();
+                        (KeyCode::Char(c), ChipKey::from(key))
+                    })
+                    .collect(),
+            }),
+            // The digits need shift on AZERTY keyboards, so the characters below them work too
+            "azerty" => {
+                let mut keymap = Keymap::grid("1234azerqsdfwxcv");
+                keymap.keys.extend(Keymap::grid("&é\"'").keys);
+                Some(keymap)
+            }
+            // Most games move with 2, 4, 6 and 8
+            "arrows" => {
+                let mut keymap = Keymap::default();
+                keymap.keys.extend(vec![
+                    (KeyCode::Up, ChipKey::Two),
+                    (KeyCode::Left, ChipKey::Four),
+                    (KeyCode::Right, ChipKey::Six),
+                    (KeyCode::Down, ChipKey::Eight),
+                ]);
+                Some(keymap)
+            }
+            _ => None,
+        }
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Keymap, Error> {
+        Keymap::parse(&fs::read_to_string(path)?)
+    }
+
+    pub fn parse(text: &str) -> Result<Keymap, Error> {
+        let mut keys = Vec::new();
+        for (number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let invalid = || {
+                Error::new(
+                    ErrorKind::InvalidData,
+                    format!("Invalid key in line {}: {}", number + 1, line),
+                )
+            };
+            let (chip, host) = line.split_once(':').ok_or_else(invalid)?;
+            let chip = u8::from_str_radix(chip.trim(), 16)
+                .ok()
+                .filter(|key| *key < 16)
+                .ok_or_else(invalid)?;
+            for name in host.split_whitespace() {
+                keys.push((key_code(name).ok_or_else(invalid)?, ChipKey::from(chip)));
+            }
+        }
+        Ok(Keymap { keys })
+    }
+
+    /// Lets a button of the ROM database like `up` or `a` press a CHIP-8 key too, the arrow keys
+    /// are the direction buttons, space is `a` and enter is `b`
+    pub fn bind(&mut self, button: &str, chip: ChipKey) {
+        let name = match button {
+            "a" => "space",
+            "b" => "enter",
+            "up" | "down" | "left" | "right" => button,
+            _ => return,
+        };
+        let key = key_code(name).unwrap();
+        self.keys.retain(|(host, _)| *host != key);
+        self.keys.push((key, chip));
+    }
+
+    /// The CHIP-8 key pressed by a key of the keyboard
+    pub fn key(&self, key: KeyCode) -> Option<ChipKey> {
+        // Caps lock or shift shouldn't matter
+        let key = match key {
+            KeyCode::Char(c) => KeyCode::Char(c.to_ascii_lowercase()),
+            key => key,
+        };
+        self.keys
+            .iter()
+            .find(|(host, _)| *host == key)
+            .map(|(_, chip)| *chip)
+    }
+}
+
+fn key_code(name: &str) -> Option<KeyCode> {
+    let mut chars = name.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) => return Some(KeyCode::Char(c.to_ascii_lowercase())),
+        (None, _) => return None,
+        _ => {}
+    }
+    match name.to_lowercase().as_str() {
+        "up" => Some(KeyCode::Up),
+        "down" => Some(KeyCode::Down),
+        "left" => Some(KeyCode::Left),
+        "right" => Some(KeyCode::Right),
+        "space" => Some(KeyCode::Char(' ')),
+        "enter" => Some(KeyCode::Enter),
+        _ => None,
+    }
+}
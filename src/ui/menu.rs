@@ -0,0 +1,156 @@
+use crossterm::event::KeyCode;
+
+/// The instructions per second that the speed steps through
+const SPEEDS: [usize; 13] = [
+    60, 120, 250, 500, 700, 1000, 1500, 2000, 3000, 5000, 10000, 20000, 50000,
+];
+
+/// Save states are kept in slots 1 to `SLOTS`
+pub(super) const SLOTS: u8 = 9;
+
+/// What a menu entry or hotkey asks the emulator to do
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum Action {
+    /// Opens the menu, which pauses the chip
+    Menu,
+    /// Pauses or resumes without the menu
+    Pause,
+    /// Closes the menu and resumes
+    Resume,
+    /// Runs a single frame and pauses
+    Advance,
+    Slower,
+    Faster,
+    /// Switches to the next or, with -1, to the previous quirk profile
+    Quirks(isize),
+    Save,
+    Load,
+    /// Starts the ROM over
+    Reset,
+    /// Reads the ROM file again, for ROMs that are being worked on
+    Reload,
+    Debugger,
+    Quit,
+}
+
+/// The entries from top to bottom, with the hotkey doing the same
+const ENTRIES: [(Action, &str); 10] = [
+    (Action::Resume, "Esc"),
+    (Action::Advance, "F6"),
+    (Action::Faster, "F7 F8"),
+    (Action::Quirks(1), ""),
+    (Action::Save, "F11"),
+    (Action::Load, "F12"),
+    (Action::Reset, "F9"),
+    (Action::Reload, "F10"),
+    (Action::Debugger, "F2"),
+    (Action::Quit, ""),
+];
+
+/// The width of the box inside the border
+pub(super) const WIDTH: usize = 32;
+
+/// The hotkey for an action that works with or without the menu
+pub(super) fn hotkey(key: KeyCode) -> Option<Action> {
+    Some(match key {
+        KeyCode::F(1) => Action::Menu,
+        KeyCode::F(2) => Action::Debugger,
+        KeyCode::F(5) => Action::Pause,
+        KeyCode::F(6) => Action::Advance,
+        KeyCode::F(7) => Action::Slower,
+        KeyCode::F(8) => Action::Faster,
+        KeyCode::F(9) => Action::Reset,
+        KeyCode::F(10) => Action::Reload,
+        KeyCode::F(11) => Action::Save,
+        KeyCode::F(12) => Action::Load,
+        _ => return None,
+    })
+}
+
+/// The speed after one step slower or faster, speeds between the steps go to the next one
+pub(super) fn step_speed(freq: usize, faster: bool) -> usize {
+    let next = match faster {
+        true => SPEEDS.iter().find(|speed| **speed > freq),
+        false => SPEEDS.iter().rev().find(|speed| **speed < freq),
+    };
+    next.copied().unwrap_or(freq)
+}
+
+/// The pause menu, the arrow keys pick an entry and change its value and Enter chooses it
+pub(super) struct Menu {
+    pub open: bool,
+    selected: usize,
+    /// The slot that states are saved to and loaded from, also by the hotkeys
+    pub slot: u8,
+}
+
+impl Menu {
+    pub fn new() -> Menu {
+        Menu {
+            open: false,
+            selected: 0,
+            slot: 1,
+        }
+    }
+
+    /// The action for a key pressed while the menu is open
+    pub fn handle(&mut self, key: KeyCode) -> Option<Action> {
+        let (action, _) = ENTRIES[self.selected];
+        match key {
+            KeyCode::Up => self.selected = (self.selected + ENTRIES.len() - 1) % ENTRIES.len(),
+            KeyCode::Down => self.selected = (self.selected + 1) % ENTRIES.len(),
+            KeyCode::Esc => return Some(Action::Resume),
+            KeyCode::Enter => return Some(action),
+            KeyCode::Left | KeyCode::Right => {
+                let right = key == KeyCode::Right;
+                match action {
+                    Action::Faster if !right => return Some(Action::Slower),
+                    Action::Faster => return Some(Action::Faster),
+                    Action::Quirks(_) => return Some(Action::Quirks(if right { 1 } else { -1 })),
+                    Action::Save | Action::Load => {
+                        self.slot = match right {
+                            true => self.slot % SLOTS + 1,
+                            false => (self.slot + SLOTS - 2) % SLOTS + 1,
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            KeyCode::Char(c @ '1'..='9') => self.slot = c as u8 - b'0',
+            _ => {}
+        }
+        None
+    }
+
+    /// The lines of the box with the current values, the selected entry is marked
+    pub fn lines(&self, paused: bool, freq: usize, quirks: &str, debugger: bool) -> Vec<String> {
+        let mut lines = vec![
+            format!("{:^1$}", if paused { "Paused" } else { "Running" }, WIDTH),
+            String::new(),
+        ];
+        for (index, (action, hotkey)) in ENTRIES.iter().enumerate() {
+            let text = match action {
+                Action::Resume => "Resume".to_owned(),
+                Action::Advance => "Advance one frame".to_owned(),
+                Action::Faster => format!("Speed ◂ {} ▸", freq),
+                Action::Quirks(_) => format!("Quirks ◂ {} ▸", quirks),
+                Action::Save => format!("Save to slot ◂ {} ▸", self.slot),
+                Action::Load => format!("Load slot ◂ {} ▸", self.slot),
+                Action::Reset => "Reset".to_owned(),
+                Action::Reload => "Reload the ROM".to_owned(),
+                Action::Debugger => format!("Debugger {}", if debugger { "on" } else { "off" }),
+                _ => "Quit".to_owned(),
+            };
+            let marker = if index == self.selected { '>' } else { ' ' };
+            let width = WIDTH - 3 - hotkey.len();
+            lines.push(format!(
+                " {} {:width$}{}",
+                marker,
+                text,
+                hotkey,
+                width = width
+            ));
+        }
+        lines
+    }
+}
@@ -1,37 +1,126 @@
-use super::chip_controller::{ChipController, ChipKey};
+use super::chip_controller::{ChipController, Quirks, State, SymbolMap};
+pub(crate) use browser::Browser;
 use crossterm::{
     cursor::{DisableBlinking, EnableBlinking, Hide, MoveTo, Show},
-    event::{poll, read, Event, KeyCode},
+    event::{
+        poll, read, Event, KeyCode, KeyEvent, KeyEventKind, KeyboardEnhancementFlags,
+        PopKeyboardEnhancementFlags, PushKeyboardEnhancementFlags,
+    },
     execute, queue,
-    style::Print,
+    style::{Print, ResetColor, SetBackgroundColor, SetForegroundColor},
     terminal::{
-        disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen, SetSize,
-        SetTitle,
+        disable_raw_mode, enable_raw_mode, size, supports_keyboard_enhancement, Clear, ClearType,
+        EnterAlternateScreen, LeaveAlternateScreen, SetTitle,
     },
-    Result as crossResult,
 };
+use debugger::DebuggerView;
+use hex::HexView;
+use keyboard::Keyboard;
+use keymap::Keymap;
+use menu::{Action, Menu};
+use phosphor::Phosphor;
+use render::{Renderer, Screen};
+pub(crate) use settings::Settings;
 use std::{
-    io::{stdout, Stdout, Write},
+    fs,
+    io::{stdout, Result as crossResult, Write},
+    path::PathBuf,
     thread,
     time::{Duration, Instant},
 };
+use theme::{Depth, Theme};
+
+mod browser;
+pub(crate) mod debugger;
+pub(crate) mod hex;
+pub(crate) mod keyboard;
+pub(crate) mod keymap;
+mod menu;
+pub(crate) mod phosphor;
+pub(crate) mod render;
+mod settings;
+pub(crate) mod theme;
+
+/// The quirk profiles that the menu switches between
+const PROFILES: [Quirks; 3] = [Quirks::CHIP8, Quirks::SCHIP, Quirks::XOCHIP];
+/// How long a message is shown below the display
+const MESSAGE_TIME: Duration = Duration::from_secs(2);
+
+/// The renderer, display dimension, whether the hex and debugger panels are shown and the
+/// terminal size
+type Layout = (Renderer, (u8, u8), (bool, bool), (u16, u16));
 
 pub struct UI {
-    output: Stdout,
+    output: Box<dyn Write>,
     chip: ChipController,
     freq: usize,
     dimension: (u8, u8),
     alt_screen_active: bool,
+    /// The size of the terminal in cells
+    terminal: (u16, u16),
+    /// The chosen renderer, it is picked to fit the terminal if there is none
+    renderer: Option<Renderer>,
+    /// What was drawn last, the screen is cleared when it changes
+    layout: Option<Layout>,
+    screen: Screen,
+    theme: Theme,
+    phosphor: Phosphor,
+    keymap: Keymap,
+    keyboard: Keyboard,
+    /// The colours the terminal supports, the theme is approximated with them
+    depth: Depth,
+    hex: HexView,
+    debugger: DebuggerView,
+    paused: bool,
+    /// Whether a single frame is run although the chip is paused
+    advance: bool,
+    menu: Menu,
+    /// The file the ROM was read from, it is read again on reload and states are saved next to it
+    rom_file: Option<PathBuf>,
+    slots: Vec<Option<State>>,
+    /// The last message about an action and when it was shown
+    message: Option<(String, Instant)>,
+    /// The line below the display as it was drawn last
+    status: String,
+    /// The menu as it was drawn last, it is drawn again when it or anything below it changes
+    shown_menu: Vec<String>,
 }
 
 impl UI {
-    pub fn new() -> Self {
+    pub fn new(chip: ChipController, settings: &Settings) -> Self {
+        Self::with(chip, Box::new(stdout()), settings)
+    }
+
+    /// Creates a UI for an already prepared chip which draws into `output` instead of stdout
+    pub fn with(chip: ChipController, output: Box<dyn Write>, settings: &Settings) -> Self {
+        let mut keyboard = Keyboard::new();
+        keyboard.hold = settings.hold;
+        keyboard.delay = settings.delay.unwrap_or(settings.hold);
         Self {
-            output: stdout(),
-            chip: ChipController::new(),
-            freq: 1000,
+            output,
+            chip,
+            freq: settings.freq,
             dimension: (64, 32),
             alt_screen_active: false,
+            terminal: (64, 32),
+            renderer: settings.renderer,
+            layout: None,
+            screen: Screen::default(),
+            theme: settings.theme,
+            phosphor: Phosphor::new(settings.phosphor),
+            keymap: settings.keymap.clone(),
+            keyboard,
+            depth: Depth::detect(),
+            hex: HexView::new(),
+            debugger: DebuggerView::new(),
+            paused: false,
+            advance: false,
+            menu: Menu::new(),
+            rom_file: None,
+            slots: vec![None; menu::SLOTS as usize],
+            message: None,
+            status: String::new(),
+            shown_menu: Vec::new(),
         }
     }
 
@@ -39,114 +128,435 @@ impl UI {
         self.alt_screen_active
     }
 
-    pub fn run(&mut self) {
-        self.activate_display().unwrap();
-
-        // Read ROM path from args
-        for (i, arg) in std::env::args().enumerate() {
-            match i {
-                1 => {
-                    let rom = match std::fs::read(arg) {
-                        Ok(vec) => vec,
-                        Err(e) => panic!("Please provide an existing path!"),
-                    };
-                    self.chip.set_rom(rom);
-                }
-                2 => {
-                    self.freq = match arg.parse() {
-                        Ok(i) => i,
-                        Err(_) => self.freq,
-                    }
-                }
-                _ => (),
-            };
-        }
+    /// The file the loaded ROM was read from, which can then be reloaded. The cheats frozen in
+    /// the hex panel are saved next to it, just like the save states.
+    pub fn set_rom_file(&mut self, path: PathBuf) {
+        self.hex.cheat_file = Some(path.with_extension("cht"));
+        self.rom_file = Some(path);
+    }
 
-        let mut key: KeyCode;
-        let mut chip_key: Option<ChipKey>;
-        let millis = Duration::from_secs_f64(1f64 / self.freq as f64);
+    /// The labels the debugger panel shows for addresses
+    pub fn set_symbols(&mut self, symbols: SymbolMap) {
+        self.debugger.symbols = symbols;
+    }
 
-        let mut now = Instant::now();
-        let mut last_delay_dec: Instant = now;
-        let mut last_sound_dec: Instant = now;
+    /// Runs the emulator cycle until it is quit from the menu, `q` is pressed while the keymap
+    /// doesn't use it or an attached debugger ends the session
+    ///
+    /// Every cycle is a frame of the 60Hz display, which executes `freq / 60` instructions
+    /// after handling all keys pressed since the last one and draws the terminal once.
+    ///
+    /// Escape or F1 opens the pause menu. F2 toggles the debugger, F5 pauses, F6 advances a
+    /// frame, F7 and F8 change the speed, F9 resets, F10 reloads the ROM and F11 and F12 save to
+    /// and load from the slot chosen in the menu.
+    pub fn emulate(&mut self) {
+        self.activate_display().unwrap();
 
-        const SIXTEEN_MS: Duration = Duration::from_millis(16);
+        const FRAME: Duration = Duration::from_micros(16_667);
+        let mut deadline = Instant::now();
+        // Instructions that are owed when `freq` is not divisible by 60
+        let mut owed = 0.0;
 
         // Emulator cycle
         loop {
-            now = Instant::now();
-            if self.chip.delay_timer() > 0 {
-                if now.duration_since(last_delay_dec) >= SIXTEEN_MS {
-                    self.chip.dec_delay_timer();
-                    last_delay_dec = now;
+            let mut quit = self.chip.finished();
+            let now = Instant::now();
+            for event in self.read_keys() {
+                let key = event.code;
+                if event.kind == KeyEventKind::Release {
+                    if let Some(chip_key) = self.keymap.key(key) {
+                        self.keyboard.release(chip_key);
+                    }
+                    continue;
                 }
-            }
-            if self.chip.sound_timer() > 0 {
-                if now.duration_since(last_sound_dec) >= SIXTEEN_MS {
-                    self.chip.dec_sound_timer();
-                    last_sound_dec = now;
+                let action = match menu::hotkey(key) {
+                    Some(action) => Some(action),
+                    None if self.menu.open => self.menu.handle(key),
+                    None if self.hex.handle(
+                        key,
+                        &mut self.chip,
+                        &mut self.paused,
+                        &self.keymap,
+                    ) =>
+                    {
+                        continue
+                    }
+                    None if self.debugger.handle(key, &mut self.chip, &mut self.paused) => continue,
+                    // The keymap may need q, but escape always opens the menu
+                    None if key == KeyCode::Esc => Some(Action::Menu),
+                    None => None,
+                };
+                if let Some(action) = action {
+                    quit |= self.act(action);
+                    continue;
+                }
+                if self.menu.open {
+                    continue;
+                }
+                match key {
+                    KeyCode::Char('q') if self.keymap.key(key).is_none() => quit = true,
+                    // F3 cycles through the renderers and back to choosing one automatically
+                    KeyCode::F(3) => {
+                        self.renderer = match self.renderer {
+                            None => Some(Renderer::Blocks),
+                            Some(Renderer::Braille) => None,
+                            Some(renderer) => Some(renderer.next()),
+                        }
+                    }
+                    KeyCode::F(4) => self.phosphor.mode = self.phosphor.mode.next(),
+                    _ => {
+                        if let Some(chip_key) = self.keymap.key(key) {
+                            self.keyboard.press(chip_key, now);
+                        }
+                    }
                 }
             }
-            key = self.read_key();
-            chip_key = match Self::into_chip_key(&key) {
-                Ok(e) => Some(e),
-                Err(_) => None,
-            };
-            if key == KeyCode::Char('q') {
+            if quit {
                 self.deactivate_display().unwrap();
                 break;
             }
-            self.chip.set_pressed_key(chip_key);
-            self.chip.tick(None);
-            thread::sleep(millis);
+            if !self.paused || self.advance {
+                self.advance = false;
+                owed += self.freq as f64 / 60.0;
+                let instructions = owed as usize;
+                owed -= instructions as f64;
+                self.chip.set_pressed_keys(self.keyboard.keys(now));
+                match self
+                    .chip
+                    .tick_until(instructions, &self.debugger.breakpoints)
+                {
+                    Ok(false) => {}
+                    Ok(true) => {
+                        self.paused = true;
+                        let at = self.debugger.symbols.describe(self.chip.pc());
+                        self.message = Some((format!("Breakpoint at {}", at), Instant::now()));
+                    }
+                    // The chip stays at the instruction, so the debugger shows where it is
+                    Err(crash) => {
+                        self.paused = true;
+                        self.message = Some((crash.to_string(), Instant::now()));
+                    }
+                }
+                self.chip.end_frame();
+                self.phosphor.push(&self.chip.get_display());
+            }
             self.update();
+
+            // Sleeping until a deadline that moves by exactly a frame keeps the rate from
+            // drifting, but a long stall is not caught up with
+            deadline += FRAME;
+            let now = Instant::now();
+            match deadline.checked_duration_since(now) {
+                Some(rest) => thread::sleep(rest),
+                None if now.duration_since(deadline) > FRAME * 4 => deadline = now,
+                None => {}
+            }
+        }
+    }
+
+    /// Does what a hotkey or menu entry asks for and returns whether to quit
+    fn act(&mut self, action: Action) -> bool {
+        let message = match action {
+            Action::Menu => {
+                self.menu.open = true;
+                self.paused = true;
+                None
+            }
+            Action::Pause => {
+                self.paused = !self.paused;
+                None
+            }
+            Action::Resume => {
+                self.close_menu();
+                self.paused = false;
+                None
+            }
+            Action::Advance => {
+                self.paused = true;
+                self.advance = true;
+                None
+            }
+            Action::Slower | Action::Faster => {
+                self.freq = menu::step_speed(self.freq, action == Action::Faster);
+                Some(format!("{} instructions per second", self.freq))
+            }
+            Action::Quirks(step) => {
+                let current = PROFILES
+                    .iter()
+                    .position(|quirks| *quirks == self.chip.quirks());
+                let next = match current {
+                    Some(index) => (index as isize + step).rem_euclid(PROFILES.len() as isize),
+                    // Custom quirks are left for the first profile in either direction
+                    None => 0,
+                };
+                self.chip.set_quirks(PROFILES[next as usize]);
+                Some(format!("Quirks of {}", self.quirks_name()))
+            }
+            Action::Save => Some(self.save_state()),
+            Action::Load => Some(self.load_state()),
+            Action::Reset => {
+                self.chip.reset();
+                Some("Reset".to_owned())
+            }
+            Action::Reload => Some(self.reload()),
+            Action::Debugger => {
+                self.debugger.visible = !self.debugger.visible;
+                None
+            }
+            Action::Quit => return true,
+        };
+        if let Some(message) = message {
+            self.message = Some((message, Instant::now()));
+        }
+        false
+    }
+
+    fn close_menu(&mut self) {
+        if self.menu.open {
+            self.menu.open = false;
+            // The box is removed by drawing everything again
+            self.layout = None;
+        }
+    }
+
+    fn quirks_name(&self) -> &'static str {
+        self.chip.quirks().name().unwrap_or("custom")
+    }
+
+    /// Where the state of a slot is saved, next to the ROM like `pong.state1`
+    fn state_file(&self, slot: u8) -> Option<PathBuf> {
+        let file = self.rom_file.as_ref()?;
+        Some(file.with_extension(format!("state{}", slot)))
+    }
+
+    /// Keeps the state in the chosen slot and writes it to its file if the ROM came from one
+    fn save_state(&mut self) -> String {
+        let (slot, state) = (self.menu.slot, self.chip.state());
+        let saved = match self.state_file(slot) {
+            Some(file) => match fs::write(&file, state.to_bytes()) {
+                Ok(()) => format!("Saved to slot {} in {}", slot, file.display()),
+                Err(e) => format!(
+                    "Saved to slot {}, but can't write {}: {}",
+                    slot,
+                    file.display(),
+                    e
+                ),
+            },
+            None => format!("Saved to slot {}", slot),
+        };
+        self.slots[slot as usize - 1] = Some(state);
+        saved
+    }
+
+    /// Loads the state of the chosen slot, from its file if it wasn't saved in this session
+    fn load_state(&mut self) -> String {
+        let slot = self.menu.slot;
+        if let Some(state) = &self.slots[slot as usize - 1] {
+            self.chip.restore(state);
+            return format!("Loaded slot {}", slot);
+        }
+        let file = match self.state_file(slot) {
+            Some(file) if file.exists() => file,
+            _ => return format!("Slot {} is empty", slot),
+        };
+        match fs::read(&file).and_then(|bytes| State::from_bytes(&bytes)) {
+            Ok(state) => {
+                self.chip.restore(&state);
+                self.slots[slot as usize - 1] = Some(state);
+                format!("Loaded slot {} from {}", slot, file.display())
+            }
+            Err(e) => format!("Can't load {}: {}", file.display(), e),
+        }
+    }
+
+    /// Reads the ROM file again and starts it over
+    fn reload(&mut self) -> String {
+        let file = match &self.rom_file {
+            Some(file) => file,
+            None => return "There is no ROM file to reload".to_owned(),
+        };
+        // The quirks stay the ones picked in the menu or by the options
+        let quirks = self.chip.quirks();
+        let chip = &mut self.chip;
+        match fs::read(file).and_then(|rom| chip.set_rom(rom)) {
+            Ok(()) => {
+                chip.set_quirks(quirks);
+                // States of the old ROM don't fit the new one, the slots are read from files again
+                self.slots = vec![None; menu::SLOTS as usize];
+                format!("Reloaded {}", file.display())
+            }
+            Err(e) => format!("Can't reload {}: {}", file.display(), e),
         }
     }
 
     fn update(&mut self) {
         let chip_display = self.chip.get_display();
-        let width = self.dimension.0 as u16;
-        for x in 0..self.dimension.0 as u16 {
-            for y in 0..self.dimension.1 as u16 {
-                queue!(
-                    self.output,
-                    MoveTo(x, y),
-                    match chip_display[(y * width + x) as usize] {
-                        0 => Print(" "),
-                        1.. => Print("█"),
-                    }
-                )
+        self.dimension = self.chip.get_dimension();
+        let panels = (self.hex.visible, self.debugger.visible);
+        // The debugger is drawn next to the hex panel if both fit side by side, else below it
+        let beside = self.terminal.0 >= hex::WIDTH + 2 + debugger::WIDTH;
+        let panel = match panels {
+            (false, false) => 0,
+            (true, true) if !beside => hex::HEIGHT + debugger::HEIGHT,
+            (true, _) => hex::HEIGHT,
+            (false, true) => debugger::HEIGHT,
+        };
+        let space = (self.terminal.0, self.terminal.1.saturating_sub(panel));
+        let renderer = self
+            .renderer
+            .unwrap_or_else(|| Renderer::fit(self.dimension, space));
+        let layout = (renderer, self.dimension, panels, self.terminal);
+        if self.layout != Some(layout) {
+            self.layout = Some(layout);
+            queue!(self.output, Clear(ClearType::All)).unwrap();
+            self.screen.invalidate();
+            self.status.clear();
+            self.shown_menu.clear();
+        }
+        let pixels = self.phosphor.pixels(&chip_display);
+        let rows = renderer.render(&pixels, self.dimension);
+        let height = rows.len() as u16;
+        // The colours are only switched when they differ from the last cell
+        let mut colors = None;
+        for (x, y, run) in self.screen.update(rows) {
+            self.shown_menu.clear();
+            queue!(self.output, MoveTo(x, y)).unwrap();
+            for cell in run {
+                if colors != Some((cell.foreground, cell.background)) {
+                    colors = Some((cell.foreground, cell.background));
+                    let foreground = self.theme.color(cell.foreground, self.depth);
+                    let background = self.theme.color(cell.background, self.depth);
+                    queue!(
+                        self.output,
+                        SetForegroundColor(foreground),
+                        SetBackgroundColor(background)
+                    )
+                    .unwrap();
+                }
+                queue!(self.output, Print(cell.character)).unwrap();
+            }
+        }
+        if colors.is_some() {
+            queue!(self.output, ResetColor).unwrap();
+        }
+        if self.hex.visible {
+            self.hex
+                .draw(&mut self.output, &self.chip, height, self.paused)
                 .unwrap();
+        }
+        if self.debugger.visible {
+            let position = match (self.hex.visible, beside) {
+                (false, _) => (0, height),
+                (true, true) => (hex::WIDTH + 2, height),
+                (true, false) => (0, height + hex::HEIGHT),
+            };
+            self.debugger
+                .draw(&mut self.output, &self.chip, position)
+                .unwrap();
+        }
+        self.draw_status(height).unwrap();
+        if self.menu.open {
+            if panel > 0 {
+                self.shown_menu.clear();
             }
+            self.draw_menu().unwrap();
         }
         self.output.flush().unwrap();
     }
 
-    fn read_key(&self) -> KeyCode {
-        let key = match poll(Duration::from_nanos(1)).unwrap() {
-            true => match read().unwrap() {
-                Event::Key(x) => x.code,
-                _ => KeyCode::Null,
-            },
-            _ => KeyCode::Null,
+    /// Shows the last message or that the chip is paused on the line below the display
+    fn draw_status(&mut self, y: u16) -> crossResult<()> {
+        let status = match &self.message {
+            Some((message, time)) if time.elapsed() < MESSAGE_TIME => message.to_owned(),
+            _ if self.paused && !self.menu.open => "Paused, F5 resumes".to_owned(),
+            _ => String::new(),
         };
-        key
+        if status == self.status || y >= self.terminal.1 {
+            return Ok(());
+        }
+        let width = self.terminal.0 as usize;
+        let shown: String = status.chars().take(width).collect();
+        queue!(
+            self.output,
+            MoveTo(0, y),
+            Clear(ClearType::UntilNewLine),
+            Print(shown)
+        )?;
+        self.status = status;
+        Ok(())
+    }
+
+    /// Draws the menu as a box in the middle of the terminal, over the display
+    fn draw_menu(&mut self) -> crossResult<()> {
+        let lines = self.menu.lines(
+            self.paused,
+            self.freq,
+            self.quirks_name(),
+            self.debugger.visible,
+        );
+        if lines == self.shown_menu {
+            return Ok(());
+        }
+        let width = menu::WIDTH as u16 + 2;
+        let x = self.terminal.0.saturating_sub(width) / 2;
+        let y = self.terminal.1.saturating_sub(lines.len() as u16 + 2) / 2;
+        let border = "─".repeat(menu::WIDTH);
+        queue!(self.output, MoveTo(x, y), Print(format!("┌{}┐", border)))?;
+        for (row, line) in lines.iter().enumerate() {
+            queue!(
+                self.output,
+                MoveTo(x, y + 1 + row as u16),
+                Print(format!("│{:1$}│", line, menu::WIDTH))
+            )?;
+        }
+        queue!(
+            self.output,
+            MoveTo(x, y + 1 + lines.len() as u16),
+            Print(format!("└{}┘", border))
+        )?;
+        self.shown_menu = lines;
+        Ok(())
+    }
+
+    /// All keys that were pressed, repeated or released since the last call, without waiting
+    /// for any
+    fn read_keys(&mut self) -> Vec<KeyEvent> {
+        let mut keys = Vec::new();
+        while poll(Duration::from_secs(0)).unwrap() {
+            match read().unwrap() {
+                Event::Key(x) => keys.push(x),
+                Event::Resize(width, height) => self.terminal = (width, height),
+                _ => {}
+            }
+        }
+        keys
     }
 
     fn activate_display(&mut self) -> crossResult<()> {
         if !self.alt_screen_active {
             self.alt_screen_active = true;
             enable_raw_mode()?;
-            let height = self.dimension.1 as u16;
-            let width = self.dimension.0 as u16;
+            self.terminal = size().unwrap_or(self.terminal);
             execute!(
                 self.output,
                 EnterAlternateScreen,
-                SetSize(width, height),
                 DisableBlinking,
                 Hide,
                 SetTitle("Chip 8 Emulator")
             )?;
+            // Every key is reported as an escape code so releases of characters are reported too.
+            // Terminals keep the flags for each screen, so they are pushed on the alternate one.
+            self.keyboard.releases = supports_keyboard_enhancement().unwrap_or(false);
+            if self.keyboard.releases {
+                execute!(
+                    self.output,
+                    PushKeyboardEnhancementFlags(
+                        KeyboardEnhancementFlags::DISAMBIGUATE_ESCAPE_CODES
+                            | KeyboardEnhancementFlags::REPORT_EVENT_TYPES
+                            | KeyboardEnhancementFlags::REPORT_ALL_KEYS_AS_ESCAPE_CODES
+                    )
+                )?;
+            }
         }
         Ok(())
     }
@@ -154,31 +564,13 @@ impl UI {
     fn deactivate_display(&mut self) -> crossResult<()> {
         if self.alt_screen_active {
             self.alt_screen_active = false;
+            // Popped while still on the alternate screen they were pushed on
+            if self.keyboard.releases {
+                execute!(self.output, PopKeyboardEnhancementFlags)?;
+            }
             disable_raw_mode()?;
             execute!(self.output, LeaveAlternateScreen, Show, EnableBlinking)?;
         }
         Ok(())
     }
-
-    fn into_chip_key(key: &KeyCode) -> Result<ChipKey, std::io::ErrorKind> {
-        match key {
-            KeyCode::Char('0') => Ok(ChipKey::Zero),
-            KeyCode::Char('1') => Ok(ChipKey::One),
-            KeyCode::Char('2') => Ok(ChipKey::Two),
-            KeyCode::Char('3') => Ok(ChipKey::Three),
-            KeyCode::Char('4') => Ok(ChipKey::Four),
-            KeyCode::Char('5') => Ok(ChipKey::Five),
-            KeyCode::Char('6') => Ok(ChipKey::Six),
-            KeyCode::Char('7') => Ok(ChipKey::Seven),
-            KeyCode::Char('8') => Ok(ChipKey::Eight),
-            KeyCode::Char('9') => Ok(ChipKey::Nine),
-            KeyCode::Char('a') => Ok(ChipKey::A),
-            KeyCode::Char('b') => Ok(ChipKey::B),
-            KeyCode::Char('c') => Ok(ChipKey::C),
-            KeyCode::Char('d') => Ok(ChipKey::D),
-            KeyCode::Char('e') => Ok(ChipKey::E),
-            KeyCode::Char('f') => Ok(ChipKey::F),
-            _ => Err(std::io::ErrorKind::NotFound),
-        }
-    }
 }
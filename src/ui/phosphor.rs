@@ -0,0 +1,84 @@
+/// The frames a pixel takes to fade out after it was erased
+pub(super) const DECAY: u8 = 3;
+
+/// Reduces the flicker of games that erase and redraw their sprites every frame by letting
+/// erased pixels glow for a while, like the phosphor of old screens
+///
+/// The filtered pixels keep the planes in the lowest two bits and how many frames ago the pixel
+/// was erased above them, so 0x12 is a pixel of the second plane that fades since one frame.
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub(crate) enum Mode {
+    Off,
+    /// Pixels of the last frame are shown dimmed, which is the same as ORing both frames
+    Blend,
+    /// Pixels fade out over `DECAY` frames
+    Decay,
+}
+
+impl Mode {
+    pub fn parse(text: &str) -> Option<Mode> {
+        match text {
+            "off" => Some(Mode::Off),
+            "blend" => Some(Mode::Blend),
+            "decay" => Some(Mode::Decay),
+            _ => None,
+        }
+    }
+
+    /// The next mode for cycling through all of them
+    pub fn next(self) -> Mode {
+        match self {
+            Mode::Off => Mode::Blend,
+            Mode::Blend => Mode::Decay,
+            Mode::Decay => Mode::Off,
+        }
+    }
+
+    /// The frames an erased pixel stays visible
+    fn frames(self) -> u8 {
+        match self {
+            Mode::Off => 0,
+            Mode::Blend => 1,
+            Mode::Decay => DECAY,
+        }
+    }
+}
+
+pub(crate) struct Phosphor {
+    pub mode: Mode,
+    pixels: Vec<u8>,
+}
+
+impl Phosphor {
+    pub fn new(mode: Mode) -> Phosphor {
+        Phosphor {
+            mode,
+            pixels: Vec::new(),
+        }
+    }
+
+    /// Passes a new frame of the display through the filter
+    pub fn push(&mut self, display: &[u8]) {
+        // The resolution changed, there is nothing to fade from
+        if self.pixels.len() != display.len() {
+            self.pixels = display.to_vec();
+            return;
+        }
+        let frames = self.mode.frames();
+        for (old, new) in self.pixels.iter_mut().zip(display) {
+            let age = (*old >> 4) + 1;
+            *old = match *new & 3 {
+                0 if *old & 3 != 0 && age <= frames => (age << 4) | (*old & 3),
+                _ => *new & 3,
+            };
+        }
+    }
+
+    /// The filtered display, which is the latest frame if none was pushed since it changed size
+    pub fn pixels(&self, display: &[u8]) -> Vec<u8> {
+        match self.pixels.len() == display.len() {
+            true => self.pixels.clone(),
+            false => display.to_vec(),
+        }
+    }
+}
@@ -0,0 +1,225 @@
+/// Unchanged cells between two changed ones are printed again when they are fewer than this,
+/// because moving the cursor over them takes more bytes
+const GAP: usize = 4;
+
+/// How pixels of the display are turned into characters of the terminal
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub(crate) enum Renderer {
+    /// One `█` per pixel, so pixels are twice as tall as wide
+    Blocks,
+    /// `▀` and `▄` for two pixels on top of each other, which makes them square
+    HalfBlocks,
+    /// The 2x3 sextants of Unicode 13, not every font has them
+    Sextants,
+    /// Braille patterns with 2x4 dots per cell
+    Braille,
+}
+
+impl Renderer {
+    /// Tried in this order when the renderer is chosen automatically, the first ones have the
+    /// biggest and squarest pixels
+    const AUTO: [Renderer; 3] = [Renderer::HalfBlocks, Renderer::Sextants, Renderer::Braille];
+
+    /// The renderer for a display of `dimension` pixels that fits into `space` cells, or the
+    /// smallest one if none does
+    pub fn fit(dimension: (u8, u8), space: (u16, u16)) -> Renderer {
+        Self::AUTO
+            .iter()
+            .copied()
+            .find(|renderer| {
+                let (columns, rows) = renderer.size(dimension);
+                columns <= space.0 && rows <= space.1
+            })
+            .unwrap_or(Renderer::Braille)
+    }
+
+    pub fn parse(name: &str) -> Option<Renderer> {
+        match name {
+            "blocks" => Some(Renderer::Blocks),
+            "halfblocks" => Some(Renderer::HalfBlocks),
+            "sextants" => Some(Renderer::Sextants),
+            "braille" => Some(Renderer::Braille),
+            _ => None,
+        }
+    }
+
+    /// The next renderer for cycling through all of them
+    pub fn next(self) -> Renderer {
+        match self {
+            Renderer::Blocks => Renderer::HalfBlocks,
+            Renderer::HalfBlocks => Renderer::Sextants,
+            Renderer::Sextants => Renderer::Braille,
+            Renderer::Braille => Renderer::Blocks,
+        }
+    }
+
+    /// The pixels one character shows horizontally and vertically
+    fn cell(self) -> (u16, u16) {
+        match self {
+            Renderer::Blocks => (1, 1),
+            Renderer::HalfBlocks => (1, 2),
+            Renderer::Sextants => (2, 3),
+            Renderer::Braille => (2, 4),
+        }
+    }
+
+    /// The columns and rows a display of `dimension` pixels takes
+    pub fn size(self, dimension: (u8, u8)) -> (u16, u16) {
+        let (width, height) = self.cell();
+        (
+            (dimension.0 as u16).div_ceil(width),
+            (dimension.1 as u16).div_ceil(height),
+        )
+    }
+
+    /// The rows of cells for `display`, which holds `dimension.0` pixels per row
+    ///
+    /// A pixel is set when one of its lowest two bits is, the other bits only select the colour.
+    pub fn render(self, display: &[u8], dimension: (u8, u8)) -> Vec<Vec<Cell>> {
+        let (width, height) = (dimension.0 as usize, dimension.1 as usize);
+        let pixel = |x: usize, y: usize| match x < width && y < height {
+            true => display[y * width + x],
+            false => 0,
+        };
+        let (cell_width, cell_height) = self.cell();
+        let (columns, rows) = self.size(dimension);
+        (0..rows as usize)
+            .map(|row| {
+                (0..columns as usize)
+                    .map(|column| {
+                        let (x, y) = (column * cell_width as usize, row * cell_height as usize);
+                        self.character(|dx, dy| pixel(x + dx, y + dy))
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    /// The cell whose pixels are given relative to its top left corner
+    ///
+    /// Only half blocks can show two colours of the planes, the others show the set pixels in
+    /// the most common colour of the cell.
+    fn character(self, pixel: impl Fn(usize, usize) -> u8) -> Cell {
+        let (cell_width, cell_height) = self.cell();
+        let pixels: Vec<u8> = (0..cell_height as usize)
+            .flat_map(|y| (0..cell_width as usize).map(move |x| (x, y)))
+            .map(|(x, y)| pixel(x, y))
+            .collect();
+        let lit = |x: usize, y: usize| pixels[y * cell_width as usize + x] & 3 != 0;
+        let color = pixels
+            .iter()
+            .copied()
+            .filter(|p| p & 3 != 0)
+            .max_by_key(|color| pixels.iter().filter(|p| *p == color).count())
+            .unwrap_or(0);
+        let character = match self {
+            Renderer::Blocks => match lit(0, 0) {
+                true => '█',
+                false => ' ',
+            },
+            Renderer::HalfBlocks => {
+                return match (pixels[0], pixels[1]) {
+                    (0, 0) => Cell::new(' ', 0, 0),
+                    (top, bottom) if top == bottom => Cell::new('█', top, 0),
+                    (top, 0) => Cell::new('▀', top, 0),
+                    (0, bottom) => Cell::new('▄', bottom, 0),
+                    (top, bottom) => Cell::new('▀', top, bottom),
+                }
+            }
+            Renderer::Sextants => {
+                // Bit 0 is the top left pixel and bit 5 the bottom right one
+                let bits = (0..6).fold(0, |bits, i| bits | (lit(i % 2, i / 2) as u32) << i);
+                match bits {
+                    0 => ' ',
+                    0b010101 => '▌',
+                    0b101010 => '▐',
+                    0b111111 => '█',
+                    // The block leaves out the patterns which already existed as half blocks
+                    _ => {
+                        let skipped = (bits > 0b010101) as u32 + (bits > 0b101010) as u32;
+                        char::from_u32(0x1FB00 + bits - 1 - skipped).unwrap()
+                    }
+                }
+            }
+            Renderer::Braille => {
+                // The dots are numbered down the left column first, the bottom row came last
+                const DOTS: [(usize, usize); 8] = [
+                    (0, 0),
+                    (0, 1),
+                    (0, 2),
+                    (1, 0),
+                    (1, 1),
+                    (1, 2),
+                    (0, 3),
+                    (1, 3),
+                ];
+                let bits = DOTS
+                    .iter()
+                    .enumerate()
+                    .fold(0, |bits, (i, (x, y))| bits | (lit(*x, *y) as u32) << i);
+                match bits {
+                    0 => ' ',
+                    _ => char::from_u32(0x2800 + bits).unwrap(),
+                }
+            }
+        };
+        match character {
+            ' ' => Cell::new(' ', 0, 0),
+            _ => Cell::new(character, color, 0),
+        }
+    }
+}
+
+/// A character on the terminal with the pixel values whose colours it is drawn in
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub(crate) struct Cell {
+    pub character: char,
+    pub foreground: u8,
+    pub background: u8,
+}
+
+impl Cell {
+    fn new(character: char, foreground: u8, background: u8) -> Cell {
+        Cell {
+            character,
+            foreground,
+            background,
+        }
+    }
+}
+
+/// The characters that are currently on the terminal, so only the cells that changed since the
+/// last frame have to be drawn
+#[derive(Default)]
+pub(crate) struct Screen {
+    rows: Vec<Vec<Cell>>,
+}
+
+impl Screen {
+    /// Forgets what is on the terminal, so the next frame is drawn completely
+    pub fn invalidate(&mut self) {
+        self.rows.clear();
+    }
+
+    /// Remembers `rows` as drawn and returns the runs of cells which have to be printed for it,
+    /// as column, row and cells
+    pub fn update(&mut self, rows: Vec<Vec<Cell>>) -> Vec<(u16, u16, Vec<Cell>)> {
+        let mut runs = Vec::new();
+        for (y, row) in rows.iter().enumerate() {
+            let old = self.rows.get(y);
+            let changed: Vec<usize> = (0..row.len())
+                .filter(|x| old.and_then(|old| old.get(*x)) != Some(&row[*x]))
+                .collect();
+            let mut changed = changed.into_iter().peekable();
+            while let Some(start) = changed.next() {
+                let mut end = start;
+                while let Some(next) = changed.next_if(|next| next - end <= GAP) {
+                    end = next;
+                }
+                runs.push((start as u16, y as u16, row[start..=end].to_vec()));
+            }
+        }
+        self.rows = rows;
+        runs
+    }
+}
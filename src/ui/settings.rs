@@ -0,0 +1,130 @@
+use super::super::chip_controller::{ChipKey, Program};
+use super::keyboard::Keyboard;
+use super::keymap::Keymap;
+use super::phosphor::Mode;
+use super::render::Renderer;
+use super::theme::Theme;
+use std::time::Duration;
+
+/// How the game is shown and played, which can be changed with options like `theme = amber`
+#[derive(Debug, Clone)]
+pub(crate) struct Settings {
+    /// Instructions per second
+    pub(super) freq: usize,
+    pub(super) theme: Theme,
+    pub(super) keymap: Keymap,
+    pub(super) phosphor: Mode,
+    /// How long a key counts as held after a press when the terminal doesn't report releases
+    pub(super) hold: Duration,
+    /// How long a key counts as held until the terminal repeats it the first time, `hold` if
+    /// there is none
+    pub(super) delay: Option<Duration>,
+    /// The renderer is picked to fit the terminal if there is none
+    pub(super) renderer: Option<Renderer>,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            freq: 1000,
+            theme: Theme::default(),
+            keymap: Keymap::default(),
+            phosphor: Mode::Off,
+            hold: Keyboard::HOLD,
+            delay: None,
+            renderer: None,
+        }
+    }
+}
+
+impl Settings {
+    /// The names of the options
+    pub const OPTIONS: [&'static str; 7] = [
+        "freq", "theme", "keymap", "phosphor", "hold", "delay", "renderer",
+    ];
+
+    /// Uses the speed, keys and colours the ROM database recommends for a ROM, options that are
+    /// set afterwards override them
+    pub fn recommend(&mut self, program: &Program) {
+        if let Some(tickrate) = program.tickrate.filter(|tickrate| *tickrate > 0) {
+            self.freq = tickrate * 60;
+        }
+        // XO-CHIP ROMs can have up to 16 colours, but only 4 are shown
+        let colors = &program.colors[..program.colors.len().min(4)];
+        if let Some(theme) = Theme::parse(&colors.join(",")) {
+            self.theme = theme;
+        }
+        for (button, key) in &program.keys {
+            self.keymap.bind(button, ChipKey::from(*key));
+        }
+    }
+
+    /// Changes an option, the error explains which values it can have
+    pub fn set(&mut self, option: &str, value: &str) -> Result<(), String> {
+        let invalid = |expected: &str| format!("Invalid {} {}, {}", option, value, expected);
+        match option {
+            "freq" => {
+                self.freq = value
+                    .parse()
+                    .ok()
+                    .filter(|freq| *freq > 0)
+                    .ok_or_else(|| invalid("it has to be a positive number"))?
+            }
+            "theme" => {
+                self.theme = Theme::parse(value).ok_or_else(|| {
+                    invalid(&format!(
+                        "it has to be one of {} or 2 to 4 colours like #000000,#FFFFFF",
+                        Theme::PRESETS.join(", ")
+                    ))
+                })?
+            }
+            // Anything but a preset is a file with a custom keymap
+            "keymap" => {
+                self.keymap = match Keymap::preset(value) {
+                    Some(keymap) => keymap,
+                    None => Keymap::load(value).map_err(|e| {
+                        invalid(&format!(
+                            "it is neither one of {} nor a readable file: {}",
+                            Keymap::PRESETS.join(", "),
+                            e
+                        ))
+                    })?,
+                }
+            }
+            "phosphor" => {
+                self.phosphor =
+                    Mode::parse(value).ok_or_else(|| invalid("it has to be off, blend or decay"))?
+            }
+            "hold" => {
+                self.hold = value
+                    .parse()
+                    .map(Duration::from_millis)
+                    .map_err(|_| invalid("it has to be a number of milliseconds"))?
+            }
+            "delay" => {
+                self.delay = Some(
+                    value
+                        .parse()
+                        .map(Duration::from_millis)
+                        .map_err(|_| invalid("it has to be a number of milliseconds"))?,
+                )
+            }
+            "renderer" => {
+                self.renderer = match value {
+                    "auto" => None,
+                    _ => Some(Renderer::parse(value).ok_or_else(|| {
+                        invalid("it has to be auto, blocks, halfblocks, sextants or braille")
+                    })?),
+                }
+            }
+            _ => {
+                return Err(format!(
+                    "There is no option {}, they are {}",
+                    option,
+                    Settings::OPTIONS.join(", ")
+                ))
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,166 @@
+use super::phosphor::DECAY;
+use crossterm::style::Color;
+
+type Rgb = (u8, u8, u8);
+
+/// The 16 colours every terminal has, in the order of their ANSI numbers
+const ANSI: [(Color, Rgb); 16] = [
+    (Color::Black, (0, 0, 0)),
+    (Color::DarkRed, (128, 0, 0)),
+    (Color::DarkGreen, (0, 128, 0)),
+    (Color::DarkYellow, (128, 128, 0)),
+    (Color::DarkBlue, (0, 0, 128)),
+    (Color::DarkMagenta, (128, 0, 128)),
+    (Color::DarkCyan, (0, 128, 128)),
+    (Color::Grey, (192, 192, 192)),
+    (Color::DarkGrey, (128, 128, 128)),
+    (Color::Red, (255, 0, 0)),
+    (Color::Green, (0, 255, 0)),
+    (Color::Yellow, (255, 255, 0)),
+    (Color::Blue, (0, 0, 255)),
+    (Color::Magenta, (255, 0, 255)),
+    (Color::Cyan, (0, 255, 255)),
+    (Color::White, (255, 255, 255)),
+];
+
+/// How many colours the terminal can show
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub(crate) enum Depth {
+    TrueColor,
+    Ansi256,
+    Ansi16,
+}
+
+impl Depth {
+    /// Guesses the depth from `COLORTERM` and `TERM` like most terminal programs do
+    pub fn detect() -> Depth {
+        let colorterm = std::env::var("COLORTERM").unwrap_or_default();
+        let term = std::env::var("TERM").unwrap_or_default();
+        if colorterm == "truecolor" || colorterm == "24bit" {
+            Depth::TrueColor
+        } else if term.contains("256color") {
+            Depth::Ansi256
+        } else {
+            Depth::Ansi16
+        }
+    }
+
+    /// The closest colour to `rgb` the terminal can show
+    pub fn color(self, (r, g, b): Rgb) -> Color {
+        match self {
+            Depth::TrueColor => Color::Rgb { r, g, b },
+            // The 6x6x6 cube starts at 16, the 24 greys at 232
+            Depth::Ansi256 => {
+                let level = |c: u8| match c {
+                    0..=47 => 0,
+                    48..=114 => 1,
+                    _ => (c - 35) / 40,
+                };
+                let cube = (level(r), level(g), level(b));
+                let value = |level: u8| if level == 0 { 0 } else { 55 + level * 40 };
+                let cube_rgb = (value(cube.0), value(cube.1), value(cube.2));
+                let average = ((r as u16 + g as u16 + b as u16) / 3) as u8;
+                let grey = average.saturating_sub(3).min(235) / 10;
+                let grey_rgb = (8 + grey * 10, 8 + grey * 10, 8 + grey * 10);
+                match distance((r, g, b), grey_rgb) < distance((r, g, b), cube_rgb) {
+                    true => Color::AnsiValue(232 + grey),
+                    false => Color::AnsiValue(16 + 36 * cube.0 + 6 * cube.1 + cube.2),
+                }
+            }
+            Depth::Ansi16 => {
+                ANSI.iter()
+                    .min_by_key(|(_, ansi)| distance((r, g, b), *ansi))
+                    .unwrap()
+                    .0
+            }
+        }
+    }
+}
+
+fn distance(a: Rgb, b: Rgb) -> u32 {
+    let d = |a: u8, b: u8| (a as i32 - b as i32).pow(2) as u32;
+    d(a.0, b.0) + d(a.1, b.1) + d(a.2, b.2)
+}
+
+/// The colours of the display, one for each combination of the XO-CHIP planes: none, only the
+/// first, only the second and both
+///
+/// `None` keeps the colour of the terminal, which the default theme uses for the first plane and
+/// the background.
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub(crate) struct Theme {
+    pub colors: [Option<Rgb>; 4],
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Theme {
+            colors: [
+                None,
+                None,
+                Some((0xFF, 0x66, 0x00)),
+                Some((0x66, 0x22, 0x00)),
+            ],
+        }
+    }
+}
+
+impl Theme {
+    /// The names of the preset themes
+    pub const PRESETS: [&'static str; 5] = ["default", "octo", "amber", "green", "lcd"];
+
+    /// A preset by name or a list of 2 to 4 hex colours like `#000000,#FFFFFF`, missing plane
+    /// colours are taken from the default theme
+    pub fn parse(text: &str) -> Option<Theme> {
+        let hex = |colors: [u32; 4]| Theme {
+            colors: colors.map(|c| Some(((c >> 16) as u8, (c >> 8) as u8, c as u8))),
+        };
+        match text.to_lowercase().as_str() {
+            "default" => return Some(Theme::default()),
+            "octo" => return Some(hex([0x996600, 0xFFCC00, 0xFF6600, 0x662200])),
+            "amber" => return Some(hex([0x1A0F00, 0xFFB000, 0x996A00, 0xFFE0A0])),
+            "green" => return Some(hex([0x001100, 0x33FF33, 0x1A801A, 0xB0FFB0])),
+            "lcd" => return Some(hex([0x9BBC0F, 0x0F380F, 0x306230, 0x8BAC0F])),
+            _ => {}
+        }
+        let colors: Vec<Rgb> = text
+            .split(',')
+            .map(|color| {
+                let color = color.trim().trim_start_matches('#');
+                match color.len() {
+                    6 => u32::from_str_radix(color, 16).ok(),
+                    _ => None,
+                }
+                .map(|c| ((c >> 16) as u8, (c >> 8) as u8, c as u8))
+            })
+            .collect::<Option<_>>()?;
+        if !(2..=4).contains(&colors.len()) {
+            return None;
+        }
+        let mut theme = Theme::default();
+        for (i, color) in colors.into_iter().enumerate() {
+            theme.colors[i] = Some(color);
+        }
+        Some(theme)
+    }
+
+    /// The terminal colour for a pixel value, pixels that fade out after being erased are
+    /// blended with the background
+    pub fn color(&self, pixel: u8, depth: Depth) -> Color {
+        let age = (pixel >> 4) as u32;
+        match (self.colors[pixel as usize & 3], age) {
+            (Some(rgb), 0) => depth.color(rgb),
+            (None, 0) => Color::Reset,
+            // The colours of the terminal are unknown, so they are assumed to be grey on black
+            (color, _) => {
+                let (r, g, b) = color.unwrap_or((192, 192, 192));
+                let (br, bg, bb) = self.colors[0].unwrap_or((0, 0, 0));
+                let total = DECAY as u32 + 1;
+                let blend = |c: u8, background: u8| {
+                    ((c as u32 * (total - age) + background as u32 * age) / total) as u8
+                };
+                depth.color((blend(r, br), blend(g, bg), blend(b, bb)))
+            }
+        }
+    }
+}